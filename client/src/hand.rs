@@ -1,7 +1,7 @@
-use server::game::card::Card;
+use server::game::card::PhysicalCard;
 
 #[derive(Default)]
 pub struct Hand {
-    pub cards: Vec<Card>,
+    pub cards: Vec<PhysicalCard>,
     pub last_choice: Option<usize>
 }
@@ -1,15 +1,16 @@
 use client::hand::Hand;
 use futures::{lock::Mutex, SinkExt, StreamExt};
 use ratatui::{
-    backend::CrosstermBackend, layout::{Constraint, Direction, Layout, Rect}, style::{Color, Style, Stylize}, widgets::{Block, Borders, List, ListItem, Paragraph}, Terminal
+    backend::CrosstermBackend, layout::{Alignment, Constraint, Direction, Layout, Rect}, style::{Color, Style, Stylize}, text::Span, widgets::{block::Title, Block, Borders, List, ListItem, Paragraph}, Terminal
 };
 use crossterm::{
     event::{self, Event, KeyCode}, execute, terminal::{disable_raw_mode, enable_raw_mode}
 };
+use client_common::{conn::Target, health::{Health, HealthMonitor, PING_EVERY}};
 use server::{game::card::Card, state::msg::{Action, DynMessage}};
-use server::game::card::Color as CardColor;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
-use std::{collections::VecDeque, fmt::write, io, sync::{Arc, RwLock}};
+use server::game::card::{Color as CardColor, PhysicalCard, Side};
+use tokio_tungstenite::tungstenite::Message;
+use std::{collections::VecDeque, io, sync::{Arc, RwLock}};
 
 #[derive(Copy, Clone)]
 enum Screen {
@@ -25,9 +26,18 @@ struct AppState {
     pub messages: VecDeque<String>,
     pub hand: Hand,
     pub top_card: Card,
+    pub active_color: CardColor,
     pub selected: usize,
     pub wild_color: CardColor,
-    pub won: bool
+    pub picking_color: bool,
+    pub won: bool,
+    pub health: HealthMonitor,
+    /// Which side of the cards is showing, only ever dark in UNO Flip
+    pub side: Side,
+    /// Who we can swap hands with, while the server waits on us to pick
+    pub targets: Vec<String>,
+    /// Our partner's name and hand, when partners play with their hands open
+    pub partner: Option<(String, Vec<PhysicalCard>)>
 }
 
 impl AppState {
@@ -39,19 +49,49 @@ impl AppState {
             messages: VecDeque::new(),
             hand: Hand::default(),
             top_card: Card::Wild(CardColor::Red),
+            active_color: CardColor::Red,
             selected: 0,
             wild_color: CardColor::None,
-            won: false
+            picking_color: false,
+            won: false,
+            health: HealthMonitor::default(),
+            side: Side::Light,
+            targets: vec![],
+            partner: None
         }
     }
+
+    /// If the server asked us to color the opening wild, answer with whatever color was just picked
+    fn pick_color(&mut self) -> Option<Action> {
+        if !self.picking_color {
+            return None
+        }
+
+        self.picking_color = false;
+        Some(Action::ChooseColor(self.wild_color))
+    }
+
+    /// Answers who to swap hands with, by their number in the list the server sent
+    fn pick_target(&mut self, key: char) -> Option<Action> {
+        let name = key.to_digit(10)
+            .and_then(|n| (n as usize).checked_sub(1))
+            .and_then(|i| self.targets.get(i))
+            .cloned()?;
+
+        self.targets.clear();
+        Some(Action::ChooseTarget(name))
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), io::Error> {
 
-    let url = "ws://127.0.0.1:8080";
+    let target = Target::from_args().unwrap_or_else(|e| {
+        eprintln!("{}\nUsage: {} [URL] [--ca FILE] [--token TOKEN]", e, env!("CARGO_PKG_NAME"));
+        std::process::exit(2);
+    });
 
-    let (ws_stream, _) = connect_async(url).await.expect("Failed to connect");
+    let ws_stream = target.connect().await.expect("Failed to connect");
 
     let (write, mut read) = ws_stream.split();
 
@@ -66,15 +106,30 @@ async fn main() -> Result<(), io::Error> {
 
     let app_state = Arc::new(RwLock::new(AppState::new()));
 
+    let pinger = write.clone();
+    let app_state_pinger = app_state.clone();
+    tokio::spawn(async move {
+        let mut every = tokio::time::interval(PING_EVERY);
+        loop {
+            every.tick().await;
+
+            let payload = app_state_pinger.write().unwrap().health.ping();
+            if pinger.lock().await.send(Message::Ping(payload)).await.is_err() {
+                break;
+            }
+        }
+    });
+
     let app_state_clone = app_state.clone();
     tokio::spawn(async move {
         while let Some(msg) = read.next().await {
             match msg {
                 Ok(Message::Text(text)) => {
+                    app_state_clone.write().unwrap().health.heard();
                     let deserialized: Result<DynMessage, _> = serde_json::from_str(&text);
 
                     if let Ok(msg) = deserialized {
-                        let begin_msg = match msg.sender {
+                        let begin_msg = match &msg.sender {
                             Some(name) => format!("{}: ", name),
                             None => String::new()
                         };
@@ -88,13 +143,37 @@ async fn main() -> Result<(), io::Error> {
                                     app_state.messages.push_back(format!("{}{}", begin_msg, msg));
                                 }
                             }, 
+                            Action::SeatToken(token) => {
+                                {
+                                    let app_state = app_state_clone.clone();
+                                    let mut app_state = app_state.write().unwrap();
+
+                                    app_state.messages.push_back(format!("Your seat token is {}, start with --token {} to get your seat back if the server restarts", token, token));
+                                }
+                            },
                             Action::Started(starting_cards) => {
                                 {
                                     let app_state = app_state_clone.clone();
                                     let mut app_state = app_state.write().unwrap();
 
-                                    app_state.hand.cards.extend(starting_cards.iter());
+                                    app_state.hand.cards = starting_cards.to_vec();
+                                    app_state.hand.last_choice = None;
                                     app_state.screen = Screen::InGame;
+                                    app_state.side = Side::Light;
+                                    app_state.targets.clear();
+                                    app_state.partner = None;
+                                }
+                            },
+                            Action::Hand(cards) => {
+                                {
+                                    let app_state = app_state_clone.clone();
+                                    let mut app_state = app_state.write().unwrap();
+
+                                    if !cards.is_empty() {
+                                        app_state.screen = Screen::InGame;
+                                    }
+                                    app_state.hand.cards = cards;
+                                    app_state.hand.last_choice = None;
                                 }
                             },
                             Action::TopCard(card) => {
@@ -103,6 +182,16 @@ async fn main() -> Result<(), io::Error> {
                                     let mut app_state = app_state.write().unwrap();
 
                                     app_state.top_card = card;
+                                    app_state.active_color = card.color();
+                                }
+                            },
+                            Action::ColorChosen(color) => {
+                                {
+                                    let app_state = app_state_clone.clone();
+                                    let mut app_state = app_state.write().unwrap();
+
+                                    app_state.active_color = color;
+                                    app_state.messages.push_back(format!("{}The color is now {}", begin_msg, color));
                                 }
                             },
                             Action::DrawnCard(card) => {
@@ -121,14 +210,14 @@ async fn main() -> Result<(), io::Error> {
                                         app_state.hand.cards.remove(choice);
                                         app_state.hand.last_choice = None;
 
-                                        if app_state.hand.cards.len() == 0 {
+                                        if app_state.hand.cards.is_empty() {
                                             println!("Win");
                                             app_state.won = true;
                                         }
                                     }
                                 }
                             },
-                            Action::PlayCard(card) => {
+                            Action::PlayCard { card, .. } => {
                                 {
                                     let app_state = app_state_clone.clone();
                                     let mut app_state = app_state.write().unwrap();
@@ -149,7 +238,43 @@ async fn main() -> Result<(), io::Error> {
                                     let app_state = app_state_clone.clone();
                                     let mut app_state = app_state.write().unwrap();
 
-                                    app_state.messages.push_back(format!("It's your turn!"));
+                                    app_state.messages.push_back("It's your turn!".to_string());
+                                }
+                            },
+                            Action::Flipped(side) => {
+                                {
+                                    let app_state = app_state_clone.clone();
+                                    let mut app_state = app_state.write().unwrap();
+
+                                    app_state.side = side;
+                                    app_state.wild_color = CardColor::None;
+                                }
+                            },
+                            Action::PickColor => {
+                                {
+                                    let app_state = app_state_clone.clone();
+                                    let mut app_state = app_state.write().unwrap();
+
+                                    app_state.picking_color = true;
+                                    app_state.messages.push_back("There's a wild on top, pick its color!".to_string());
+                                }
+                            },
+                            Action::PickTarget(names) => {
+                                {
+                                    let app_state = app_state_clone.clone();
+                                    let mut app_state = app_state.write().unwrap();
+
+                                    let choices: Vec<String> = names.iter().enumerate().map(|(i, name)| format!("{} {}", i + 1, name)).collect();
+                                    app_state.messages.push_back(format!("Swap hands with who? {}", choices.join(", ")));
+                                    app_state.targets = names;
+                                }
+                            },
+                            Action::PartnerHand(cards) => {
+                                {
+                                    let app_state = app_state_clone.clone();
+                                    let mut app_state = app_state.write().unwrap();
+
+                                    app_state.partner = msg.sender.clone().map(|name| (name, cards));
                                 }
                             },
                             Action::Skipped => {
@@ -157,7 +282,7 @@ async fn main() -> Result<(), io::Error> {
                                     let app_state = app_state_clone.clone();
                                     let mut app_state = app_state.write().unwrap();
 
-                                    app_state.messages.push_back(format!("YOU GOT SKIPPED BOY"));
+                                    app_state.messages.push_back("YOU GOT SKIPPED BOY".to_string());
                                 }
                             },
                             Action::DrawFour(cards) => {
@@ -186,14 +311,40 @@ async fn main() -> Result<(), io::Error> {
 
                                     app_state.screen = Screen::Action;
                                 }
-                            }
+                            },
+                            Action::GameOver => {
+                                {
+                                    let app_state = app_state_clone.clone();
+                                    let mut app_state = app_state.write().unwrap();
+
+                                    app_state.won = false;
+                                    app_state.hand.cards = vec![];
+                                    app_state.hand.last_choice = None;
+                                    app_state.picking_color = false;
+                                    app_state.targets.clear();
+                                    app_state.partner = None;
+
+                                    app_state.screen = Screen::Action;
+                                }
+                            },
                             _ => {}
                         }
                     }
                 }
-                _ => { break }
+                Ok(Message::Ping(_)) => app_state_clone.write().unwrap().health.heard(),
+                Ok(Message::Pong(payload)) => app_state_clone.write().unwrap().health.pong(&payload),
+                Ok(Message::Close(Some(frame))) => {
+                    app_state_clone.write().unwrap().messages.push_back(frame.reason.to_string());
+                    break;
+                },
+                Ok(Message::Close(None)) | Err(_) => break,
+                _ => {}
             }
         }
+
+        let mut app_state = app_state_clone.write().unwrap();
+        app_state.health.lost();
+        app_state.messages.push_back("Lost connection to the server".to_string());
     });
 
     loop {
@@ -252,14 +403,19 @@ async fn main() -> Result<(), io::Error> {
                         },
                         KeyCode::Enter => {
                             // Move to the next screen on Enter
-                            let app_state = app_state.clone();
-                            let mut app_state = app_state.write().unwrap();
-                            app_state.screen = Screen::Action;
+                            let msg = {
+                                let mut app_state = app_state.write().unwrap();
+                                app_state.screen = Screen::Action;
 
-                            let name = Action::SetName(app_state.input.clone());
-                            let action_msg = serde_json::to_string(&name).unwrap();
+                                // A seat token from before a restart asks for that seat back
+                                let name = match &target.token {
+                                    Some(token) => Action::Rejoin { name: app_state.input.clone(), token: token.clone() },
+                                    None => Action::SetName(app_state.input.clone())
+                                };
+                                let action_msg = serde_json::to_string(&name).unwrap();
 
-                            let msg = Message::text(action_msg.trim());
+                                Message::text(action_msg.trim())
+                            };
                             write.lock().await.send(msg).await.expect("Failed to set name");
                         },
                         _ => {}
@@ -279,22 +435,24 @@ async fn main() -> Result<(), io::Error> {
                             app_state.chat_input.pop(); 
                         },
                         KeyCode::Enter => {
-                            let app_state = app_state.clone();
-                            let mut app_state = app_state.write().unwrap();
-                            let msg = if &app_state.chat_input.trim() == &"START" {
-                                let string = serde_json::to_string(&Action::Start).unwrap();
-                                Message::text(string)
-                            } else {
+                            let msg = {
+                                let mut app_state = app_state.write().unwrap();
+                                let msg = if app_state.chat_input.trim() == "START" {
+                                    let string = serde_json::to_string(&Action::Start).unwrap();
+                                    Message::text(string)
+                                } else {
 
-                                let message = format!("{}: {}", app_state.input, app_state.chat_input);
-                                app_state.messages.push_back(message.clone());
+                                    let message = format!("{}: {}", app_state.input, app_state.chat_input);
+                                    app_state.messages.push_back(message.clone());
 
-                                let action = Action::Message(app_state.chat_input.clone());
-                                let action_msg = serde_json::to_string(&action).unwrap();
-                                Message::text(action_msg.trim())
-                            };
+                                    let action = Action::Message(app_state.chat_input.clone());
+                                    let action_msg = serde_json::to_string(&action).unwrap();
+                                    Message::text(action_msg.trim())
+                                };
 
-                            app_state.chat_input.clear();
+                                app_state.chat_input.clear();
+                                msg
+                            };
                             write.lock().await.send(msg).await.expect("Failed to send message");
                         },
                         _ => {}
@@ -325,57 +483,31 @@ async fn main() -> Result<(), io::Error> {
                             KeyCode::Char('d') | KeyCode::Char(' ') => {
                                 Some(Action::DrawCard)
                             },
-                            KeyCode::Char('r') | KeyCode::Char('1') => {
-                                let app_state = app_state.clone();
-                                let mut app_state = app_state.write().unwrap();
-            
-                                app_state.wild_color = CardColor::Red;
-
-                                None
-                            },
-                            KeyCode::Char('y') | KeyCode::Char('2') => {
-                                let app_state = app_state.clone();
-                                let mut app_state = app_state.write().unwrap();
-            
-                                app_state.wild_color = CardColor::Yellow;
-
-                                None
+                            KeyCode::Char(key @ '1'..='9') if !app_state.read().unwrap().targets.is_empty() => {
+                                app_state.write().unwrap().pick_target(key)
                             },
-                            KeyCode::Char('b') | KeyCode::Char('3') => {
+                            KeyCode::Char(key) if color_for_key(key, app_state.read().unwrap().side).is_some() => {
                                 let app_state = app_state.clone();
                                 let mut app_state = app_state.write().unwrap();
 
-                                app_state.wild_color = CardColor::Blue;
+                                app_state.wild_color = color_for_key(key, app_state.side).unwrap_or(CardColor::None);
 
-                                None
-                            },
-                            KeyCode::Char('g') | KeyCode::Char('4') => {
-                                let app_state = app_state.clone();
-                                let mut app_state = app_state.write().unwrap();
-            
-                                app_state.wild_color = CardColor::Green;
-
-                                None
+                                app_state.pick_color()
                             },
                             KeyCode::Enter => {
                                 let app_state = app_state.clone();
                                 let mut app_state = app_state.write().unwrap();
                                 let chosen_card = app_state.hand.cards[app_state.selected];
-                                if chosen_card.color() == CardColor::None {
+                                if chosen_card.card.color() == CardColor::None {
                                     if app_state.wild_color != CardColor::None {
-                                        let card = match chosen_card {
-                                            Card::DrawFour(_) => Card::DrawFour(app_state.wild_color),
-                                            Card::Wild(_) => Card::Wild(app_state.wild_color),
-                                            _ => unreachable!("No card other than +4 and Wild will ever have None as a color")
-                                        };
                                         app_state.hand.last_choice = Some(app_state.selected);
-                                        Some(Action::PlayCard(card))
+                                        Some(Action::PlayCard { card: chosen_card, chosen_color: Some(app_state.wild_color) })
                                     } else {
                                         None
                                     }
                                 } else {
                                     app_state.hand.last_choice = Some(app_state.selected);
-                                    Some(Action::PlayCard(chosen_card))
+                                    Some(Action::PlayCard { card: chosen_card, chosen_color: None })
                                 }
                             }
                             _ => None
@@ -406,7 +538,7 @@ fn draw_input_screen(f: &mut ratatui::Frame, app_state: Arc<RwLock<AppState>>) {
 
     let app_state = app_state.read().unwrap();
     let input = Paragraph::new(app_state.input.clone())
-        .block(Block::default().borders(Borders::ALL).title("Enter your name:"));
+        .block(Block::default().borders(Borders::ALL).title("Enter your name:").title(health_title(app_state.health.status())));
     f.render_widget(input, size);
 }
 
@@ -433,7 +565,7 @@ fn draw_action_screen(f: &mut ratatui::Frame, app_state: Arc<RwLock<AppState>>)
         .collect();
 
     let messages_widget = List::new(messages)
-        .block(Block::default().borders(Borders::ALL).title("Chat"));
+        .block(Block::default().borders(Borders::ALL).title("Chat").title(health_title(app_state.health.status())));
 
     f.render_widget(messages_widget, chunks[0]);
 
@@ -446,14 +578,19 @@ fn draw_action_screen(f: &mut ratatui::Frame, app_state: Arc<RwLock<AppState>>)
 fn draw_game_screen(f: &mut ratatui::Frame, app_state: Arc<RwLock<AppState>>) {
 
     let app_state = app_state.read().unwrap();
-    let chosen_color_none = 
+    let chosen_color_none = app_state.picking_color || (
         app_state.hand.cards.len() > app_state.selected 
-        && app_state.hand.cards[app_state.selected].color() == CardColor::None;
+        && app_state.hand.cards[app_state.selected].card.color() == CardColor::None);
 
     let size = f.size();
 
+    let title = match app_state.side {
+        Side::Light => "UNO Game".to_string(),
+        Side::Dark => format!("UNO Game ({} side)", app_state.side)
+    };
     let block = Block::default()
-        .title("UNO Game")
+        .title(title)
+        .title(health_title(app_state.health.status()))
         .borders(Borders::ALL);
     f.render_widget(block, size);
 
@@ -493,7 +630,7 @@ fn draw_game_screen(f: &mut ratatui::Frame, app_state: Arc<RwLock<AppState>>) {
         .messages
         .iter()
         .map(|m| ListItem::new(m.as_str()))
-        .last()
+        .next_back()
         .unwrap_or(ListItem::new(""));
 
     let messages_widget = List::new(vec![message])
@@ -502,11 +639,19 @@ fn draw_game_screen(f: &mut ratatui::Frame, app_state: Arc<RwLock<AppState>>) {
     f.render_widget(messages_widget, chunks[0]);
 
     let top = app_state.top_card;
-    let top_card_text = top.to_string();
+    let mut top_card_text = if top.color() == CardColor::None && app_state.active_color != CardColor::None {
+        format!("{} ({})", top, app_state.active_color)
+    } else {
+        top.to_string()
+    };
+    if let Some((name, cards)) = &app_state.partner {
+        let cards: Vec<String> = cards.iter().map(|card| card.to_string()).collect();
+        top_card_text.push_str(&format!("\n\n{} holds: {}", name, cards.join(", ")));
+    }
     let top_card_paragraph = Paragraph::new(top_card_text)
         .style(Style::default()
             .fg(Color::White)
-            .bg(color_to_tui_color(top.color())))
+            .bg(color_to_tui_color(app_state.active_color)))
         .block(Block::default().title("Top Card")
             .borders(Borders::ALL));
 
@@ -514,7 +659,7 @@ fn draw_game_screen(f: &mut ratatui::Frame, app_state: Arc<RwLock<AppState>>) {
 
     let cards = &app_state.hand.cards;
 
-    let card_width = if cards.len() != 0 {
+    let card_width = if !cards.is_empty() {
         size.width / cards.len() as u16
     } else {
         size.width
@@ -532,22 +677,22 @@ fn draw_game_screen(f: &mut ratatui::Frame, app_state: Arc<RwLock<AppState>>) {
 
     for (i, &card) in cards.iter().enumerate() {
         let style = if i == app_state.selected {
-            Style::default().bg(color_to_tui_color(card.color())).fg(Color::White)
+            Style::default().bg(color_to_tui_color(card.card.color())).fg(Color::White)
         } else {
             Style::default()
         };
         let card_text = card.to_string();
         let card_block = Paragraph::new(card_text)
             .style(style)
-            .block(Block::default().borders(Borders::ALL).style(Style::default().fg(color_to_tui_color(card.color()))));
+            .block(Block::default().borders(Borders::ALL).style(Style::default().fg(color_to_tui_color(card.card.color()))));
         f.render_widget(card_block, card_chunks[i]);
     }
 
     if chosen_color_none {
 
-        let colors = [CardColor::Red, CardColor::Yellow, CardColor::Blue, CardColor::Green];
+        let colors = palette(app_state.side);
 
-        let card_width = if colors.len() != 0 {
+        let card_width = if !colors.is_empty() {
             size.width / colors.len() as u16
         } else {
             size.width
@@ -579,6 +724,17 @@ fn draw_game_screen(f: &mut ratatui::Frame, app_state: Arc<RwLock<AppState>>) {
     }
 }
 
+/// Connection health, tucked into the top right corner of a block
+fn health_title(health: Health) -> Title<'static> {
+    let color = match health {
+        Health::Healthy(_) => Color::Green,
+        Health::Connecting | Health::Lagging(_) => Color::Yellow,
+        Health::Lost => Color::Red
+    };
+
+    Title::from(Span::styled(format!(" {} ", health), Style::default().fg(color))).alignment(Alignment::Right)
+}
+
 fn color_to_tui_color(color: CardColor) -> Color {
     match color {
         CardColor::None => Color::White,
@@ -586,5 +742,36 @@ fn color_to_tui_color(color: CardColor) -> Color {
         CardColor::Yellow => Color::Yellow,
         CardColor::Green => Color::Green,
         CardColor::Blue => Color::Blue,
+        CardColor::Pink => Color::LightMagenta,
+        CardColor::Teal => Color::Cyan,
+        CardColor::Orange => Color::Rgb(255, 140, 0),
+        CardColor::Purple => Color::Rgb(128, 0, 192)
     }
 }
+
+/// The colors a wild can be played as on `side`, in the order they're shown and numbered
+fn palette(side: Side) -> [CardColor; 4] {
+    match side {
+        Side::Light => [CardColor::Red, CardColor::Yellow, CardColor::Blue, CardColor::Green],
+        Side::Dark => [CardColor::Pink, CardColor::Teal, CardColor::Orange, CardColor::Purple]
+    }
+}
+
+/// Wild colors are picked by number, or by the first letter of their name on the light side and
+/// a letter of their own on the dark side
+fn color_for_key(key: char, side: Side) -> Option<CardColor> {
+    let color = match key {
+        '1'..='4' => palette(side)[key as usize - '1' as usize],
+        'r' => CardColor::Red,
+        'y' => CardColor::Yellow,
+        'b' => CardColor::Blue,
+        'g' => CardColor::Green,
+        'p' => CardColor::Pink,
+        't' => CardColor::Teal,
+        'o' => CardColor::Orange,
+        'u' => CardColor::Purple,
+        _ => return None
+    };
+
+    Some(color).filter(|color| color.side() == Some(side))
+}
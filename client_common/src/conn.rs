@@ -0,0 +1,70 @@
+use std::{env, fs::File, io::{self, BufReader}, path::{Path, PathBuf}, sync::Arc};
+
+use rustls::{ClientConfig, RootCertStore};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async_tls_with_config, tungstenite, Connector, MaybeTlsStream, WebSocketStream};
+
+pub const DEFAULT_URL: &str = "ws://127.0.0.1:8080";
+
+pub type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Where to connect, taken from the command line as `[URL] [--ca FILE] [--token TOKEN]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub url: String,
+    /// PEM file with the certificates to trust for `wss://` instead of the usual web roots
+    pub ca: Option<PathBuf>,
+    /// The seat token the server handed out last time, for getting that seat back after a restart
+    pub token: Option<String>
+}
+
+impl Target {
+    pub fn from_args() -> Result<Self, String> {
+        Self::parse(env::args().skip(1))
+    }
+
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut url = None;
+        let mut ca = None;
+        let mut token = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ca" => ca = Some(args.next().ok_or("--ca needs a file")?.into()),
+                "--token" => token = Some(args.next().ok_or("--token needs a token")?),
+                flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+                _ if url.is_none() => url = Some(arg),
+                _ => return Err(format!("Unexpected argument {}", arg))
+            }
+        }
+
+        Ok(Self { url: url.unwrap_or_else(|| DEFAULT_URL.to_string()), ca, token })
+    }
+
+    /// Opens the socket, over TLS if the url is `wss://`
+    pub async fn connect(&self) -> Result<Socket, tungstenite::Error> {
+        let connector = match &self.ca {
+            Some(ca) => Some(Connector::Rustls(Arc::new(trusting(ca)?))),
+            None => None
+        };
+
+        let (socket, _) = connect_async_tls_with_config(self.url.as_str(), None, false, connector).await?;
+
+        Ok(socket)
+    }
+}
+
+/// A TLS config that only trusts the certificates in `ca`
+pub(crate) fn trusting(ca: &Path) -> io::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+
+    for cert in rustls_pemfile::certs(&mut BufReader::new(File::open(ca)?)) {
+        roots.add(cert?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+
+    if roots.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("No certificates found in {}", ca.display())))
+    }
+
+    Ok(ClientConfig::builder().with_root_certificates(roots).with_no_client_auth())
+}
@@ -0,0 +1,91 @@
+use std::{fmt, time::{Duration, Instant}};
+
+/// How often we ping the server to see how it's doing
+pub const PING_EVERY: Duration = Duration::from_secs(5);
+/// Going this long without hearing anything from the server means something is wrong
+pub const LAGGING_AFTER: Duration = Duration::from_secs(10);
+
+/// How the connection to the server looks from our end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    /// Nothing has come back yet
+    Connecting,
+    /// Round trip time of the last ping we had answered
+    Healthy(Duration),
+    /// The server has been quiet for this long
+    Lagging(Duration),
+    Lost
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Health::Connecting => write!(f, "connecting"),
+            Health::Healthy(rtt) => write!(f, "connected, {} ms", rtt.as_millis()),
+            Health::Lagging(quiet) => write!(f, "no reply for {}s", quiet.as_secs()),
+            Health::Lost => write!(f, "disconnected")
+        }
+    }
+}
+
+/// Keeps track of our pings and anything else we hear back
+#[derive(Debug)]
+pub struct HealthMonitor {
+    last_heard: Instant,
+    rtt: Option<Duration>,
+    pings: u64,
+    in_flight: Option<(u64, Instant)>,
+    lost: bool
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self { last_heard: Instant::now(), rtt: None, pings: 0, in_flight: None, lost: false }
+    }
+}
+
+impl HealthMonitor {
+    /// Anything at all arrived from the server
+    pub fn heard(&mut self) {
+        self.last_heard = Instant::now();
+    }
+
+    /// Payload for the next ping, which is how we recognise its pong
+    pub fn ping(&mut self) -> Vec<u8> {
+        self.pings += 1;
+        self.in_flight = Some((self.pings, Instant::now()));
+
+        self.pings.to_be_bytes().to_vec()
+    }
+
+    pub fn pong(&mut self, payload: &[u8]) {
+        self.heard();
+
+        if let Some((id, sent)) = self.in_flight {
+            if payload == id.to_be_bytes() {
+                self.rtt = Some(sent.elapsed());
+                self.in_flight = None;
+            }
+        }
+    }
+
+    pub fn lost(&mut self) {
+        self.lost = true;
+    }
+
+    pub fn status(&self) -> Health {
+        self.status_at(Instant::now())
+    }
+
+    /// How things look as of `now`
+    pub(crate) fn status_at(&self, now: Instant) -> Health {
+        let quiet = now.saturating_duration_since(self.last_heard);
+
+        match self.rtt {
+            _ if self.lost => Health::Lost,
+            _ if quiet >= LAGGING_AFTER => Health::Lagging(quiet),
+            Some(rtt) => Health::Healthy(rtt),
+            None => Health::Connecting
+        }
+    }
+}
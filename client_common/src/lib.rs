@@ -0,0 +1,3 @@
+pub mod conn;
+pub mod health;
+pub mod test;
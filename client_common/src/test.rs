@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::ErrorKind, path::PathBuf, time::{Duration, Instant}};
+
+    use crate::{conn::{trusting, Target, DEFAULT_URL}, health::{Health, HealthMonitor, LAGGING_AFTER}};
+
+    /// A self signed certificate, good for a century
+    const TEST_CA: &str = "-----BEGIN CERTIFICATE-----
+MIIBhjCCAS2gAwIBAgIUK8AJY9tfBQYA7fCFbva0/XxDyiowCgYIKoZIzj0EAwIw
+GDEWMBQGA1UEAwwNd3Vub3MgdGVzdCBDQTAgFw0yNjEwMTkwMzIyNDdaGA8yMTI2
+MDkyNTAzMjI0N1owGDEWMBQGA1UEAwwNd3Vub3MgdGVzdCBDQTBZMBMGByqGSM49
+AgEGCCqGSM49AwEHA0IABNY0oZxrI8woFi4yks8dXd6gOFNsC267TjyoDIeHkLkt
+qjRn/Z6UzbV1p9O4MZwOeewuGG+qReTtgh5nH39uQoyjUzBRMB0GA1UdDgQWBBSw
+aIVgqc/1zKHL1n/T/uWy/0S7rTAfBgNVHSMEGDAWgBSwaIVgqc/1zKHL1n/T/uWy
+/0S7rTAPBgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0cAMEQCIEQZXkBxJxGp
+5oYVKTd2f0ech2rxjTnxdxBngDFgEtl+AiA8pBJEAGq9H5AUNeIUcxGUm9oNpjJS
+O7T1zsa20jB0eA==
+-----END CERTIFICATE-----
+";
+
+    fn parse(args: &[&str]) -> Result<Target, String> {
+        Target::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn target_from_args() {
+        assert_eq!(Ok(Target { url: DEFAULT_URL.into(), ca: None, token: None }), parse(&[]));
+        assert_eq!(Ok(Target { url: "wss://uno.example".into(), ca: None, token: None }), parse(&["wss://uno.example"]));
+        assert_eq!(
+            Ok(Target { url: "wss://uno.example".into(), ca: Some(PathBuf::from("ca.pem")), token: Some("abc".into()) }),
+            parse(&["--ca", "ca.pem", "wss://uno.example", "--token", "abc"])
+        );
+
+        assert!(parse(&["--ca"]).is_err());
+        assert!(parse(&["--token"]).is_err());
+        assert!(parse(&["--insecure"]).is_err());
+        assert!(parse(&["wss://one.example", "wss://two.example"]).is_err());
+    }
+
+    #[test]
+    fn custom_ca_is_loaded() {
+        let dir = env::temp_dir().join(format!("wunos-ca-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let ca = dir.join("ca.pem");
+        fs::write(&ca, TEST_CA).unwrap();
+        assert!(trusting(&ca).is_ok());
+
+        let empty = dir.join("empty.pem");
+        fs::write(&empty, "not a certificate").unwrap();
+        assert_eq!(ErrorKind::InvalidData, trusting(&empty).unwrap_err().kind());
+
+        assert_eq!(ErrorKind::NotFound, trusting(&dir.join("missing.pem")).unwrap_err().kind());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn health_follows_pings() {
+        let mut monitor = HealthMonitor::default();
+        assert_eq!(Health::Connecting, monitor.status());
+
+        // A pong that isn't for the ping in flight doesn't count as a round trip
+        let ping = monitor.ping();
+        monitor.pong(&[0xff]);
+        assert_eq!(Health::Connecting, monitor.status());
+
+        monitor.pong(&ping);
+        assert!(matches!(monitor.status(), Health::Healthy(_)));
+
+        let later = Instant::now() + LAGGING_AFTER + Duration::from_secs(1);
+        assert!(matches!(monitor.status_at(later), Health::Lagging(quiet) if quiet >= LAGGING_AFTER));
+
+        monitor.lost();
+        assert_eq!(Health::Lost, monitor.status());
+    }
+}
@@ -0,0 +1,148 @@
+use std::{convert::Infallible, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use warp::{http::StatusCode, reject::{self, Reject}, reply, Filter, Rejection, Reply};
+
+use crate::{client::outbox::QueueSnapshot, res::err::{Result, ServerError}, state::lobby::RoomDetail};
+
+use super::{api::{self, with_ctx}, context::{Health, ServerContext}};
+
+/// The dashboard itself. It holds no data, everything is fetched with the token once it's entered
+const PAGE: &str = include_str!("admin.html");
+
+/// Bigger than any kick or announcement needs
+const MAX_BODY: u64 = 4 * 1024;
+
+/// Everything the dashboard shows, fetched in one go
+#[derive(Serialize)]
+struct Overview {
+    health: Health,
+    queues: QueueSnapshot,
+    rooms: Vec<RoomDetail>
+}
+
+#[derive(Deserialize)]
+struct Kick {
+    player: String
+}
+
+#[derive(Deserialize)]
+struct Announce {
+    /// Every room when left out
+    room: Option<String>,
+    text: String
+}
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl Reject for Unauthorized {}
+
+/// The moderation dashboard, served at `/admin` only when there's a `token` to protect it. Its
+/// API under `/admin/api` wants `Authorization: Bearer <token>` on every request
+///
+/// - `GET /admin/api/overview` has every room in detail, plus the server's health and queues
+/// - `POST /admin/api/rooms/<name>/kick` with `{"player": ...}` removes a player
+/// - `POST /admin/api/rooms/<name>/end` stops the game without a winner
+/// - `POST /admin/api/announce` with `{"text": ...}` tells every room, or just `"room"`
+pub fn routes(ctx: ServerContext, token: Option<String>) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let enabled = token.is_some();
+    let token: Arc<str> = token.unwrap_or_default().into();
+
+    let page = warp::path!("admin")
+        .and(warp::get())
+        .map(|| reply::html(PAGE));
+
+    let overview = warp::path!("admin" / "api" / "overview")
+        .and(warp::get())
+        .and(authorized(token.clone()))
+        .and(with_ctx(ctx.clone()))
+        .and_then(overview);
+
+    let kick = warp::path!("admin" / "api" / "rooms" / String / "kick")
+        .and(warp::post())
+        .and(authorized(token.clone()))
+        .and(warp::body::content_length_limit(MAX_BODY))
+        .and(warp::body::json())
+        .and(with_ctx(ctx.clone()))
+        .and_then(|room: String, kick: Kick, ctx: ServerContext| async move {
+            done(ctx.kick(&room, &kick.player).await, || format!("Nobody called {} is in {}", kick.player, room))
+        });
+
+    let end = warp::path!("admin" / "api" / "rooms" / String / "end")
+        .and(warp::post())
+        .and(authorized(token.clone()))
+        .and(with_ctx(ctx.clone()))
+        .and_then(|room: String, ctx: ServerContext| async move {
+            done(ctx.end_game(&room).await, || format!("There's no game going in {}", room))
+        });
+
+    let announce = warp::path!("admin" / "api" / "announce")
+        .and(warp::post())
+        .and(authorized(token))
+        .and(warp::body::content_length_limit(MAX_BODY))
+        .and(warp::body::json())
+        .and(with_ctx(ctx))
+        .and_then(|announce: Announce, ctx: ServerContext| async move {
+            done(ctx.announce(announce.room.as_deref(), &announce.text).await.map(|_| true), String::new)
+        });
+
+    warp::any()
+        .and_then(move || async move {
+            if enabled { Ok(()) } else { Err(reject::not_found()) }
+        })
+        .untuple_one()
+        .and(page.or(overview).or(kick).or(end).or(announce))
+        .recover(unauthorized)
+}
+
+fn authorized(token: Arc<str>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let token = token.clone();
+            async move {
+                match header.as_deref().and_then(|header| header.strip_prefix("Bearer ")) {
+                    Some(given) if same(given.as_bytes(), token.as_bytes()) => Ok(()),
+                    _ => Err(reject::custom(Unauthorized))
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// Compares every byte whatever the first difference, so how long a guess takes to be turned
+/// down doesn't give away how close it was
+fn same(given: &[u8], token: &[u8]) -> bool {
+    given.len() == token.len() && given.iter().zip(token).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn unauthorized(rejection: Rejection) -> std::result::Result<reply::Response, Rejection> {
+    match rejection.find::<Unauthorized>() {
+        Some(Unauthorized) => Ok(api::error(StatusCode::UNAUTHORIZED, "Missing or wrong admin token").into_response()),
+        None => Err(rejection)
+    }
+}
+
+async fn overview(ctx: ServerContext) -> std::result::Result<reply::Json, Infallible> {
+    let rooms: Vec<String> = ctx.lobby.read().unwrap().summaries().into_iter().map(|room| room.name).collect();
+
+    let mut details = vec![];
+    for room in rooms {
+        // Rooms closing in the meantime are left out
+        if let Some(detail) = ctx.room_detail(&room).await {
+            details.push(detail);
+        }
+    }
+
+    Ok(reply::json(&Overview { health: ctx.health(), queues: ctx.metrics.queues.snapshot(), rooms: details }))
+}
+
+/// 204 when the action was carried out, otherwise why not
+fn done(res: Result<bool>, missing: impl FnOnce() -> String) -> std::result::Result<reply::Response, Infallible> {
+    Ok(match res {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => api::error(StatusCode::NOT_FOUND, missing()).into_response(),
+        Err(e @ ServerError::NoSuchRoom(_)) => api::error(StatusCode::NOT_FOUND, e.to_string()).into_response(),
+        Err(e) => api::error(StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response()
+    })
+}
@@ -0,0 +1,105 @@
+use std::{convert::Infallible, time::Duration};
+
+use futures_util::{stream, StreamExt};
+use serde::Serialize;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tracing::debug;
+use warp::{http::StatusCode, reply, sse, Filter, Rejection, Reply};
+
+use crate::state::view::FeedEvent;
+
+use super::context::ServerContext;
+
+/// How often a quiet event feed sends a comment to keep the connection open
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Why a request couldn't be answered
+#[derive(Serialize)]
+struct ApiError {
+    error: String
+}
+
+/// A JSON `{"error": ...}` body
+pub(crate) fn error(status: StatusCode, error: impl Into<String>) -> reply::WithStatus<reply::Json> {
+    reply::with_status(reply::json(&ApiError { error: error.into() }), status)
+}
+
+/// Read only JSON routes for anything that wants to look at the server without speaking the game
+/// protocol, all under `/api`
+///
+/// - `GET /api/rooms` lists every open room
+/// - `GET /api/rooms/<name>` shows who's sitting where and how the game is going, never anyone's cards
+/// - `GET /api/rooms/<name>/events` streams the room's public events as Server-Sent Events,
+///   starting with a `room` event holding the same as `/api/rooms/<name>`
+/// - `GET /api/health` answers 200 while serving and 503 once shutting down
+pub fn routes(ctx: ServerContext) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let rooms = warp::path!("api" / "rooms")
+        .and(with_ctx(ctx.clone()))
+        .map(|ctx: ServerContext| reply::json(&ctx.lobby.read().unwrap().summaries()));
+
+    let room = warp::path!("api" / "rooms" / String)
+        .and(with_ctx(ctx.clone()))
+        .and_then(room);
+
+    let events = warp::path!("api" / "rooms" / String / "events")
+        .and(with_ctx(ctx.clone()))
+        .and_then(events);
+
+    let health = warp::path!("api" / "health")
+        .and(with_ctx(ctx))
+        .map(|ctx: ServerContext| {
+            let health = ctx.health();
+            let status = if health.shutting_down { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+
+            reply::with_status(reply::json(&health), status)
+        });
+
+    // Dashboards usually live on another origin
+    let cors = warp::cors().allow_any_origin().allow_method("GET");
+
+    warp::get()
+        .and(rooms.or(room).or(events).or(health))
+        .with(cors)
+}
+
+async fn room(name: String, ctx: ServerContext) -> Result<impl Reply, Infallible> {
+    Ok(match ctx.room_detail(&name).await {
+        Some(detail) => reply::with_status(reply::json(&detail), StatusCode::OK),
+        None => not_found(&name)
+    })
+}
+
+async fn events(name: String, ctx: ServerContext) -> Result<reply::Response, Infallible> {
+    let Some((detail, feed)) = ctx.watch_room(&name).await else {
+        return Ok(not_found(&name).into_response())
+    };
+
+    let first = sse::Event::default().event("room").json_data(&detail);
+    let events = stream::once(async { first }).chain(follow(feed).map(|event| {
+        sse::Event::default().event(event.kind()).json_data(&event)
+    }));
+
+    // Proxies tend to close connections that go quiet
+    Ok(sse::reply(sse::keep_alive().interval(KEEP_ALIVE).stream(events)).into_response())
+}
+
+/// Every event from `feed` until the room closes
+fn follow(feed: Receiver<FeedEvent>) -> impl futures_util::Stream<Item = FeedEvent> {
+    stream::unfold(feed, |mut feed| async move {
+        loop {
+            match feed.recv().await {
+                Ok(event) => return Some((event, feed)),
+                Err(RecvError::Lagged(missed)) => debug!(missed, "Event feed fell behind"),
+                Err(RecvError::Closed) => return None
+            }
+        }
+    })
+}
+
+fn not_found(name: &str) -> reply::WithStatus<reply::Json> {
+    error(StatusCode::NOT_FOUND, format!("No room called {}", name))
+}
+
+pub(crate) fn with_ctx(ctx: ServerContext) -> impl Filter<Extract = (ServerContext,), Error = Infallible> + Clone {
+    warp::any().map(move || ctx.clone())
+}
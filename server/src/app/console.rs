@@ -0,0 +1,187 @@
+use std::{io::{self, BufRead}, str::FromStr, thread};
+
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::{res::err::Result, state::lobby::RoomDetail, ServerHandle};
+
+use super::logging::LogHandle;
+
+const HELP: &str = "\
+rooms                    list open rooms
+room <room>              who's in a room and how their game is going
+kick <room> <player>     remove a player and close their connection
+say <room|*> <text>      announce something to one room, or * for every room
+end <room>               stop the game in a room without a winner
+dump <room>              print everything about a room as JSON, hands and deck included
+log [filter]             show the log level, or change it, e.g. `log debug`
+shutdown                 save every room and stop the server
+help                     show this";
+
+/// Everything the operator can type into the server's stdin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Rooms,
+    Room(String),
+    Kick { room: String, player: String },
+    /// `room` is `None` for every room
+    Say { room: Option<String>, text: String },
+    End(String),
+    Dump(String),
+    Log(Option<String>),
+    Shutdown
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> std::result::Result<Self, Self::Err> {
+        let line = line.trim();
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        let room = |usage: &str| match rest.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [room] => Ok(room.to_string()),
+            _ => Err(format!("Usage: {}", usage))
+        };
+
+        match word.to_lowercase().as_str() {
+            "help" | "?" => Ok(Command::Help),
+            "rooms" | "ls" => Ok(Command::Rooms),
+            "room" => room("room <room>").map(Command::Room),
+            "end" => room("end <room>").map(Command::End),
+            "dump" => room("dump <room>").map(Command::Dump),
+            "kick" => match rest.split_once(char::is_whitespace) {
+                // Names can have spaces in them
+                Some((room, player)) => Ok(Command::Kick { room: room.to_string(), player: player.trim().to_string() }),
+                None => Err("Usage: kick <room> <player>".into())
+            },
+            "say" => match rest.split_once(char::is_whitespace) {
+                Some(("*", text)) => Ok(Command::Say { room: None, text: text.trim().to_string() }),
+                Some((room, text)) => Ok(Command::Say { room: Some(room.to_string()), text: text.trim().to_string() }),
+                None => Err("Usage: say <room|*> <text>".into())
+            },
+            "log" => Ok(Command::Log(Some(rest.to_string()).filter(|level| !level.is_empty()))),
+            "shutdown" | "quit" | "exit" => Ok(Command::Shutdown),
+            _ => Err(format!("Unknown command {:?}, try help", word))
+        }
+    }
+}
+
+/// Reads commands from stdin until it closes or the server shuts down. A server without a
+/// terminal, like one run as a service, just never hears from its operator
+pub async fn run(handle: ServerHandle, logs: LogHandle) {
+    // A plain thread rather than Tokio's stdin, which would keep the process from exiting until
+    // the operator pressed enter one last time
+    let (tx, mut lines) = mpsc::channel(16);
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) => if tx.blocking_send(line).is_err() {
+                    break
+                },
+                Err(e) => {
+                    warn!(error = %e, "Couldn't read the console");
+                    break
+                }
+            }
+        }
+    });
+
+    loop {
+        let line = tokio::select! {
+            line = lines.recv() => line,
+            _ = handle.shutting_down() => None
+        };
+        let Some(line) = line else {
+            break
+        };
+
+        if line.trim().is_empty() {
+            continue
+        }
+
+        let reply = match line.parse() {
+            Ok(command) => execute(&handle, &logs, command).await.unwrap_or_else(|e| e.to_string()),
+            Err(e) => e
+        };
+        println!("{}", reply);
+    }
+}
+
+async fn execute(handle: &ServerHandle, logs: &LogHandle, command: Command) -> Result<String> {
+    Ok(match command {
+        Command::Help => HELP.to_string(),
+        Command::Rooms => {
+            let rooms = handle.rooms();
+            if rooms.is_empty() {
+                return Ok("No rooms are open".into())
+            }
+
+            rooms.iter()
+                .map(|room| format!(
+                    "{}: {} playing, {} watching{}",
+                    room.name,
+                    room.players,
+                    room.spectators,
+                    if room.in_game { ", game in progress" } else { "" }
+                ))
+                .collect::<Vec<_>>()
+                .join("\n")
+        },
+        Command::Room(name) => match handle.room_detail(&name).await {
+            Some(room) => describe(&room),
+            None => format!("There's no room called {}", name)
+        },
+        Command::Kick { room, player } => match handle.kick(&room, &player).await? {
+            true => format!("Kicked {} from {}", player, room),
+            false => format!("Nobody called {} is in {}", player, room)
+        },
+        Command::Say { room, text } => {
+            let heard = handle.announce(room.as_deref(), &text).await?;
+            format!("Told {} room{}", heard, if heard == 1 { "" } else { "s" })
+        },
+        Command::End(room) => match handle.end_game(&room).await? {
+            true => format!("Ended the game in {}", room),
+            false => format!("There's no game going in {}", room)
+        },
+        Command::Dump(room) => {
+            let saved = handle.dump(&room).await?;
+            serde_json::to_string_pretty(&saved).unwrap_or_else(|e| format!("Couldn't dump {}: {}", room, e))
+        },
+        Command::Log(None) => format!("Logging {}", logs.level()),
+        Command::Log(Some(level)) => {
+            logs.set_level(&level)?;
+            format!("Now logging {}", logs.level())
+        },
+        Command::Shutdown => {
+            handle.shutdown();
+            "Shutting down".into()
+        }
+    })
+}
+
+fn describe(room: &RoomDetail) -> String {
+    let mut lines = vec![match (&room.top_card, &room.turn) {
+        (Some(top), turn) => format!(
+            "{}: game in progress, {} on top, {}'s turn",
+            room.summary.name,
+            top,
+            turn.as_deref().unwrap_or("someone")
+        ),
+        (None, _) => format!("{}: waiting for a game", room.summary.name)
+    }];
+
+    for seat in room.seats.iter() {
+        lines.push(format!(
+            "  {} ({:?}) {} cards{}",
+            seat.name.as_deref().unwrap_or("<unnamed>"),
+            seat.role,
+            seat.cards,
+            if seat.away { ", away" } else { "" }
+        ));
+    }
+
+    lines.join("\n")
+}
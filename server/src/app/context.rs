@@ -0,0 +1,294 @@
+use std::{path::PathBuf, sync::{Arc, RwLock}, time::Duration};
+
+use serde::Serialize;
+
+use tokio::{sync::{broadcast, watch}, time::Instant};
+use tracing::error;
+
+use super::{metrics::Metrics, webhooks::Notifier};
+
+use crate::{client::outbox::QueueSnapshot, config::settings::{Config, Outbound}, game::{decks::{Decks, DECK_DIR}, script::{Scripts, SCRIPT_DIR}}, res::err::{Result, ServerError}, state::{lobby::{Lobby, RoomDetail, RoomSummary}, room::RoomHandle, save::{self, SavedRoom}, view::FeedEvent}};
+
+/// Everything a connection needs from the server it belongs to. Cheap to clone, every clone
+/// shares the same rooms and shutdown signal
+#[derive(Clone)]
+pub struct ServerContext {
+    pub lobby: Arc<RwLock<Lobby>>,
+    pub idle_timeout: Duration,
+    pub ping_interval: Duration,
+    pub pong_timeout: Duration,
+    pub outbound: Outbound,
+    pub metrics: Arc<Metrics>,
+    pub data_dir: PathBuf,
+    shutdown: Arc<watch::Sender<bool>>,
+    connections: Arc<watch::Sender<usize>>,
+    started: Instant
+}
+
+/// How the server is doing, at a glance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Health {
+    pub shutting_down: bool,
+    pub uptime_secs: u64,
+    pub connections: usize,
+    pub rooms: usize,
+    pub games_in_progress: usize
+}
+
+/// Counts a connection as open for as long as it's held
+pub struct ConnectionGuard(Arc<watch::Sender<usize>>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.send_modify(|open| *open -= 1);
+    }
+}
+
+impl ServerContext {
+    pub fn new(config: &Config) -> Result<Self> {
+        let shutdown = watch::channel(false).0;
+        let metrics = Arc::new(Metrics::default());
+        let notifier = Notifier::start(&config.webhooks, metrics.clone())?;
+        let scripts = Scripts::load(&config.data_dir.join(SCRIPT_DIR))?.map(Arc::new);
+        let decks = Decks::load(&config.data_dir.join(DECK_DIR))?;
+        if decks.get(&config.rules.deck).is_none() {
+            return Err(ServerError::Config(format!("there's no deck called {}, only {}", config.rules.deck, decks.names().join(", "))))
+        }
+
+        Ok(Self {
+            lobby: Arc::new(RwLock::new(Lobby::new(config, shutdown.subscribe(), metrics.clone(), notifier, scripts, Arc::new(decks)))),
+            idle_timeout: config.idle_timeout(),
+            ping_interval: config.ping_interval(),
+            pong_timeout: config.pong_timeout(),
+            outbound: config.outbound,
+            metrics,
+            data_dir: config.data_dir.clone(),
+            shutdown: Arc::new(shutdown),
+            connections: Arc::new(watch::channel(0).0),
+            started: Instant::now()
+        })
+    }
+
+    pub fn track_connection(&self) -> ConnectionGuard {
+        self.connections.send_modify(|open| *open += 1);
+        ConnectionGuard(self.connections.clone())
+    }
+
+    pub fn open_connections(&self) -> usize {
+        *self.connections.borrow()
+    }
+
+    pub fn health(&self) -> Health {
+        let rooms = self.lobby.read().unwrap().summaries();
+
+        Health {
+            shutting_down: self.is_shutting_down(),
+            uptime_secs: self.started.elapsed().as_secs(),
+            connections: self.open_connections(),
+            rooms: rooms.len(),
+            games_in_progress: rooms.iter().filter(|room| room.in_game).count()
+        }
+    }
+
+    /// Everything about the room called `name` that isn't secret, `None` if it isn't open
+    pub async fn room_detail(&self, name: &str) -> Option<RoomDetail> {
+        let room = self.lobby.read().unwrap().get(name)?;
+        room.detail().await.ok()
+    }
+
+    /// The room called `name` as it is now and its public events from then on, `None` if it
+    /// isn't open
+    pub async fn watch_room(&self, name: &str) -> Option<(RoomDetail, broadcast::Receiver<FeedEvent>)> {
+        let room = self.lobby.read().unwrap().get(name)?;
+        room.watch().await.ok()
+    }
+
+    fn open_room(&self, name: &str) -> Result<RoomHandle> {
+        self.lobby.read().unwrap().get(name).ok_or_else(|| ServerError::NoSuchRoom(name.to_string()))
+    }
+
+    /// Every open room, for acting on all of them at once
+    fn open_rooms(&self) -> Vec<RoomHandle> {
+        let lobby = self.lobby.read().unwrap();
+        lobby.summaries().iter().filter_map(|room| lobby.get(&room.name)).collect()
+    }
+
+    /// Removes the player called `player` from the room called `room`, `false` if they aren't in it
+    pub async fn kick(&self, room: &str, player: &str) -> Result<bool> {
+        self.open_room(room)?.kick(player).await
+    }
+
+    /// Stops the game in `room` without a winner, `false` if there wasn't one going
+    pub async fn end_game(&self, room: &str) -> Result<bool> {
+        self.open_room(room)?.end().await
+    }
+
+    /// Says `text` to everyone in `room`, or in every room when `room` is `None`. Returns how
+    /// many rooms heard it
+    pub async fn announce(&self, room: Option<&str>, text: &str) -> Result<usize> {
+        let rooms = match room {
+            Some(room) => vec![self.open_room(room)?],
+            None => self.open_rooms()
+        };
+
+        let mut heard = 0;
+        for room in rooms {
+            // Rooms closing in the meantime just miss out
+            if room.announce(text).await.is_ok() {
+                heard += 1;
+            }
+        }
+
+        Ok(heard)
+    }
+
+    /// Everything about `room`, hands and deck included
+    pub async fn dump(&self, room: &str) -> Result<SavedRoom> {
+        self.open_room(room)?.dump().await
+    }
+
+    /// Everything `/metrics` serves
+    pub fn render_metrics(&self) -> String {
+        let rooms = self.lobby.read().unwrap().summaries();
+        self.metrics.render(&rooms, self.open_connections())
+    }
+
+    /// Resolves once every tracked connection has been dropped
+    pub async fn connections_closed(&self) {
+        let mut rx = self.connections.subscribe();
+        let _ = rx.wait_for(|open| *open == 0).await;
+    }
+
+    /// Reopens whatever rooms were saved at the last shutdown, returning how many there were
+    pub fn restore_rooms(&self) -> Result<usize> {
+        let saved = save::load(&self.data_dir)?;
+        let restored = saved.len();
+
+        // Players get as long to come back as they would to make a move
+        self.lobby.write().unwrap().restore(saved, Instant::now() + self.idle_timeout);
+
+        Ok(restored)
+    }
+
+    /// Waits for the server to shut down, then for every room to hand over its game, and writes
+    /// them all to `data_dir`. Returns how many rooms were saved
+    pub async fn save_rooms(&self) -> Result<usize> {
+        self.shutting_down().await;
+
+        let tasks = self.lobby.write().unwrap().take_tasks();
+        let mut rooms = vec![];
+        for task in tasks {
+            match task.await {
+                Ok(Some(room)) => rooms.push(room),
+                Ok(None) => {},
+                // Whatever broke it was logged under the room's span as it happened
+                Err(e) => error!(error = %e, "A room crashed, its game can't be saved")
+            }
+        }
+
+        save::store(&self.data_dir, &rooms)?;
+        Ok(rooms.len())
+    }
+
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Resolves once [`ServerContext::shutdown`] has been called
+    pub async fn shutting_down(&self) {
+        let mut rx = self.shutdown.subscribe();
+        // The sender lives as long as self, so this can't fail
+        let _ = rx.wait_for(|stop| *stop).await;
+    }
+}
+
+/// Lets whoever started a server stop it and look at what's going on inside
+#[derive(Clone)]
+pub struct ServerHandle {
+    ctx: ServerContext
+}
+
+impl ServerHandle {
+    pub(crate) fn new(ctx: ServerContext) -> Self {
+        Self { ctx }
+    }
+
+    /// Closes every connection and stops accepting new ones
+    pub fn shutdown(&self) {
+        self.ctx.shutdown()
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.ctx.is_shutting_down()
+    }
+
+    /// Resolves once the server starts shutting down
+    pub async fn shutting_down(&self) {
+        self.ctx.shutting_down().await
+    }
+
+    pub fn rooms(&self) -> Vec<RoomSummary> {
+        self.ctx.lobby.read().unwrap().summaries()
+    }
+
+    pub fn room(&self, name: &str) -> Option<RoomSummary> {
+        self.ctx.lobby.read().unwrap().summary(name)
+    }
+
+    /// Saves every room once the server has shut down, so the next server started with the same
+    /// `data_dir` can pick them back up. Only needed when serving [`WunosServer::routes`] yourself,
+    /// [`WunosServer::bind`] and [`WunosServer::run`] save on their own
+    ///
+    /// [`WunosServer::routes`]: crate::WunosServer::routes
+    /// [`WunosServer::bind`]: crate::WunosServer::bind
+    /// [`WunosServer::run`]: crate::WunosServer::run
+    pub async fn save_rooms(&self) -> Result<usize> {
+        self.ctx.save_rooms().await
+    }
+
+    /// Who's sitting where in the room called `name`, and how their game is going
+    pub async fn room_detail(&self, name: &str) -> Option<RoomDetail> {
+        self.ctx.room_detail(name).await
+    }
+
+    /// Follows everything public that happens in the room called `name`, see [`RoomHandle::watch`]
+    ///
+    /// [`RoomHandle::watch`]: crate::state::room::RoomHandle::watch
+    pub async fn watch_room(&self, name: &str) -> Option<(RoomDetail, broadcast::Receiver<FeedEvent>)> {
+        self.ctx.watch_room(name).await
+    }
+
+    pub fn health(&self) -> Health {
+        self.ctx.health()
+    }
+
+    /// Removes the player called `player` from the room called `room`, `false` if they aren't in it
+    pub async fn kick(&self, room: &str, player: &str) -> Result<bool> {
+        self.ctx.kick(room, player).await
+    }
+
+    /// Stops the game in `room` without a winner, `false` if there wasn't one going
+    pub async fn end_game(&self, room: &str) -> Result<bool> {
+        self.ctx.end_game(room).await
+    }
+
+    /// Says `text` to everyone in `room`, or in every room when `room` is `None`. Returns how
+    /// many rooms heard it
+    pub async fn announce(&self, room: Option<&str>, text: &str) -> Result<usize> {
+        self.ctx.announce(room, text).await
+    }
+
+    /// Everything about `room`, hands and deck included, for working out what went wrong with it
+    pub async fn dump(&self, room: &str) -> Result<SavedRoom> {
+        self.ctx.dump(room).await
+    }
+
+    /// How backed up the queues to every client are
+    pub fn queue_stats(&self) -> QueueSnapshot {
+        self.ctx.metrics.queues.snapshot()
+    }
+}
@@ -0,0 +1,41 @@
+use std::io::{self, IsTerminal};
+
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
+
+use crate::{config::settings::Log, res::err::{Result, ServerError}};
+
+/// Changes what gets logged while the server is running
+#[derive(Clone)]
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>
+}
+
+/// Installs the process wide logger. Only the first call in a process can succeed
+pub fn init(log: &Log) -> Result<LogHandle> {
+    let (filter, handle) = reload::Layer::new(parse(&log.level)?);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(log.json.then(|| fmt::layer().json()))
+        .with((!log.json).then(|| fmt::layer().with_ansi(io::stdout().is_terminal())))
+        .try_init()
+        .map_err(|e| ServerError::Config(format!("couldn't set up logging: {}", e)))?;
+
+    Ok(LogHandle { filter: handle })
+}
+
+impl LogHandle {
+    pub fn set_level(&self, level: &str) -> Result<()> {
+        self.filter.reload(parse(level)?)
+            .map_err(|e| ServerError::Config(format!("couldn't change the log level: {}", e)))
+    }
+
+    pub fn level(&self) -> String {
+        self.filter.with_current(|filter| filter.to_string()).unwrap_or_default()
+    }
+}
+
+fn parse(level: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(level)
+        .map_err(|e| ServerError::Config(format!("log.level {:?} is not a valid filter: {}", level, e)))
+}
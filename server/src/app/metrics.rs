@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use prometheus::{core::Collector, exponential_buckets, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+
+use crate::{client::outbox::QueueStats, state::{lobby::RoomSummary, msg::Action}};
+
+/// Everything exported on `/metrics`. Counters are bumped as things happen, gauges are read off
+/// the server whenever it's scraped
+pub struct Metrics {
+    registry: Registry,
+    pub queues: QueueStats,
+    connections: IntGauge,
+    rooms: IntGauge,
+    games: IntGauge,
+    received: IntCounterVec,
+    sent: IntCounterVec,
+    handled: HistogramVec,
+    reshuffles: IntCounter,
+    errors: IntCounterVec,
+    queued: IntGauge,
+    deepest: IntGauge,
+    dropped: IntCounter,
+    coalesced: IntCounter,
+    evicted: IntCounter,
+    webhooks: IntCounterVec
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = Registry::new_custom(Some("wunos".into()), None).expect("The prefix is valid");
+
+        // Actions are handled in memory, so the interesting range is microseconds to a few
+        // hundred milliseconds rather than the default's 5ms to 10s
+        let buckets = exponential_buckets(0.00001, 4.0, 9).expect("The buckets are valid");
+
+        Self {
+            queues: QueueStats::default(),
+            connections: register(&registry, IntGauge::new("connections", "Open websocket connections")),
+            rooms: register(&registry, IntGauge::new("rooms", "Open rooms")),
+            games: register(&registry, IntGauge::new("games_in_progress", "Rooms with a game underway")),
+            received: register(&registry, IntCounterVec::new(Opts::new("messages_received_total", "Messages read from clients"), &["action"])),
+            sent: register(&registry, IntCounterVec::new(Opts::new("messages_sent_total", "Messages queued for clients"), &["action"])),
+            handled: register(&registry, HistogramVec::new(HistogramOpts::new("action_duration_seconds", "Time rooms spend handling an action").buckets(buckets), &["action"])),
+            reshuffles: register(&registry, IntCounter::new("reshuffles_total", "Discard piles shuffled back into the draw pile")),
+            errors: register(&registry, IntCounterVec::new(Opts::new("errors_total", "Errors, by what went wrong"), &["kind"])),
+            queued: register(&registry, IntGauge::new("outbound_queued", "Messages waiting to be written across every connection")),
+            deepest: register(&registry, IntGauge::new("outbound_deepest", "The most messages any one connection has had waiting")),
+            dropped: register(&registry, IntCounter::new("outbound_dropped_total", "Chat dropped for clients falling behind")),
+            coalesced: register(&registry, IntCounter::new("outbound_coalesced_total", "Queued top cards and colors replaced by newer ones")),
+            evicted: register(&registry, IntCounter::new("outbound_evicted_total", "Clients disconnected for falling too far behind")),
+            webhooks: register(&registry, IntCounterVec::new(Opts::new("webhooks_total", "Webhook deliveries, by how they went"), &["outcome"])),
+            registry
+        }
+    }
+}
+
+impl Metrics {
+    pub fn received(&self, action: &Action) {
+        self.received.with_label_values(&[action.kind()]).inc();
+    }
+
+    pub fn sent(&self, action: &Action) {
+        self.sent.with_label_values(&[action.kind()]).inc();
+    }
+
+    pub fn handled(&self, action: &str, took: Duration) {
+        self.handled.with_label_values(&[action]).observe(took.as_secs_f64());
+    }
+
+    pub fn reshuffled(&self, times: u64) {
+        self.reshuffles.inc_by(times);
+    }
+
+    pub fn error(&self, kind: &str) {
+        self.errors.with_label_values(&[kind]).inc();
+    }
+
+    pub fn webhook(&self, outcome: &str) {
+        self.webhooks.with_label_values(&[outcome]).inc();
+    }
+
+    /// Everything in the Prometheus text format
+    pub fn render(&self, rooms: &[RoomSummary], connections: usize) -> String {
+        self.connections.set(connections as i64);
+        self.rooms.set(rooms.len() as i64);
+        self.games.set(rooms.iter().filter(|room| room.in_game).count() as i64);
+
+        let queues = self.queues.snapshot();
+        self.queued.set(queues.queued as i64);
+        self.deepest.set(queues.deepest as i64);
+        catch_up(&self.dropped, queues.dropped);
+        catch_up(&self.coalesced, queues.coalesced);
+        catch_up(&self.evicted, queues.evicted);
+
+        TextEncoder::new().encode_to_string(&self.registry.gather()).unwrap_or_default()
+    }
+}
+
+fn register<T: Collector + Clone + 'static>(registry: &Registry, metric: prometheus::Result<T>) -> T {
+    let metric = metric.expect("Metric definitions are valid");
+    registry.register(Box::new(metric.clone())).expect("Metric names are unique");
+
+    metric
+}
+
+/// Brings a counter up to a total kept somewhere else
+fn catch_up(counter: &IntCounter, total: u64) {
+    counter.inc_by(total.saturating_sub(counter.get()));
+}
@@ -0,0 +1,9 @@
+pub mod admin;
+pub mod api;
+pub mod console;
+pub mod context;
+pub mod logging;
+pub mod metrics;
+pub mod server;
+pub mod webhooks;
+pub mod test;
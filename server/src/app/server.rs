@@ -0,0 +1,218 @@
+use std::{future::Future, net::SocketAddr, path::PathBuf, pin::Pin, time::Duration};
+
+use tracing::{error, info};
+use warp::{reply, Filter, Rejection, Reply};
+
+use crate::{client::handler::handle_connection, config::settings::Config, game::rules::Rules, res::err::Result, state::lobby::DEFAULT_ROOM};
+
+use super::{admin, api::{self, with_ctx}, context::{ServerContext, ServerHandle}};
+
+/// How long connections get to say goodbye to their clients once the server shuts down
+const CLOSE_GRACE: Duration = Duration::from_secs(5);
+
+/// A Wunos server that can either run on its own or have its routes mounted inside another warp
+/// application
+pub struct WunosServer {
+    config: Config,
+    ctx: ServerContext
+}
+
+/// Runs until the server is shut down through its [`ServerHandle`]
+pub type ServeFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+impl WunosServer {
+    pub fn builder() -> WunosServerBuilder {
+        WunosServerBuilder::default()
+    }
+
+    /// Also reopens any rooms saved when a server last shut down with the same `data_dir`, so
+    /// this has to be called from inside a Tokio runtime
+    pub fn new(config: Config) -> Result<Self> {
+        config.validate()?;
+        let ctx = ServerContext::new(&config)?;
+
+        let restored = ctx.restore_rooms()?;
+        if restored > 0 {
+            info!(rooms = restored, data_dir = %config.data_dir.display(), "Restored rooms");
+        }
+
+        Ok(Self { config, ctx })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle::new(self.ctx.clone())
+    }
+
+    /// The websocket routes, `/` for the default room and `/rooms/<name>` for any other,
+    /// `/metrics` for Prometheus, the read only JSON API under `/api` and, with a token set, the
+    /// admin dashboard under `/admin`
+    pub fn routes(&self) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        let ctx = self.ctx.clone();
+
+        let default_room = warp::path::end().map(|| DEFAULT_ROOM.to_string());
+        let named_room = warp::path!("rooms" / String);
+
+        let rooms = default_room.or(named_room).unify()
+            .and(warp::ws())
+            .and(with_ctx(ctx.clone()))
+            .map(|room: String, ws: warp::ws::Ws, ctx: ServerContext| {
+                ws.on_upgrade(move |socket| handle_connection(socket, ctx, room))
+            });
+
+        let metrics = warp::path!("metrics")
+            .and(warp::get())
+            .and(with_ctx(ctx.clone()))
+            .map(|ctx: ServerContext| reply::with_header(ctx.render_metrics(), "content-type", prometheus::TEXT_FORMAT));
+
+        metrics
+            .or(api::routes(ctx.clone()))
+            .or(admin::routes(ctx, self.config.admin.token.clone()))
+            .or(rooms)
+    }
+
+    /// Binds to the configured address, over TLS if it's set up. Returns the address actually
+    /// bound, which matters when asking for port 0, and the future that runs the server. Once
+    /// shut down, that future saves every room before finishing
+    pub fn bind(&self) -> Result<(SocketAddr, ServeFuture)> {
+        let ctx = self.ctx.clone();
+        let stopped = async move { ctx.shutting_down().await };
+
+        let (addr, serving): (SocketAddr, ServeFuture) = match self.config.tls() {
+            Some((cert, key)) => {
+                let (addr, serving) = warp::serve(self.routes())
+                    .tls()
+                    .cert_path(cert)
+                    .key_path(key)
+                    .try_bind_with_graceful_shutdown(self.config.addr(), stopped)?;
+
+                (addr, Box::pin(serving))
+            },
+            None => {
+                let (addr, serving) = warp::serve(self.routes())
+                    .try_bind_with_graceful_shutdown(self.config.addr(), stopped)?;
+
+                (addr, Box::pin(serving))
+            }
+        };
+
+        let ctx = self.ctx.clone();
+        let serving = async move {
+            serving.await;
+
+            match ctx.save_rooms().await {
+                Ok(0) => {},
+                Ok(saved) => info!(rooms = saved, data_dir = %ctx.data_dir.display(), "Saved rooms"),
+                Err(e) => {
+                    error!(error = %e, "Couldn't save rooms");
+                    ctx.metrics.error(e.kind());
+                }
+            }
+
+            let _ = tokio::time::timeout(CLOSE_GRACE, ctx.connections_closed()).await;
+        };
+
+        Ok((addr, Box::pin(serving)))
+    }
+
+    /// Serves until shut down
+    pub async fn run(self) -> Result<()> {
+        let (addr, serving) = self.bind()?;
+        let scheme = if self.config.tls().is_some() { "wss" } else { "ws" };
+
+        info!("Listening on {}://{}", scheme, addr);
+        serving.await;
+
+        Ok(())
+    }
+}
+
+/// Starts from the built in defaults, see [`Config`] for what each setting does
+#[derive(Debug, Clone, Default)]
+pub struct WunosServerBuilder {
+    config: Config
+}
+
+impl WunosServerBuilder {
+    /// Replaces every setting at once, usually with one loaded through [`Config::load`]
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn addr(mut self, addr: SocketAddr) -> Self {
+        self.config.bind = addr.ip();
+        self.config.port = addr.port();
+        self
+    }
+
+    pub fn rules(mut self, rules: Rules) -> Self {
+        self.config.rules = rules;
+        self
+    }
+
+    pub fn max_rooms(mut self, max_rooms: usize) -> Self {
+        self.config.max_rooms = max_rooms;
+        self
+    }
+
+    pub fn max_players(mut self, max_players: usize) -> Self {
+        self.config.max_players = max_players;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeouts.idle_secs = timeout.as_secs();
+        self
+    }
+
+    /// How often clients are pinged, and how long one can go without answering before it's
+    /// dropped. Both are rounded down to whole seconds
+    pub fn heartbeat(mut self, ping: Duration, pong: Duration) -> Self {
+        self.config.timeouts.ping_secs = ping.as_secs();
+        self.config.timeouts.pong_secs = pong.as_secs();
+        self
+    }
+
+    pub fn data_dir(mut self, data_dir: impl Into<PathBuf>) -> Self {
+        self.config.data_dir = data_dir.into();
+        self
+    }
+
+    pub fn tls(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.config.tls.cert = Some(cert.into());
+        self.config.tls.key = Some(key.into());
+        self
+    }
+
+    pub fn allow_plaintext(mut self, allow: bool) -> Self {
+        self.config.allow_plaintext = allow;
+        self
+    }
+
+    /// Adds a url to POST game events to
+    pub fn webhook(mut self, url: impl Into<String>) -> Self {
+        self.config.webhooks.urls.push(url.into());
+        self
+    }
+
+    pub fn webhook_secret(mut self, secret: impl Into<String>) -> Self {
+        self.config.webhooks.secret = Some(secret.into());
+        self
+    }
+
+    /// Serves the admin dashboard at `/admin`, for anyone with `token`
+    pub fn admin_token(mut self, token: impl Into<String>) -> Self {
+        self.config.admin.token = Some(token.into());
+        self
+    }
+
+    /// Checks the settings and sets up an empty lobby. Nothing is bound until
+    /// [`WunosServer::bind`] or [`WunosServer::run`]
+    pub fn build(self) -> Result<WunosServer> {
+        WunosServer::new(self.config)
+    }
+}
@@ -0,0 +1,906 @@
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io, net::SocketAddr, path::PathBuf, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
+
+    use ring::hmac;
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, sync::mpsc};
+    use tracing::Level;
+    use warp::{http::{HeaderMap, StatusCode}, hyper::body::Bytes, Filter};
+
+    use crate::{app::{console::Command, logging, metrics::Metrics, webhooks::{HookEvent, Notifier}}, config::settings::{Log, Webhooks}, game::rules::Rules, res::err::ServerError, state::{lobby::RoomSummary, msg::{Action, DynMessage}, save::SAVE_FILE}, WunosServer, WunosServerBuilder};
+
+    fn builder(name: &str) -> (WunosServerBuilder, PathBuf) {
+        let dir = env::temp_dir().join(format!("wunos-app-{}-{}", name, std::process::id()));
+
+        (WunosServer::builder().data_dir(&dir), dir)
+    }
+
+    #[test]
+    fn builder_validates_settings() {
+        let (builder, dir) = builder("validate");
+
+        assert!(matches!(builder.clone().max_players(1).build(), Err(ServerError::Config(_))));
+
+        let server = builder.max_rooms(2).rules(Rules { match_actions: false, ..Rules::default() }).build().unwrap();
+        assert_eq!(2, server.config().max_rooms);
+        assert!(!server.config().rules.match_actions);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn routes_mount_inside_another_app() {
+        let (builder, dir) = builder("embed");
+        let server = builder.build().unwrap();
+        let handle = server.handle();
+
+        let portal = warp::path("uno").and(server.routes());
+        let mut client = warp::test::ws().path("/uno/rooms/lounge").handshake(portal).await.unwrap();
+
+        // Being greeted means we've been seated
+        let greeting: DynMessage = serde_json::from_str(client.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert!(greeting.sender.is_none());
+
+        let lounge = handle.room("lounge").unwrap();
+        assert_eq!(1, lounge.players);
+        assert!(!lounge.in_game);
+        assert_eq!(None, handle.room("default"));
+
+        handle.shutdown();
+
+        client.recv_closed().await.unwrap();
+
+        // The room closes once its last player is gone
+        for _ in 0..50 {
+            if handle.rooms().is_empty() {
+                break
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(handle.rooms().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rooms_survive_a_restart() {
+        let (builder, dir) = builder("restart");
+        let server = builder.clone().build().unwrap();
+        let handle = server.handle();
+
+        let mut alice = warp::test::ws().path("/rooms/lounge").handshake(server.routes()).await.unwrap();
+        alice.recv().await.unwrap();
+        let mut bob = warp::test::ws().path("/rooms/lounge").handshake(server.routes()).await.unwrap();
+
+        alice.send_text(serde_json::to_string(&Action::SetName("alice".into())).unwrap()).await;
+        alice.send_text(serde_json::to_string(&Action::Message("brb".into())).unwrap()).await;
+
+        // Bob hearing it from alice means her name has been set
+        let heard: DynMessage = serde_json::from_str(bob.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(Some("alice".to_string()), heard.sender);
+        let msg: DynMessage = serde_json::from_str(alice.recv().await.unwrap().to_str().unwrap()).unwrap();
+        let Action::SeatToken(token) = msg.action else {
+            panic!("Expected a seat token, got {:?}", msg.action)
+        };
+
+        handle.shutdown();
+        assert_eq!(1, handle.save_rooms().await.unwrap());
+
+        let server = builder.build().unwrap();
+        let handle = server.handle();
+        assert_eq!(2, handle.room("lounge").unwrap().players);
+
+        let mut returning = warp::test::ws().path("/rooms/lounge").handshake(server.routes()).await.unwrap();
+        returning.send_text(serde_json::to_string(&Action::Rejoin { name: "alice".into(), token }).unwrap()).await;
+
+        let mut rejoined = false;
+        for _ in 0..3 {
+            let msg: DynMessage = serde_json::from_str(returning.recv().await.unwrap().to_str().unwrap()).unwrap();
+            if let Action::Hand(_) = msg.action {
+                rejoined = true;
+                break;
+            }
+        }
+        assert!(rejoined);
+        assert_eq!(2, handle.room("lounge").unwrap().players);
+
+        // The save was used up by the restore
+        assert!(!dir.join(SAVE_FILE).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn metrics_are_scrapeable() {
+        let (builder, dir) = builder("metrics");
+        let server = builder.build().unwrap();
+        let routes = server.routes();
+
+        let mut client = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        client.recv().await.unwrap();
+        client.send_text(serde_json::to_string(&Action::SetName("alice".into())).unwrap()).await;
+
+        let mut scraped = String::new();
+        for _ in 0..50 {
+            let res = warp::test::request().path("/metrics").reply(&routes).await;
+            assert_eq!(200, res.status());
+            assert!(res.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+
+            scraped = String::from_utf8(res.body().to_vec()).unwrap();
+            if scraped.contains(r#"wunos_action_duration_seconds_count{action="set_name"} 1"#) {
+                break
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert!(scraped.contains("wunos_connections 1"));
+        assert!(scraped.contains("wunos_rooms 1"));
+        assert!(scraped.contains("wunos_games_in_progress 0"));
+        assert!(scraped.contains(r#"wunos_messages_received_total{action="set_name"} 1"#));
+        assert!(scraped.contains(r#"wunos_action_duration_seconds_count{action="set_name"} 1"#));
+        // The admin greeting
+        assert!(scraped.contains(r#"wunos_messages_sent_total{action="message"} 1"#));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn metrics_keep_their_names_and_labels() {
+        let metrics = Metrics::default();
+        metrics.received(&Action::DrawCard);
+        metrics.received(&Action::DrawCard);
+        metrics.sent(&Action::YourTurn);
+        metrics.handled("draw_card", Duration::from_micros(50));
+        metrics.reshuffled(2);
+        metrics.error(ServerError::RoomFull.kind());
+        metrics.webhook("delivered");
+
+        let room = |name: &str, in_game| RoomSummary { name: name.into(), players: 2, spectators: 0, in_game, max_players: None };
+        let scraped = metrics.render(&[room("lounge", true), room("den", false)], 3);
+
+        // Dashboards are built on these, so renaming any of them breaks someone's graphs
+        for line in [
+            "wunos_connections 3",
+            "wunos_rooms 2",
+            "wunos_games_in_progress 1",
+            r#"wunos_messages_received_total{action="draw_card"} 2"#,
+            r#"wunos_messages_sent_total{action="your_turn"} 1"#,
+            r#"wunos_action_duration_seconds_bucket{action="draw_card",le="0.00001"} 0"#,
+            r#"wunos_action_duration_seconds_bucket{action="draw_card",le="0.00016"} 1"#,
+            r#"wunos_action_duration_seconds_count{action="draw_card"} 1"#,
+            "wunos_reshuffles_total 2",
+            r#"wunos_errors_total{kind="room_full"} 1"#,
+            r#"wunos_webhooks_total{outcome="delivered"} 1"#,
+            "wunos_outbound_queued 0",
+            "wunos_outbound_dropped_total 0",
+            "# TYPE wunos_messages_received_total counter",
+            "# TYPE wunos_connections gauge",
+            "# TYPE wunos_action_duration_seconds histogram"
+        ] {
+            assert!(scraped.lines().any(|scraped| scraped == line), "{} missing from\n{}", line, scraped);
+        }
+
+        // Gauges follow the server rather than adding up
+        let scraped = metrics.render(&[], 0);
+        assert!(scraped.lines().any(|line| line == "wunos_rooms 0"));
+        assert!(scraped.lines().any(|line| line == "wunos_connections 0"));
+    }
+
+    /// Somewhere to write logs to that the test can read back
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            io::Write::write(&mut *self.0.lock().unwrap(), buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Captured {
+        fn lines(&self) -> Vec<serde_json::Value> {
+            let logs = self.0.lock().unwrap();
+            String::from_utf8_lossy(&logs).lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+        }
+    }
+
+    #[test]
+    fn log_levels_change_at_runtime() {
+        let bad = Log { level: "server=loud".into(), json: false };
+        assert!(matches!(logging::init(&bad), Err(ServerError::Config(_))));
+
+        // Kept quiet so the rest of the tests don't print everything they do
+        let logs = logging::init(&Log { level: "off".into(), json: false }).unwrap();
+        assert_eq!("off", logs.level());
+        assert!(matches!(logging::init(&Log { level: "off".into(), json: true }), Err(ServerError::Config(_))));
+
+        logs.set_level("off,server::state=off").unwrap();
+        assert_eq!("server::state=off,off", logs.level());
+        // A bad filter leaves the old one in place
+        assert!(matches!(logs.set_level("server=loud"), Err(ServerError::Config(_))));
+        assert_eq!("server::state=off,off", logs.level());
+    }
+
+    #[tokio::test]
+    async fn actions_are_logged_with_who_sent_them() {
+        let logs = Captured::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_max_level(Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+        // Rooms and connections run as tasks on this same thread, so they log here too
+        let _logging = tracing::subscriber::set_default(subscriber);
+
+        let (builder, dir) = builder("logging");
+        let server = builder.build().unwrap();
+        let mut client = warp::test::ws().path("/rooms/lounge").handshake(server.routes()).await.unwrap();
+        client.recv().await.unwrap();
+        client.send_text(serde_json::to_string(&Action::SetName("alice".into())).unwrap()).await;
+
+        let handled = |line: &serde_json::Value| line["fields"]["message"] == "Handling" && line["span"]["action"] == "set_name";
+        while !logs.lines().iter().any(handled) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let lines = logs.lines();
+        let joined = lines.iter().find(|line| line["fields"]["message"] == "Joined").unwrap();
+        assert_eq!("INFO", joined["level"]);
+        assert_eq!("connection", joined["span"]["name"]);
+        assert_eq!("lounge", joined["span"]["room"]);
+        let player = &joined["span"]["player"];
+        assert!(player.is_string());
+
+        // Whatever a room does about an action is logged under the room, and who sent it
+        let handling = lines.iter().find(|line| handled(line)).unwrap();
+        assert_eq!("DEBUG", handling["level"]);
+        assert_eq!("room", handling["spans"][0]["name"]);
+        assert_eq!("lounge", handling["spans"][0]["room"]);
+        assert_eq!(player, &handling["span"]["player"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rest_api_describes_rooms() {
+        let (builder, dir) = builder("api");
+        let server = builder.build().unwrap();
+        let handle = server.handle();
+        let routes = server.routes();
+
+        let mut client = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        client.recv().await.unwrap();
+        client.send_text(serde_json::to_string(&Action::SetName("alice".into())).unwrap()).await;
+
+        let mut detail = serde_json::Value::Null;
+        for _ in 0..50 {
+            let res = warp::test::request().path("/api/rooms/lounge").reply(&routes).await;
+            assert_eq!(200, res.status());
+
+            detail = serde_json::from_slice(res.body()).unwrap();
+            if detail["seats"][0]["name"] == "alice" {
+                break
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!("lounge", detail["name"]);
+        assert_eq!("alice", detail["seats"][0]["name"]);
+        assert_eq!(0, detail["seats"][0]["cards"]);
+        assert!(detail["top_card"].is_null());
+
+        let res = warp::test::request().path("/api/rooms").reply(&routes).await;
+        let rooms: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!("lounge", rooms[0]["name"]);
+
+        let res = warp::test::request().path("/api/rooms/nowhere").reply(&routes).await;
+        assert_eq!(404, res.status());
+
+        let res = warp::test::request().path("/api/health").reply(&routes).await;
+        assert_eq!(200, res.status());
+        let health: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(1, health["connections"]);
+        assert_eq!(1, health["rooms"]);
+
+        // Only reads are allowed
+        let res = warp::test::request().method("POST").path("/api/rooms").reply(&routes).await;
+        assert_ne!(200, res.status());
+
+        handle.shutdown();
+        let res = warp::test::request().path("/api/health").reply(&routes).await;
+        assert_eq!(503, res.status());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rest_api_json_keeps_its_shape() {
+        let (builder, dir) = builder("api-shape");
+        let server = builder.max_players(4).build().unwrap();
+        let handle = server.handle();
+        let routes = server.routes();
+
+        let mut alice = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        alice.recv().await.unwrap();
+        alice.send_text(serde_json::to_string(&Action::SetName("alice".into())).unwrap()).await;
+        let mut bob = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        bob.send_text(serde_json::to_string(&Action::SetName("bob".into())).unwrap()).await;
+        while handle.room_detail("lounge").await.map_or(0, |room| room.seats.iter().filter(|seat| seat.name.is_some()).count()) < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        alice.send_text(serde_json::to_string(&Action::Start).unwrap()).await;
+        while !handle.rooms()[0].in_game {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let get = |path: &'static str| {
+            let routes = routes.clone();
+            async move {
+                let res = warp::test::request().path(path).reply(&routes).await;
+                assert_eq!("application/json", res.headers()["content-type"]);
+                (res.status(), serde_json::from_slice::<serde_json::Value>(res.body()).unwrap())
+            }
+        };
+        let keys = |value: &serde_json::Value| {
+            let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+
+        let (status, rooms) = get("/api/rooms").await;
+        assert_eq!(200, status);
+        assert_eq!(serde_json::json!([
+            { "name": "lounge", "players": 2, "spectators": 0, "in_game": true, "max_players": 4 }
+        ]), rooms);
+
+        // Other dashboards read these by name, so none of them can quietly change
+        let (_, detail) = get("/api/rooms/lounge").await;
+        assert_eq!(
+            ["active_color", "direction", "in_game", "max_players", "name", "players", "seats", "spectators", "top_card", "turn"].as_slice(),
+            keys(&detail)
+        );
+        assert_eq!(["away", "cards", "name", "role"].as_slice(), keys(&detail["seats"][0]));
+        assert_eq!("alice", detail["seats"][0]["name"]);
+        assert_eq!("Admin", detail["seats"][0]["role"]);
+        assert_eq!(false, detail["seats"][0]["away"]);
+        // The opening card can have made someone draw on top of the seven dealt
+        assert!(detail["seats"][0]["cards"].as_u64().unwrap() >= 7);
+        assert_eq!("User", detail["seats"][1]["role"]);
+        assert!(["alice", "bob"].iter().any(|name| detail["turn"] == *name));
+        assert!(["Forward", "Backward"].iter().any(|direction| detail["direction"] == *direction));
+        assert!(detail["active_color"].is_string());
+        assert!(!detail["top_card"].is_null());
+
+        let (_, health) = get("/api/health").await;
+        assert_eq!(["connections", "games_in_progress", "rooms", "shutting_down", "uptime_secs"].as_slice(), keys(&health));
+        assert_eq!(1, health["games_in_progress"]);
+        assert_eq!(false, health["shutting_down"]);
+        assert!(health["uptime_secs"].is_u64());
+
+        let (status, missing) = get("/api/rooms/nowhere").await;
+        assert_eq!(404, status);
+        assert_eq!(serde_json::json!({ "error": "No room called nowhere" }), missing);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rooms_can_be_followed() {
+        let (builder, dir) = builder("events");
+        let server = builder.build().unwrap();
+        let handle = server.handle();
+        let routes = server.routes();
+
+        let res = warp::test::request().path("/api/rooms/lounge/events").reply(&routes).await;
+        assert_eq!(404, res.status());
+
+        let mut client = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        client.recv().await.unwrap();
+
+        let feed = routes.clone();
+        let following = tokio::spawn(async move { warp::test::request().path("/api/rooms/lounge/events").reply(&feed).await });
+        // Long enough for the room to take the watcher on
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The feed lasts as long as the room, which ends with the server
+        handle.shutdown();
+        let res = tokio::time::timeout(Duration::from_secs(5), following).await.unwrap().unwrap();
+        assert_eq!(200, res.status());
+        assert_eq!("text/event-stream", res.headers()["content-type"]);
+
+        let body = String::from_utf8(res.body().to_vec()).unwrap();
+        assert!(body.starts_with("event:room\ndata:{\"name\":\"lounge\""), "{}", body);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn webhooks_are_signed_and_retried() {
+        // Stands in for a chat bot, turning the first delivery away to make sure it's retried
+        let (tx, mut delivered) = mpsc::unbounded_channel();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let receiver = warp::post()
+            .and(warp::header::headers_cloned())
+            .and(warp::body::bytes())
+            .map(move |headers: HeaderMap, body: Bytes| {
+                let status = match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => StatusCode::SERVICE_UNAVAILABLE,
+                    _ => StatusCode::OK
+                };
+                tx.send((headers, body, status)).unwrap();
+                warp::reply::with_status("", status)
+            });
+        let (hook_addr, receiving) = warp::serve(receiver).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(receiving);
+
+        let (builder, dir) = builder("webhooks");
+        let server = builder.webhook(format!("http://{}/uno", hook_addr)).webhook_secret("hunter2").build().unwrap();
+        let routes = server.routes();
+
+        let mut alice = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        alice.recv().await.unwrap();
+        alice.send_text(serde_json::to_string(&Action::SetName("alice".into())).unwrap()).await;
+
+        let mut bob = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        bob.send_text(serde_json::to_string(&Action::SetName("bob".into())).unwrap()).await;
+
+        // Both have to be seated before the game starts
+        while server.handle().room_detail("lounge").await.map_or(0, |room| room.seats.iter().filter(|seat| seat.name.is_some()).count()) < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        alice.send_text(serde_json::to_string(&Action::Start).unwrap()).await;
+
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"hunter2");
+        let mut turned_away = None;
+        let mut events = vec![];
+        while events.len() < 3 {
+            let (headers, body, status) = tokio::time::timeout(Duration::from_secs(5), delivered.recv()).await.unwrap().unwrap();
+
+            let signature = headers["x-wunos-signature"].to_str().unwrap().strip_prefix("sha256=").unwrap();
+            let signature: Vec<u8> = (0..signature.len()).step_by(2).map(|i| u8::from_str_radix(&signature[i..i + 2], 16).unwrap()).collect();
+            hmac::verify(&key, &body, &signature).unwrap();
+
+            let event: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!("lounge", event["room"]);
+            assert_eq!(event["event"], headers["x-wunos-event"].to_str().unwrap());
+            assert_eq!(event["id"], headers["x-wunos-delivery"].to_str().unwrap());
+
+            match turned_away.take() {
+                None if status != StatusCode::OK => turned_away = Some(event),
+                // The retry carries the very same event
+                Some(first) => {
+                    assert_eq!(first, event);
+                    events.push(event);
+                },
+                None => events.push(event)
+            }
+        }
+
+        assert_eq!("player_joined", events[0]["event"]);
+        assert_eq!("player_joined", events[1]["event"]);
+        assert_eq!("game_started", events[2]["event"]);
+        assert_eq!(2, events[2]["players"].as_array().unwrap().len());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Stands in for a webhook receiver, answering each delivery with the next of `statuses` and
+    /// the last one from then on
+    fn hook_receiver(statuses: Vec<StatusCode>) -> (String, mpsc::UnboundedReceiver<(HeaderMap, Bytes, Instant)>) {
+        let (tx, delivered) = mpsc::unbounded_channel();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let receiver = warp::post()
+            .and(warp::header::headers_cloned())
+            .and(warp::body::bytes())
+            .map(move |headers: HeaderMap, body: Bytes| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                tx.send((headers, body, Instant::now())).unwrap();
+                warp::reply::with_status("", statuses[attempt.min(statuses.len() - 1)])
+            });
+        let (addr, receiving) = warp::serve(receiver).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(receiving);
+
+        (format!("http://{}/hooks", addr), delivered)
+    }
+
+    async fn webhook_outcome(metrics: &Metrics, outcome: &str) {
+        let counted = format!("wunos_webhooks_total{{outcome=\"{}\"}} 1", outcome);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !metrics.render(&[], 0).lines().any(|line| line == counted) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_webhooks_back_off_then_give_up() {
+        let (url, mut delivered) = hook_receiver(vec![StatusCode::BAD_GATEWAY]);
+        let metrics = Arc::new(Metrics::default());
+        let webhooks = Webhooks { urls: vec![url], retries: 2, ..Webhooks::default() };
+        let notifier = Notifier::start(&webhooks, metrics.clone()).unwrap();
+
+        notifier.for_room("lounge").send(HookEvent::PlayerLeft { player: "alice".into() });
+
+        let mut attempts = vec![];
+        for _ in 0..3 {
+            let (headers, _, at) = tokio::time::timeout(Duration::from_secs(5), delivered.recv()).await.unwrap().unwrap();
+            // Nothing's signed without a secret
+            assert!(!headers.contains_key("x-wunos-signature"));
+            attempts.push(at);
+        }
+        webhook_outcome(&metrics, "failed").await;
+        assert!(delivered.try_recv().is_err());
+
+        // Half a second, then twice that
+        assert!(attempts[1] - attempts[0] >= Duration::from_millis(500));
+        assert!(attempts[2] - attempts[1] >= Duration::from_millis(1000));
+    }
+
+    #[tokio::test]
+    async fn rejected_webhooks_arent_retried() {
+        // Being told to slow down is worth another try, being told no isn't
+        let (url, mut delivered) = hook_receiver(vec![StatusCode::TOO_MANY_REQUESTS, StatusCode::BAD_REQUEST]);
+        let metrics = Arc::new(Metrics::default());
+        let webhooks = Webhooks { urls: vec![url], secret: Some("hunter2".into()), retries: 5, ..Webhooks::default() };
+        let notifier = Notifier::start(&webhooks, metrics.clone()).unwrap();
+
+        notifier.for_room("lounge").send(HookEvent::Won { winner: Some("bob".into()) });
+
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"hunter2");
+        for _ in 0..2 {
+            let (headers, body, _) = tokio::time::timeout(Duration::from_secs(5), delivered.recv()).await.unwrap().unwrap();
+            assert_eq!("application/json", headers["content-type"]);
+            assert_eq!("won", headers["x-wunos-event"]);
+
+            // Lowercase hex, as most webhook libraries expect
+            let signature = headers["x-wunos-signature"].to_str().unwrap().strip_prefix("sha256=").unwrap();
+            assert_eq!(64, signature.len());
+            assert!(signature.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')));
+            let signature: Vec<u8> = (0..signature.len()).step_by(2).map(|i| u8::from_str_radix(&signature[i..i + 2], 16).unwrap()).collect();
+            hmac::verify(&key, &body, &signature).unwrap();
+            // Signed with anything else, it doesn't check out
+            assert!(hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, b"hunter3"), &body, &signature).is_err());
+
+            let event: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(serde_json::json!("bob"), event["winner"]);
+        }
+        webhook_outcome(&metrics, "rejected").await;
+        assert!(delivered.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn full_webhook_queue_drops_events() {
+        let (url, mut delivered) = hook_receiver(vec![StatusCode::OK]);
+        let metrics = Arc::new(Metrics::default());
+        let webhooks = Webhooks { urls: vec![url], queue: 1, ..Webhooks::default() };
+        let notifier = Notifier::start(&webhooks, metrics.clone()).unwrap().for_room("lounge");
+
+        // Nothing's been taken off the queue yet, so only the first fits
+        for player in ["alice", "bob", "carol"] {
+            notifier.send(HookEvent::PlayerJoined { player: player.into() });
+        }
+        assert!(metrics.render(&[], 0).lines().any(|line| line == r#"wunos_webhooks_total{outcome="dropped"} 2"#));
+
+        let (_, body, _) = tokio::time::timeout(Duration::from_secs(5), delivered.recv()).await.unwrap().unwrap();
+        let event: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("alice", event["player"]);
+        webhook_outcome(&metrics, "delivered").await;
+        assert!(delivered.try_recv().is_err());
+    }
+
+    #[test]
+    fn console_commands_parse() {
+        assert_eq!(Ok(Command::Rooms), "rooms".parse());
+        assert_eq!(Ok(Command::Kick { room: "lounge".into(), player: "big al".into() }), "kick lounge big al".parse());
+        assert_eq!(Ok(Command::Say { room: None, text: "back in 5".into() }), "say * back in 5".parse());
+        assert_eq!(Ok(Command::Say { room: Some("lounge".into()), text: "hi".into() }), " SAY lounge  hi ".parse());
+        assert_eq!(Ok(Command::Log(None)), "log".parse());
+        assert_eq!(Ok(Command::Log(Some("server=debug".into()))), "log server=debug".parse());
+
+        assert!("kick lounge".parse::<Command>().is_err());
+        assert!("end".parse::<Command>().is_err());
+        assert!("dance".parse::<Command>().is_err());
+    }
+
+    #[tokio::test]
+    async fn operators_can_step_in() {
+        let (builder, dir) = builder("operator");
+        let server = builder.build().unwrap();
+        let handle = server.handle();
+        let routes = server.routes();
+
+        let mut alice = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        alice.recv().await.unwrap();
+        alice.send_text(serde_json::to_string(&Action::SetName("alice".into())).unwrap()).await;
+        let mut bob = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        bob.send_text(serde_json::to_string(&Action::SetName("bob".into())).unwrap()).await;
+
+        while handle.room_detail("lounge").await.map_or(0, |room| room.seats.iter().filter(|seat| seat.name.is_some()).count()) < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        alice.send_text(serde_json::to_string(&Action::Start).unwrap()).await;
+        while !handle.rooms()[0].in_game {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(2, handle.dump("lounge").await.unwrap().players.len());
+        assert!(handle.end_game("lounge").await.unwrap());
+        assert!(!handle.end_game("lounge").await.unwrap());
+        assert!(!handle.rooms()[0].in_game);
+        // Everyone's told to throw their hand away
+        for client in [&mut alice, &mut bob] {
+            let mut over = false;
+            while !over {
+                let msg: DynMessage = serde_json::from_str(client.recv().await.unwrap().to_str().unwrap()).unwrap();
+                over = matches!(msg.action, Action::GameOver);
+            }
+        }
+
+        assert!(handle.kick("lounge", "bob").await.unwrap());
+        assert!(!handle.kick("lounge", "bob").await.unwrap());
+        // Told why, then disconnected
+        let mut told = false;
+        while let Ok(msg) = bob.recv().await {
+            told |= msg.to_str().is_ok_and(|text| text.contains("removed by the server operator"));
+        }
+        assert!(told);
+
+        assert_eq!(1, handle.announce(None, "Back in 5").await.unwrap());
+        let mut heard = false;
+        while let Ok(msg) = alice.recv().await {
+            let msg: DynMessage = serde_json::from_str(msg.to_str().unwrap()).unwrap();
+            if msg.sender.is_none() && matches!(&msg.action, Action::Message(text) if text == "Back in 5") {
+                heard = true;
+                break
+            }
+        }
+        assert!(heard);
+
+        assert!(matches!(handle.kick("nowhere", "bob").await, Err(ServerError::NoSuchRoom(_))));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn admin_dashboard_needs_its_token() {
+        let (builder, dir) = builder("admin");
+        let hidden = builder.clone().build().unwrap().routes();
+        assert_eq!(404, warp::test::request().path("/admin").reply(&hidden).await.status());
+
+        let token = "correct horse battery staple";
+        let server = builder.admin_token(token).build().unwrap();
+        let routes = server.routes();
+
+        let page = warp::test::request().path("/admin").reply(&routes).await;
+        assert_eq!(200, page.status());
+        assert!(String::from_utf8_lossy(page.body()).contains("Wunos admin"));
+
+        let mut client = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        client.recv().await.unwrap();
+        client.send_text(serde_json::to_string(&Action::SetName("alice".into())).unwrap()).await;
+        while server.handle().room_detail("lounge").await.is_none_or(|room| room.seats[0].name.is_none()) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let overview = || warp::test::request().path("/admin/api/overview");
+        assert_eq!(401, overview().reply(&routes).await.status());
+        assert_eq!(401, overview().header("authorization", "Bearer correct horse battery stapler").reply(&routes).await.status());
+
+        let auth = format!("Bearer {}", token);
+        let res = overview().header("authorization", &auth).reply(&routes).await;
+        assert_eq!(200, res.status());
+        let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(1, body["health"]["connections"]);
+        assert_eq!("lounge", body["rooms"][0]["name"]);
+
+        let post = |path: &str| warp::test::request().method("POST").path(path).header("authorization", &auth);
+        assert_eq!(401, warp::test::request().method("POST").path("/admin/api/rooms/lounge/end").reply(&routes).await.status());
+        assert_eq!(404, post("/admin/api/rooms/lounge/end").reply(&routes).await.status());
+        assert_eq!(404, post("/admin/api/rooms/nowhere/end").reply(&routes).await.status());
+        assert_eq!(404, post("/admin/api/rooms/lounge/kick").json(&serde_json::json!({ "player": "bob" })).reply(&routes).await.status());
+        assert_eq!(204, post("/admin/api/announce").json(&serde_json::json!({ "room": "lounge", "text": "Be nice" })).reply(&routes).await.status());
+
+        let mut told = false;
+        while !told {
+            let msg: DynMessage = serde_json::from_str(client.recv().await.unwrap().to_str().unwrap()).unwrap();
+            told = matches!(&msg.action, Action::Message(text) if text == "Be nice");
+        }
+
+        // Kicking alice empties the room, closing it
+        assert_eq!(204, post("/admin/api/rooms/lounge/kick").json(&serde_json::json!({ "player": "alice" })).reply(&routes).await.status());
+        while !server.handle().rooms().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn admin_api_turns_away_bad_tokens() {
+        let (builder, dir) = builder("admin-refused");
+        let token = "correct horse battery staple";
+        let server = builder.admin_token(token).build().unwrap();
+        let handle = server.handle();
+        let routes = server.routes();
+
+        let mut alice = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        alice.recv().await.unwrap();
+        alice.send_text(serde_json::to_string(&Action::SetName("alice".into())).unwrap()).await;
+        let mut bob = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        bob.send_text(serde_json::to_string(&Action::SetName("bob".into())).unwrap()).await;
+        while handle.room_detail("lounge").await.map_or(0, |room| room.seats.iter().filter(|seat| seat.name.is_some()).count()) < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        alice.send_text(serde_json::to_string(&Action::Start).unwrap()).await;
+        while !handle.rooms()[0].in_game {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let wrong = [
+            None,
+            Some(String::new()),
+            Some(token.to_string()),
+            Some(format!("Basic {}", token)),
+            Some(format!("bearer {}", token)),
+            Some("Bearer ".to_string()),
+            Some("Bearer correct horse battery stapl".to_string()),
+            Some(format!("Bearer {} ", token)),
+            Some("Bearer CORRECT HORSE BATTERY STAPLE".to_string())
+        ];
+        for auth in wrong.iter() {
+            let request = |method: &str, path: &str| {
+                let request = warp::test::request().method(method).path(path);
+                match auth {
+                    Some(auth) => request.header("authorization", auth),
+                    None => request
+                }
+            };
+
+            for res in [
+                request("GET", "/admin/api/overview").reply(&routes).await,
+                request("POST", "/admin/api/rooms/lounge/end").reply(&routes).await,
+                request("POST", "/admin/api/rooms/lounge/kick").json(&serde_json::json!({ "player": "bob" })).reply(&routes).await,
+                request("POST", "/admin/api/announce").json(&serde_json::json!({ "text": "Sneaky" })).reply(&routes).await
+            ] {
+                assert_eq!(401, res.status(), "{:?} got in", auth);
+                let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+                assert_eq!(serde_json::json!({ "error": "Missing or wrong admin token" }), body);
+            }
+        }
+
+        // None of it went through
+        let room = handle.room("lounge").unwrap();
+        assert!(room.in_game);
+        assert_eq!(2, room.players);
+        let res = warp::test::request().method("POST").path("/admin/api/announce")
+            .header("authorization", format!("Bearer {}", token))
+            .json(&serde_json::json!({ "text": "Real" }))
+            .reply(&routes).await;
+        assert_eq!(204, res.status());
+        loop {
+            let msg: DynMessage = serde_json::from_str(alice.recv().await.unwrap().to_str().unwrap()).unwrap();
+            match msg.action {
+                Action::Message(text) if msg.sender.is_none() && (text == "Real" || text == "Sneaky") => {
+                    assert_eq!("Real", text);
+                    break
+                },
+                _ => {}
+            }
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_stops_serving() {
+        let (builder, dir) = builder("shutdown");
+        let server = builder.addr(SocketAddr::from(([127, 0, 0, 1], 0))).build().unwrap();
+        let handle = server.handle();
+
+        let (addr, serving) = server.bind().unwrap();
+        assert_ne!(0, addr.port());
+
+        let serving = tokio::spawn(serving);
+        handle.shutdown();
+
+        assert!(handle.is_shutting_down());
+        tokio::time::timeout(Duration::from_secs(5), serving).await.unwrap().unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dead_connections_are_dropped() {
+        let (builder, dir) = builder("dead");
+        let server = builder
+            .addr(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .heartbeat(Duration::from_secs(5), Duration::from_secs(15))
+            .build()
+            .unwrap();
+        let handle = server.handle();
+        let (addr, serving) = server.bind().unwrap();
+        tokio::spawn(serving);
+
+        // A client that finishes the handshake and then goes quiet, like a laptop that was shut
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        socket.write_all(format!(
+            "GET / HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            addr
+        ).as_bytes()).await.unwrap();
+        let mut response = [0; 12];
+        socket.read_exact(&mut response).await.unwrap();
+        assert_eq!(b"HTTP/1.1 101", &response);
+
+        while handle.room("default").is_none() {
+            tokio::task::yield_now().await;
+        }
+
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert_eq!(None, handle.room("default"));
+
+        handle.shutdown();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pongs_dont_count_as_activity() {
+        let (builder, dir) = builder("idle");
+        let server = builder
+            .heartbeat(Duration::from_secs(5), Duration::from_secs(15))
+            .idle_timeout(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        let handle = server.handle();
+        let routes = server.routes();
+
+        let mut alice = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        alice.recv().await.unwrap();
+        alice.send_text(serde_json::to_string(&Action::SetName("alice".into())).unwrap()).await;
+        let mut bob = warp::test::ws().path("/rooms/lounge").handshake(routes.clone()).await.unwrap();
+        bob.send_text(serde_json::to_string(&Action::SetName("bob".into())).unwrap()).await;
+
+        // Waiting around for a game doesn't make anyone idle
+        tokio::time::sleep(Duration::from_secs(120)).await;
+        assert_eq!(2, handle.room("lounge").unwrap().players);
+
+        alice.send_text(serde_json::to_string(&Action::Start).unwrap()).await;
+        while !handle.room("lounge").is_some_and(|room| room.in_game) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        tokio::time::sleep(Duration::from_secs(50)).await;
+        assert_eq!(2, handle.room("lounge").unwrap().players);
+
+        // Only whoever's turn it is gets dropped for sitting on it, which ends the game
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        let room = handle.room("lounge").unwrap();
+        assert_eq!(1, room.players);
+        assert!(!room.in_game);
+
+        tokio::time::sleep(Duration::from_secs(120)).await;
+        assert_eq!(1, handle.room("lounge").unwrap().players);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn answered_pings_keep_connections_alive() {
+        let (builder, dir) = builder("alive");
+        let server = builder.heartbeat(Duration::from_secs(5), Duration::from_secs(15)).build().unwrap();
+        let handle = server.handle();
+
+        let mut client = warp::test::ws().handshake(server.routes()).await.unwrap();
+        client.recv().await.unwrap();
+
+        // Reading a ping is enough for the pong to go out
+        for _ in 0..10 {
+            assert!(client.recv().await.unwrap().is_ping());
+        }
+        assert_eq!(1, handle.room("default").unwrap().players);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+use std::{sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use futures_util::future::join_all;
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode, Url};
+use ring::hmac;
+use serde::Serialize;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{debug, info_span, warn, Instrument};
+use uuid::Uuid;
+
+use crate::{config::settings::Webhooks, res::err::{Result, ServerError}};
+
+use super::metrics::Metrics;
+
+/// How long to wait before trying a failed delivery again, doubled after every attempt
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Something that happened in a room that the outside world might want to hear about
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HookEvent {
+    /// Everyone dealt in, in the order they sit
+    GameStarted { players: Vec<Option<String>> },
+    Won { winner: Option<String> },
+    /// A game finished, with no winner if everyone left before it was won
+    RoundEnded { winner: Option<String>, hands: Vec<HandCount> },
+    /// Someone named themselves for the first time in the room
+    PlayerJoined { player: String },
+    PlayerLeft { player: String }
+}
+
+/// How many cards a player was left holding
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HandCount {
+    pub player: Option<String>,
+    pub cards: usize
+}
+
+impl HookEvent {
+    /// Sent in the `X-Wunos-Event` header
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HookEvent::GameStarted { .. } => "game_started",
+            HookEvent::Won { .. } => "won",
+            HookEvent::RoundEnded { .. } => "round_ended",
+            HookEvent::PlayerJoined { .. } => "player_joined",
+            HookEvent::PlayerLeft { .. } => "player_left"
+        }
+    }
+}
+
+/// The body POSTed to every url
+#[derive(Debug, Serialize)]
+struct Delivery {
+    /// The same across every retry, so receivers can tell when they've seen one before
+    id: Uuid,
+    room: String,
+    /// Seconds since the Unix epoch
+    at: u64,
+    #[serde(flatten)]
+    event: HookEvent
+}
+
+/// Hands events over to be POSTed in the background, dropping them once `webhooks.queue` are
+/// waiting rather than holding up any game. Does nothing when there are no urls to deliver to
+#[derive(Clone, Default)]
+pub struct Notifier(Option<Arc<Outgoing>>);
+
+struct Outgoing {
+    queue: mpsc::Sender<Delivery>,
+    metrics: Arc<Metrics>
+}
+
+/// A [`Notifier`] for a single room
+#[derive(Clone, Default)]
+pub struct RoomNotifier {
+    room: String,
+    notifier: Notifier
+}
+
+impl Notifier {
+    /// Starts delivering to `webhooks.urls`, so has to be called from inside a Tokio runtime
+    /// unless there aren't any
+    pub fn start(webhooks: &Webhooks, metrics: Arc<Metrics>) -> Result<Self> {
+        if webhooks.urls.is_empty() {
+            return Ok(Self(None))
+        }
+
+        let urls = webhooks.urls.iter()
+            .map(|url| Url::parse(url).map_err(|e| ServerError::Config(format!("webhook url {:?} is invalid: {}", url, e))))
+            .collect::<Result<Vec<Url>>>()?;
+        let client = Client::builder()
+            .timeout(Duration::from_secs(webhooks.timeout_secs))
+            .build()
+            .map_err(|e| ServerError::Config(format!("couldn't set up webhooks: {}", e)))?;
+
+        let courier = Courier {
+            client,
+            urls,
+            key: webhooks.secret.as_ref().map(|secret| hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes())),
+            retries: webhooks.retries,
+            metrics: metrics.clone()
+        };
+
+        let (queue, rx) = mpsc::channel(webhooks.queue);
+        tokio::spawn(courier.run(rx).instrument(info_span!(parent: None, "webhooks")));
+
+        Ok(Self(Some(Arc::new(Outgoing { queue, metrics }))))
+    }
+
+    pub fn for_room(&self, room: &str) -> RoomNotifier {
+        RoomNotifier { room: room.to_string(), notifier: self.clone() }
+    }
+}
+
+impl RoomNotifier {
+    pub fn send(&self, event: HookEvent) {
+        let Some(outgoing) = &self.notifier.0 else {
+            return
+        };
+
+        let at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let delivery = Delivery { id: Uuid::new_v4(), room: self.room.clone(), at, event };
+
+        if let Err(TrySendError::Full(delivery)) = outgoing.queue.try_send(delivery) {
+            warn!(event = delivery.event.kind(), "Webhook queue is full, dropping event");
+            outgoing.metrics.webhook("dropped");
+        }
+    }
+}
+
+/// Works through the queue one event at a time, so every url sees events in the order they
+/// happened
+struct Courier {
+    client: Client,
+    urls: Vec<Url>,
+    key: Option<hmac::Key>,
+    retries: u32,
+    metrics: Arc<Metrics>
+}
+
+impl Courier {
+    async fn run(self, mut queue: mpsc::Receiver<Delivery>) {
+        while let Some(delivery) = queue.recv().await {
+            let body = match serde_json::to_vec(&delivery) {
+                Ok(body) => body,
+                Err(e) => {
+                    warn!(error = %e, "Couldn't serialize webhook event");
+                    continue
+                }
+            };
+            let signature = self.key.as_ref().map(|key| format!("sha256={}", hex(hmac::sign(key, &body).as_ref())));
+
+            join_all(self.urls.iter().map(|url| self.deliver(url, &delivery, &body, signature.as_deref()))).await;
+        }
+    }
+
+    async fn deliver(&self, url: &Url, delivery: &Delivery, body: &[u8], signature: Option<&str>) {
+        let event = delivery.event.kind();
+        let mut backoff = RETRY_BACKOFF;
+
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+
+            let mut request = self.client.post(url.clone())
+                .header(CONTENT_TYPE, "application/json")
+                .header("X-Wunos-Event", event)
+                .header("X-Wunos-Delivery", delivery.id.to_string())
+                .body(body.to_vec());
+            if let Some(signature) = signature {
+                request = request.header("X-Wunos-Signature", signature);
+            }
+
+            match request.send().await {
+                Ok(res) if res.status().is_success() => {
+                    debug!(%url, event, attempt, "Webhook delivered");
+                    self.metrics.webhook("delivered");
+                    return
+                },
+                // The receiver understood and said no, asking again won't change its mind
+                Ok(res) if res.status().is_client_error() && res.status() != StatusCode::TOO_MANY_REQUESTS => {
+                    warn!(%url, event, status = %res.status(), "Webhook rejected");
+                    self.metrics.webhook("rejected");
+                    return
+                },
+                Ok(res) => debug!(%url, event, attempt, status = %res.status(), "Webhook delivery failed"),
+                Err(e) => debug!(%url, event, attempt, error = %e, "Webhook delivery failed")
+            }
+        }
+
+        warn!(%url, event, attempts = self.retries + 1, "Giving up on webhook");
+        self.metrics.webhook("failed");
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
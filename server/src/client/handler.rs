@@ -1,222 +1,174 @@
-use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
-use futures_util::{stream::StreamExt, SinkExt};
-use tokio::sync::mpsc;
+use futures_util::{stream::{SplitSink, StreamExt}, SinkExt};
+use tokio::{sync::oneshot, time::Instant};
+use tracing::{debug, info, info_span, warn, Instrument};
 use uuid::Uuid;
+use warp::ws::{Message, WebSocket};
 
-use crate::{game::{card::{Card, Color}, deck::Deck}, state::{msg::{Action, DynMessage}, player::{Player, Role}, state_man::GameState}};
+use crate::{app::context::ServerContext, res::err::{Result, ServerError}, state::{msg::Action, player::Player, room::RoomHandle}};
 
-pub async fn handle_connection(ws: warp::ws::WebSocket, state: Arc<RwLock<GameState>>) {
-    let (mut sender, mut receiver) = ws.split();
+use super::outbox::Outbox;
 
-    let (tx, mut rx) = mpsc::unbounded_channel();
+/// Close code for a connection turned away because the server is at capacity
+const TRY_AGAIN_LATER: u16 = 1013;
+/// Close code sent to everyone when the server shuts down
+const GOING_AWAY: u16 = 1001;
+/// Close code for a client that stopped reading what we send it
+const POLICY_VIOLATION: u16 = 1008;
 
+pub async fn handle_connection(ws: WebSocket, ctx: ServerContext, room: String) {
     let player_id = Uuid::new_v4();
-    let mut player_name: Option<String> = None;
+    let span = info_span!("connection", room = %room, player = %player_id);
 
-    let mut player = Player::new(tx.clone());
+    serve(ws, ctx, room, player_id).instrument(span).await
+}
 
-    if state.read().unwrap().num_players() == 0 {
-        player.set_admin();
-        player.send_msg(&DynMessage::broadcast("You're admin! Please type START to start the game when you'd like")).expect("Message send error");
-    }
+async fn serve(ws: WebSocket, ctx: ServerContext, room: String, player_id: Uuid) {
+    let (mut sender, mut receiver) = ws.split();
+    let open = ctx.track_connection();
+
+    let (outbox, mut queued) = Outbox::new(ctx.outbound, ctx.metrics.clone());
+
+    // The room holds on to the only sender from here on, so our queue closes once we've left
+    let joined = join(&ctx, &room, player_id, outbox).await;
+
+    let room = match joined {
+        Ok(room) => room,
+        Err(e) => {
+            warn!(error = %e, "Turned away");
+            ctx.metrics.error(e.kind());
+            let _ = sender.send(Message::close_with(TRY_AGAIN_LATER, e.to_string())).await;
+            return
+        }
+    };
 
-    state.write().unwrap().players.insert(player_id, player);
+    info!("Joined");
 
+    // Lets the reader know to stop once nothing more can be written
+    let (written, mut writer_done) = oneshot::channel::<()>();
+
+    let reader_ctx = ctx.clone();
     tokio::spawn(async move {
-        while let Some(result) = receiver.next().await {
+        let ctx = reader_ctx;
+
+        // Pongs keep a connection alive, but only game messages stop it from being idle. Being
+        // idle only counts while it's their turn, so nobody's dropped for waiting on everyone else
+        let mut last_heard = Instant::now();
+        let mut last_action = Instant::now();
+
+        loop {
+            let deadline = (last_heard + ctx.pong_timeout).min(last_action + ctx.idle_timeout);
+
+            let next = tokio::select! {
+                next = receiver.next() => next,
+                _ = tokio::time::sleep_until(deadline) => {
+                    if last_heard.elapsed() >= ctx.pong_timeout {
+                        info!(timeout = ?ctx.pong_timeout, "Dropping unresponsive connection");
+                        break;
+                    }
+
+                    match room.turn_since(player_id).await {
+                        Ok(Some(since)) if since.max(last_action).elapsed() >= ctx.idle_timeout => {
+                            info!(timeout = ?ctx.idle_timeout, "Dropping idle connection");
+                            break;
+                        },
+                        Ok(Some(since)) => last_action = since.max(last_action),
+                        Ok(None) => last_action = Instant::now(),
+                        Err(_) => break
+                    }
+                    continue;
+                },
+                _ = ctx.shutting_down() => break,
+                _ = &mut writer_done => break
+            };
+
+            let Some(result) = next else {
+                break
+            };
+
             match result {
                 Ok(msg) => {
+                    last_heard = Instant::now();
+
                     if let Ok(text) = msg.to_str() {
-                        if let Ok(action) = serde_json::from_str::<Action>(text) {
-                            match action {
-                                Action::Message(txt) => {
-                                    // Broadcast message from user to everyone else
-                                    if let Some(name) = &player_name {
-                                        let msg = DynMessage::new_msg(Some(name.to_string()), Action::Message(txt.to_string()));
-
-                                        state.read().unwrap().broadcast_but(msg, &[player_id]).expect("Error broadcasting");
-                                    }
-                                },
-                                Action::Start => { 
-                                    // Double check they are admin, if so start game
-                                    if state.read().unwrap().in_game || state.read().unwrap().players[&player_id].role != Role::Admin {
-                                        continue;
-                                    }
-                                    println!("Game start time");
-
-                                    {
-                                        let mut write_state = state.write().unwrap();
-                                        write_state.in_game = true;
-                                        write_state.turn = player_id;
-                                    }
-
-                                    let num_players = {
-                                        state.read().unwrap().num_players().clone()
-                                    };
-
-                                    let mut hands: Vec<[Card; 7]> = vec![];
-
-                                    {
-                                        let mut state = state.write().unwrap();
-                                        // Ensures we have enough copies of uno for all of our
-                                        // friends to play
-                                        state.deck = Deck::new(num_players);
-                                        for _ in 0..num_players {
-                                            // Draw 7 cards per player
-
-                                            let mut hand = [Card::Wild(Color::None); 7];
-                                            for i in 0..7 {
-                                                hand[i] = state.deck.draw();
-                                            }
-
-                                            hands.push(hand)
-                                        }
-                                    }
-
-                                    for player in state.read().unwrap().players.values() {
-                                        let new_message = DynMessage::new_msg(None, Action::Started(hands.pop().unwrap()));
-                                        player.send_msg(&new_message).expect("Failed to send message");
-                                    }
-
-                                    let curr_card = {
-                                        let mut write_state = state.write().unwrap();
-
-                                        write_state.deck.start_game();
-                                        *write_state.deck.get_facing().unwrap()
-                                    };
-
-                                    state.read().unwrap().broadcast(DynMessage::top_card(curr_card)).expect("Broadcast Message Failure");
-                                },
-                                Action::Win => {
-                                    // Check game exists, then what players hand size is
-                                    if !state.read().unwrap().in_game {
-                                        continue;
-                                    }
-
-                                    let mut state = state.write().unwrap();
-                                    state.broadcast(DynMessage::broadcast(&format!("{} has won!!!", player_name.clone().unwrap()))).unwrap();
-                                    state.broadcast(DynMessage::new_msg(None, Action::Win)).unwrap();
-                                    state.in_game = false;
-                                },
-                                Action::DrawCard => {
-                                    // Draw card for user and send it back as a drawn card
-                                    if !state.read().unwrap().in_game || state.read().unwrap().turn != player_id {
-                                        continue;
-                                    }
-
-                                    let mut state = state.write().unwrap();
-                                    let card = state.deck.draw();
-
-                                    state.send_msg(&player_id, &DynMessage::draw(card)).expect("Draw failed");
-                                },
-                                Action::PlayCard(card) => {
-                                    // Check if card can be played on top of current deck, if so do
-                                    // it and return a success. If not then return a failure
-                                    if !state.read().unwrap().in_game || state.read().unwrap().turn != player_id {
-                                        continue;
-                                    }
-                                    let mut state = state.write().unwrap();
-                                    let mut curr_turn = None;
-                                    let response = match &state.deck.play(card) {
-                                        Some(_) => {
-                                            state.turn = state.after(&player_id).expect("Next player invalid");
-                                            curr_turn = Some(state.turn);
-
-                                            match &state.deck.get_facing().unwrap() {
-                                                Card::Skip(_) => {
-                                                    state.send_msg(&curr_turn.unwrap(), &DynMessage::new_msg(None, Action::Skipped)).unwrap();
-                                                    state.turn = state.after(&curr_turn.unwrap()).expect("Next player invalid");
-                                                    curr_turn = Some(state.turn);
-                                                },
-                                                Card::DrawTwo(_) => {
-                                                    let mut cards = [Card::Wild(Color::None); 2];
-
-                                                    for i in 0..2 {
-                                                        cards[i] = state.deck.draw();
-                                                    }
-
-                                                    state.send_msg(&curr_turn.unwrap(), &DynMessage::new_msg(None, Action::DrawTwo(cards))).unwrap();
-
-                                                    state.turn = state.after(&curr_turn.unwrap()).expect("Next player invalid");
-                                                    curr_turn = Some(state.turn);
-                                                },
-                                                Card::DrawFour(_) => {
-                                                    let mut cards = [Card::Wild(Color::None); 4];
-
-                                                    for i in 0..4 {
-                                                        cards[i] = state.deck.draw();
-                                                    }
-
-                                                    state.send_msg(&curr_turn.unwrap(), &DynMessage::new_msg(None, Action::DrawFour(cards))).unwrap();
-
-                                                    state.turn = state.after(&curr_turn.unwrap()).expect("Next player invalid");
-                                                    curr_turn = Some(state.turn);
-                                                }, 
-                                                Card::Reverse(_) => {
-                                                    state.reverse();
-
-                                                    state.turn = state.after(&player_id).expect("Next player invalid");
-                                                    state.turn = state.after(&player_id).expect("Next player invalid");
-
-                                                    curr_turn = Some(state.turn);
-                                                }
-                                                _ => {}
-                                            };
-
-                                            Action::AcceptPlayCard
-                                        },
-                                        None => Action::DenyPlayCard
-                                    };
-
-                                    let message = DynMessage::new_msg(None, response);
-                                    state.send_msg(&player_id, &message).expect("Send message fail");
-                                    let new_card = DynMessage::new_msg(player_name.clone(), Action::TopCard(state.deck.get_facing().unwrap().clone()));
-                                    state.broadcast(new_card).expect("Broadcast fail");
-                                    if let Some(new_turn) = curr_turn {
-                                        state.send_msg(&new_turn, &DynMessage::new_msg(None, Action::YourTurn)).expect("New turn failed");
-                                    }
-                                },
-                                Action::SetName(name) => {
-                                    println!("Set name: {}", name);
-                                    // Set user's name to `name`
-                                    state.write().unwrap().players.get_mut(&player_id).unwrap().set_name(&name);
-                                    player_name = Some(name);
-                                },
-                                Action::DrawnCard(_) => { unreachable!("User will never initialize a DrawnCard action") },
-                                Action::TopCard(_) => { unreachable!("User will never call TopCard") },
-                                Action::Started(_) => { unreachable!("User will never call Started") },
-                                Action::AcceptPlayCard | Action::DenyPlayCard => { unreachable!("User will never accept or deny played card") },
-                                Action::YourTurn => { unreachable!("Player will never YourTurn the server >:(") },
-                                Action::DrawFour(_) | Action::Skipped | Action::DrawTwo(_) => { unreachable!("Player will never cause a client intended message") }
+                        last_action = Instant::now();
+
+                        match serde_json::from_str::<Action>(text) {
+                            Ok(action) => {
+                                debug!(action = action.kind(), "Received");
+                                ctx.metrics.received(&action);
+                                if room.act(player_id, action).await.is_err() {
+                                    break;
+                                }
+                            },
+                            Err(e) => {
+                                debug!(error = %e, "Ignoring a message that isn't an action");
+                                ctx.metrics.error("bad_message");
                             }
                         }
                     }
-                }, 
+                },
                 Err(e) => {
-                    eprintln!("Message handling error: {}", e);
+                    warn!(error = %e, "Socket error");
+                    ctx.metrics.error("socket");
                     break;
                 }
             }
         }
 
         // Remove connection on disconect
-        let mut state = state.write().unwrap();
-        state.players.remove(&player_id);
-        if let Some(name) = player_name {
-            state.broadcast(DynMessage::broadcast(&format!("{} has left the game", name))).expect("Message send error");
-        }
-
-        if state.turn.clone() == player_id {
-            state.turn = state.after(&player_id).expect("Next player invalid");
-        }
-    });
+        let _ = room.leave(player_id).await;
+        info!("Left");
+    }.in_current_span());
 
     tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            if sender.send(msg).await.is_err() {
-                break;
+        let _written = written;
+        let _open = open;
+        let mut heartbeat = tokio::time::interval_at(Instant::now() + ctx.ping_interval, ctx.ping_interval);
+
+        loop {
+            tokio::select! {
+                _ = heartbeat.tick() => if !deliver(&mut sender, Message::ping(Vec::new()), ctx.pong_timeout).await {
+                    break;
+                },
+                msg = queued.recv() => match msg {
+                    Some(msg) => if !deliver(&mut sender, msg, ctx.pong_timeout).await {
+                        break;
+                    },
+                    None => {
+                        if queued.was_evicted() {
+                            warn!("Evicted for falling behind");
+                            let _ = sender.send(Message::close_with(POLICY_VIOLATION, "Too far behind")).await;
+                        } else if queued.was_kicked() {
+                            let _ = sender.send(Message::close_with(POLICY_VIOLATION, "Removed by the server operator")).await;
+                        }
+                        break;
+                    }
+                },
+                _ = ctx.shutting_down() => {
+                    let _ = sender.send(Message::close_with(GOING_AWAY, "Server is shutting down, rejoin with your seat token once it's back")).await;
+                    break;
+                }
             }
         }
-    });
+    }.in_current_span());
+}
+
+/// Writes a single message, giving up if the socket won't take it within `timeout`
+async fn deliver(sender: &mut SplitSink<WebSocket, Message>, msg: Message, timeout: Duration) -> bool {
+    matches!(tokio::time::timeout(timeout, sender.send(msg)).await, Ok(Ok(())))
+}
+
+/// Seats the player in the room called `name`. A room can close between us finding it and our
+/// join reaching it, in which case we just open a fresh one
+async fn join(ctx: &ServerContext, name: &str, id: Uuid, outbox: Outbox) -> Result<RoomHandle> {
+    loop {
+        let room = ctx.lobby.write().unwrap().room(name)?;
+
+        match room.join(id, Player::new(outbox.clone())).await {
+            Err(ServerError::RoomClosed) => continue,
+            res => return res.map(|()| room)
+        }
+    }
 }
@@ -1 +1,3 @@
 pub mod handler;
+pub mod outbox;
+pub mod test;
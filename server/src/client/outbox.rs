@@ -0,0 +1,233 @@
+use std::{collections::VecDeque, sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Arc, Mutex}};
+
+use serde::Serialize;
+use tokio::sync::Notify;
+use tracing::warn;
+use warp::ws::Message;
+
+use crate::{app::metrics::Metrics, config::settings::Outbound, res::err::{Result, ServerError}, state::msg::{Action, DynMessage}};
+
+/// How a queued message is treated once a client starts falling behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Player chat, the first thing to go when the queue backs up
+    Chat,
+    /// Only the latest one matters, a newer one replaces whatever is still queued
+    TopCard,
+    ColorChosen,
+    /// Everything the game can't continue without
+    Essential
+}
+
+impl Kind {
+    fn of(msg: &DynMessage) -> Self {
+        match (&msg.sender, &msg.action) {
+            (Some(_), Action::Message(_)) => Kind::Chat,
+            (_, Action::TopCard(_)) => Kind::TopCard,
+            (_, Action::ColorChosen(_)) => Kind::ColorChosen,
+            _ => Kind::Essential
+        }
+    }
+}
+
+/// Counters shared by every outbox on the server, kept in the server's [`Metrics`]
+#[derive(Debug, Default)]
+pub struct QueueStats {
+    queued: AtomicUsize,
+    deepest: AtomicUsize,
+    dropped: AtomicU64,
+    coalesced: AtomicU64,
+    evicted: AtomicU64
+}
+
+/// A point in time copy of [`QueueStats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct QueueSnapshot {
+    /// Messages waiting across every connection right now
+    pub queued: usize,
+    /// The most any one connection has ever had waiting
+    pub deepest: usize,
+    pub dropped: u64,
+    pub coalesced: u64,
+    pub evicted: u64
+}
+
+impl QueueStats {
+    pub fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            queued: self.queued.load(Ordering::Relaxed),
+            deepest: self.deepest.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            evicted: self.evicted.load(Ordering::Relaxed)
+        }
+    }
+}
+
+struct Shared {
+    queue: Mutex<VecDeque<(Kind, Message)>>,
+    ready: Notify,
+    closed: AtomicBool,
+    evicted: AtomicBool,
+    kicked: AtomicBool,
+    limits: Outbound,
+    metrics: Arc<Metrics>
+}
+
+impl Shared {
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.ready.notify_one();
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        let left = self.queue.get_mut().map(|queue| queue.len()).unwrap_or(0);
+        self.metrics.queues.queued.fetch_sub(left, Ordering::Relaxed);
+    }
+}
+
+/// Closes the outbox once the last sender is gone
+struct Senders(Arc<Shared>);
+
+impl Drop for Senders {
+    fn drop(&mut self) {
+        self.0.close()
+    }
+}
+
+/// The sending half of a connection's bounded message queue. Chat is dropped once the queue
+/// backs up past `chat_limit`, top cards and colors are coalesced to the latest, and a client
+/// that lets `capacity` messages pile up is disconnected
+#[derive(Clone)]
+pub struct Outbox {
+    shared: Arc<Shared>,
+    _senders: Arc<Senders>
+}
+
+/// The receiving half, read by the task writing to the socket
+pub struct OutboxReader {
+    shared: Arc<Shared>
+}
+
+impl std::fmt::Debug for Outbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Outbox").field("queued", &self.len()).finish()
+    }
+}
+
+impl Outbox {
+    pub fn new(limits: Outbound, metrics: Arc<Metrics>) -> (Outbox, OutboxReader) {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(VecDeque::new()),
+            ready: Notify::new(),
+            closed: AtomicBool::new(false),
+            evicted: AtomicBool::new(false),
+            kicked: AtomicBool::new(false),
+            limits,
+            metrics
+        });
+
+        let outbox = Outbox { shared: shared.clone(), _senders: Arc::new(Senders(shared.clone())) };
+        (outbox, OutboxReader { shared })
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.queue.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Closes the connection once whatever is already queued has been sent
+    pub fn kick(&self) {
+        self.shared.kicked.store(true, Ordering::Release);
+        self.shared.close();
+    }
+
+    pub fn send(&self, msg: &DynMessage) -> Result<()> {
+        let shared = &self.shared;
+        if shared.closed.load(Ordering::Acquire) {
+            return Err(ServerError::Disconnected)
+        }
+
+        let kind = Kind::of(msg);
+        let mut queue = shared.queue.lock().unwrap();
+
+        match kind {
+            Kind::Chat if queue.len() >= shared.limits.chat_limit => {
+                shared.metrics.queues.dropped.fetch_add(1, Ordering::Relaxed);
+                return Ok(())
+            },
+            Kind::TopCard | Kind::ColorChosen => {
+                // Pulled out rather than overwritten so the newer one keeps its place in line
+                if let Some(pos) = queue.iter().position(|(queued, _)| *queued == kind) {
+                    queue.remove(pos);
+                    shared.metrics.queues.queued.fetch_sub(1, Ordering::Relaxed);
+                    shared.metrics.queues.coalesced.fetch_add(1, Ordering::Relaxed);
+                }
+            },
+            _ => {}
+        }
+
+        if queue.len() >= shared.limits.capacity {
+            shared.metrics.queues.queued.fetch_sub(queue.len(), Ordering::Relaxed);
+            shared.metrics.queues.evicted.fetch_add(1, Ordering::Relaxed);
+            queue.clear();
+            drop(queue);
+
+            warn!(capacity = shared.limits.capacity, "Disconnecting a client that fell too far behind");
+            shared.evicted.store(true, Ordering::Release);
+            shared.close();
+            return Err(ServerError::SlowClient)
+        }
+
+        queue.push_back((kind, msg.clone().into()));
+        shared.metrics.sent(&msg.action);
+        shared.metrics.queues.queued.fetch_add(1, Ordering::Relaxed);
+        shared.metrics.queues.deepest.fetch_max(queue.len(), Ordering::Relaxed);
+        drop(queue);
+
+        shared.ready.notify_one();
+        Ok(())
+    }
+}
+
+impl OutboxReader {
+    /// Waits for the next message. `None` once every sender is gone or the client was evicted
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            if let Some(msg) = self.try_recv() {
+                return Some(msg)
+            }
+            if self.shared.closed.load(Ordering::Acquire) {
+                return None
+            }
+
+            self.shared.ready.notified().await;
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Option<Message> {
+        if self.was_evicted() {
+            return None
+        }
+
+        let (_, msg) = self.shared.queue.lock().unwrap().pop_front()?;
+        self.shared.metrics.queues.queued.fetch_sub(1, Ordering::Relaxed);
+
+        Some(msg)
+    }
+
+    /// Whether the queue was closed because the client couldn't keep up
+    pub fn was_evicted(&self) -> bool {
+        self.shared.evicted.load(Ordering::Acquire)
+    }
+
+    /// Whether the queue was closed because the client was kicked
+    pub fn was_kicked(&self) -> bool {
+        self.shared.kicked.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{app::metrics::Metrics, client::outbox::{Outbox, OutboxReader}, config::settings::Outbound, game::card::{Card, Color}, res::err::ServerError, state::msg::{Action, DynMessage}};
+
+    fn outbox(capacity: usize, chat_limit: usize) -> (Outbox, OutboxReader, Arc<Metrics>) {
+        let stats = Arc::new(Metrics::default());
+        let (outbox, reader) = Outbox::new(Outbound { capacity, chat_limit }, stats.clone());
+
+        (outbox, reader, stats)
+    }
+
+    fn chat(text: &str) -> DynMessage {
+        DynMessage::new_msg(Some("bob".into()), Action::Message(text.into()))
+    }
+
+    fn drain(reader: &mut OutboxReader) -> Vec<Action> {
+        let mut actions = vec![];
+        while let Some(msg) = reader.try_recv() {
+            actions.push(serde_json::from_str::<DynMessage>(msg.to_str().unwrap()).unwrap().action);
+        }
+
+        actions
+    }
+
+    #[test]
+    fn chat_is_dropped_first() {
+        let (outbox, mut reader, stats) = outbox(8, 2);
+
+        for i in 0..4 {
+            outbox.send(&chat(&i.to_string())).unwrap();
+        }
+        outbox.send(&DynMessage::new_msg(None, Action::YourTurn)).unwrap();
+
+        assert_eq!(3, outbox.len());
+        assert_eq!(2, stats.queues.snapshot().dropped);
+        assert_eq!(3, stats.queues.snapshot().queued);
+
+        let actions = drain(&mut reader);
+        assert!(matches!(actions.as_slice(), [Action::Message(a), Action::Message(b), Action::YourTurn] if a == "0" && b == "1"));
+        assert_eq!(0, stats.queues.snapshot().queued);
+        assert_eq!(3, stats.queues.snapshot().deepest);
+    }
+
+    #[test]
+    fn top_cards_are_coalesced() {
+        let (outbox, mut reader, stats) = outbox(8, 8);
+
+        outbox.send(&DynMessage::top_card(Card::Normal(Color::Red, 1))).unwrap();
+        outbox.send(&DynMessage::new_msg(None, Action::YourTurn)).unwrap();
+        outbox.send(&DynMessage::top_card(Card::Normal(Color::Blue, 2))).unwrap();
+
+        // Only the latest top card is left, after everything that came before it
+        let actions = drain(&mut reader);
+        assert!(matches!(actions.as_slice(), [Action::YourTurn, Action::TopCard(Card::Normal(Color::Blue, 2))]));
+        assert_eq!(1, stats.queues.snapshot().coalesced);
+    }
+
+    #[tokio::test]
+    async fn slow_clients_are_evicted() {
+        let (outbox, mut reader, stats) = outbox(3, 1);
+
+        for _ in 0..3 {
+            outbox.send(&DynMessage::new_msg(None, Action::YourTurn)).unwrap();
+        }
+        assert!(matches!(outbox.send(&DynMessage::new_msg(None, Action::Skipped)), Err(ServerError::SlowClient)));
+        assert!(matches!(outbox.send(&DynMessage::new_msg(None, Action::Skipped)), Err(ServerError::Disconnected)));
+
+        assert_eq!(None, reader.recv().await);
+        assert!(reader.was_evicted());
+
+        let snapshot = stats.queues.snapshot();
+        assert_eq!(1, snapshot.evicted);
+        assert_eq!(0, snapshot.queued);
+    }
+
+    #[tokio::test]
+    async fn reader_ends_with_the_last_sender() {
+        let (outbox, mut reader, stats) = outbox(8, 8);
+        let copy = outbox.clone();
+
+        outbox.send(&DynMessage::new_msg(None, Action::YourTurn)).unwrap();
+        drop(outbox);
+        drop(copy);
+
+        // Whatever was queued still goes out first
+        assert!(reader.recv().await.is_some());
+        assert_eq!(None, reader.recv().await);
+        assert!(!reader.was_evicted());
+
+        assert_eq!(0, stats.queues.snapshot().queued);
+
+        // Messages nobody will ever read stop counting once the connection is gone
+        let (outbox, reader, stats) = self::outbox(8, 8);
+        outbox.send(&DynMessage::new_msg(None, Action::YourTurn)).unwrap();
+        assert_eq!(1, stats.queues.snapshot().queued);
+        drop(reader);
+        drop(outbox);
+        assert_eq!(0, stats.queues.snapshot().queued);
+    }
+}
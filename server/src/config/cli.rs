@@ -0,0 +1,93 @@
+use std::{net::IpAddr, path::PathBuf};
+
+use clap::Parser;
+
+/// Command line flags, each of which can also be set through a `WUNOS_*` environment variable.
+/// Anything passed here wins over the config file
+#[derive(Parser, Debug, Default)]
+#[command(name = "wunos", about = "Uno over WebSockets")]
+pub struct Cli {
+    /// Path to a TOML config file
+    #[arg(short, long, env = "WUNOS_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on
+    #[arg(long, env = "WUNOS_BIND")]
+    pub bind: Option<IpAddr>,
+
+    /// Port to listen on
+    #[arg(short, long, env = "WUNOS_PORT")]
+    pub port: Option<u16>,
+
+    /// How many rooms can be open at once
+    #[arg(long, env = "WUNOS_MAX_ROOMS")]
+    pub max_rooms: Option<usize>,
+
+    /// How many players can sit in a single room
+    #[arg(long, env = "WUNOS_MAX_PLAYERS")]
+    pub max_players: Option<usize>,
+
+    /// Seconds a player can sit on their turn without doing anything before they're dropped
+    #[arg(long, env = "WUNOS_IDLE_TIMEOUT")]
+    pub idle_timeout: Option<u64>,
+
+    /// Seconds between pings to every client
+    #[arg(long, env = "WUNOS_PING_INTERVAL")]
+    pub ping_interval: Option<u64>,
+
+    /// Seconds without hearing from a client before it's considered dead
+    #[arg(long, env = "WUNOS_PONG_TIMEOUT")]
+    pub pong_timeout: Option<u64>,
+
+    /// How many messages can wait for a client before it's disconnected
+    #[arg(long, env = "WUNOS_QUEUE_CAPACITY")]
+    pub queue_capacity: Option<usize>,
+
+    /// How many messages can wait for a client before chat to it is dropped
+    #[arg(long, env = "WUNOS_CHAT_QUEUE_LIMIT")]
+    pub chat_queue_limit: Option<usize>,
+
+    /// Where the server keeps anything it writes to disk
+    #[arg(long, env = "WUNOS_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    /// Whether action cards match other actions of any color
+    #[arg(long, env = "WUNOS_MATCH_ACTIONS")]
+    pub match_actions: Option<bool>,
+
+    /// How many players share a single copy of the deck
+    #[arg(long, env = "WUNOS_PLAYERS_PER_DECK")]
+    pub players_per_deck: Option<usize>,
+
+    /// PEM certificate chain to serve wss:// with
+    #[arg(long, env = "WUNOS_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key matching --tls-cert
+    #[arg(long, env = "WUNOS_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Allow plain ws:// on addresses other than localhost
+    #[arg(long, env = "WUNOS_ALLOW_PLAINTEXT")]
+    pub allow_plaintext: bool,
+
+    /// What to log, e.g. `debug` or `info,server::state=trace`
+    #[arg(long, env = "WUNOS_LOG_LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Log JSON lines instead of text
+    #[arg(long, env = "WUNOS_LOG_JSON")]
+    pub log_json: bool,
+
+    /// Url to POST game events to, can be given more than once
+    #[arg(long = "webhook", env = "WUNOS_WEBHOOKS", value_delimiter = ',')]
+    pub webhooks: Vec<String>,
+
+    /// Secret to sign webhook bodies with
+    #[arg(long, env = "WUNOS_WEBHOOK_SECRET")]
+    pub webhook_secret: Option<String>,
+
+    /// Token for the admin dashboard at /admin, which is only served when this is set
+    #[arg(long, env = "WUNOS_ADMIN_TOKEN")]
+    pub admin_token: Option<String>
+}
@@ -0,0 +1,339 @@
+use std::{fs, net::{IpAddr, Ipv4Addr, SocketAddr}, path::{Path, PathBuf}, time::Duration};
+
+use clap::Parser;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::EnvFilter;
+
+use crate::{game::rules::Rules, res::err::{Result, ServerError}};
+
+use super::cli::Cli;
+
+/// Picked up from the working directory when no config file is given explicitly
+pub const DEFAULT_CONFIG: &str = "wunos.toml";
+
+/// Anything shorter is too easy to guess
+const MIN_ADMIN_TOKEN: usize = 16;
+
+/// Everything the server can be tuned with. Layered from lowest to highest priority as built in
+/// defaults, the config file, `WUNOS_*` environment variables and finally command line flags
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: IpAddr,
+    pub port: u16,
+    pub max_rooms: usize,
+    pub max_players: usize,
+    /// Rules every new room starts out with
+    pub rules: Rules,
+    pub timeouts: Timeouts,
+    pub outbound: Outbound,
+    pub data_dir: PathBuf,
+    pub tls: Tls,
+    /// Lets the server listen on a non-loopback address without TLS
+    pub allow_plaintext: bool,
+    pub log: Log,
+    pub webhooks: Webhooks,
+    pub admin: Admin
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// Seconds a player can sit on their turn without doing anything before they're dropped.
+    /// Waiting on everyone else never counts, even though only pongs come in meanwhile
+    pub idle_secs: u64,
+    /// Seconds between pings to every client
+    pub ping_secs: u64,
+    /// Seconds a connection can go without answering a ping, or sending anything else, before
+    /// it's considered dead
+    pub pong_secs: u64
+}
+
+/// Limits on how many messages can wait to be sent to a single client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Outbound {
+    /// A client with this many messages waiting is disconnected
+    pub capacity: usize,
+    /// Chat to a client with this many messages waiting is dropped
+    pub chat_limit: usize
+}
+
+/// Certificate and private key to serve `wss://` with, both PEM encoded. Plain `ws://` is served
+/// when neither is set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tls {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Log {
+    /// What gets logged, anything from a plain `info` to per module filters like
+    /// `info,server::state=debug`
+    pub level: String,
+    /// One JSON object per line instead of human readable text
+    pub json: bool
+}
+
+/// Where game lifecycle events are POSTed as JSON. Nothing is sent when `urls` is empty
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Webhooks {
+    pub urls: Vec<String>,
+    /// Signs every body with HMAC-SHA256, sent in the `X-Wunos-Signature` header
+    pub secret: Option<String>,
+    /// Events waiting to go out beyond this many are dropped
+    pub queue: usize,
+    /// How many more times a failed delivery is tried before it's given up on
+    pub retries: u32,
+    /// Seconds to wait on a url before counting the attempt as failed
+    pub timeout_secs: u64
+}
+
+/// The dashboard at `/admin`, only served once a token is set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Admin {
+    /// Sent by the dashboard as `Authorization: Bearer <token>`
+    pub token: Option<String>
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8080,
+            max_rooms: 16,
+            max_players: 10,
+            rules: Rules::default(),
+            timeouts: Timeouts::default(),
+            outbound: Outbound::default(),
+            data_dir: PathBuf::from("data"),
+            tls: Tls::default(),
+            allow_plaintext: false,
+            log: Log::default(),
+            webhooks: Webhooks::default(),
+            admin: Admin::default()
+        }
+    }
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self { urls: vec![], secret: None, queue: 256, retries: 3, timeout_secs: 5 }
+    }
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self { level: "info".into(), json: false }
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self { idle_secs: 600, ping_secs: 15, pong_secs: 45 }
+    }
+}
+
+impl Default for Outbound {
+    fn default() -> Self {
+        Self { capacity: 256, chat_limit: 64 }
+    }
+}
+
+impl Config {
+    /// Builds the config from the process's arguments and environment
+    pub fn load() -> Result<Self> {
+        Self::from_cli(Cli::parse())
+    }
+
+    pub fn from_cli(cli: Cli) -> Result<Self> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG).exists() => Self::from_file(DEFAULT_CONFIG)?,
+            None => Self::default()
+        };
+
+        config.apply(cli);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| ServerError::Config(format!("couldn't read {}: {}", path.display(), e)))?;
+
+        toml::from_str(&text)
+            .map_err(|e| ServerError::Config(format!("{} is not a valid config: {}", path.display(), e)))
+    }
+
+    fn apply(&mut self, cli: Cli) {
+        if let Some(bind) = cli.bind {
+            self.bind = bind;
+        }
+        if let Some(port) = cli.port {
+            self.port = port;
+        }
+        if let Some(max_rooms) = cli.max_rooms {
+            self.max_rooms = max_rooms;
+        }
+        if let Some(max_players) = cli.max_players {
+            self.max_players = max_players;
+        }
+        if let Some(idle_secs) = cli.idle_timeout {
+            self.timeouts.idle_secs = idle_secs;
+        }
+        if let Some(ping_secs) = cli.ping_interval {
+            self.timeouts.ping_secs = ping_secs;
+        }
+        if let Some(pong_secs) = cli.pong_timeout {
+            self.timeouts.pong_secs = pong_secs;
+        }
+        if let Some(capacity) = cli.queue_capacity {
+            self.outbound.capacity = capacity;
+        }
+        if let Some(chat_limit) = cli.chat_queue_limit {
+            self.outbound.chat_limit = chat_limit;
+        }
+        if let Some(data_dir) = cli.data_dir {
+            self.data_dir = data_dir;
+        }
+        if let Some(match_actions) = cli.match_actions {
+            self.rules.match_actions = match_actions;
+        }
+        if let Some(players_per_deck) = cli.players_per_deck {
+            self.rules.players_per_deck = players_per_deck;
+        }
+        if let Some(cert) = cli.tls_cert {
+            self.tls.cert = Some(cert);
+        }
+        if let Some(key) = cli.tls_key {
+            self.tls.key = Some(key);
+        }
+        if cli.allow_plaintext {
+            self.allow_plaintext = true;
+        }
+        if let Some(level) = cli.log_level {
+            self.log.level = level;
+        }
+        if cli.log_json {
+            self.log.json = true;
+        }
+        if !cli.webhooks.is_empty() {
+            self.webhooks.urls = cli.webhooks;
+        }
+        if let Some(secret) = cli.webhook_secret {
+            self.webhooks.secret = Some(secret);
+        }
+        if let Some(token) = cli.admin_token {
+            self.admin.token = Some(token);
+        }
+    }
+
+    /// Makes sure every setting is usable, creating the data directory if it doesn't exist yet
+    pub fn validate(&self) -> Result<()> {
+        if self.max_rooms == 0 {
+            return Err(ServerError::Config("max_rooms must be at least 1".into()))
+        }
+        if self.max_players < 2 {
+            return Err(ServerError::Config(format!("max_players must be at least 2, got {}", self.max_players)))
+        }
+        if self.rules.players_per_deck == 0 {
+            return Err(ServerError::Config("rules.players_per_deck must be at least 1".into()))
+        }
+        if self.timeouts.idle_secs == 0 {
+            return Err(ServerError::Config("timeouts.idle_secs must be at least 1".into()))
+        }
+        if self.timeouts.ping_secs == 0 || self.timeouts.ping_secs >= self.timeouts.pong_secs {
+            return Err(ServerError::Config(format!(
+                "timeouts.ping_secs must be at least 1 and less than timeouts.pong_secs ({}), got {}",
+                self.timeouts.pong_secs, self.timeouts.ping_secs
+            )))
+        }
+
+        if self.outbound.chat_limit == 0 || self.outbound.chat_limit > self.outbound.capacity {
+            return Err(ServerError::Config(format!(
+                "outbound.chat_limit must be between 1 and outbound.capacity ({}), got {}",
+                self.outbound.capacity, self.outbound.chat_limit
+            )))
+        }
+
+        match (&self.tls.cert, &self.tls.key) {
+            (Some(cert), Some(key)) => {
+                for path in [cert, key] {
+                    if !path.is_file() {
+                        return Err(ServerError::Config(format!("TLS file {} does not exist", path.display())))
+                    }
+                }
+            },
+            (Some(_), None) | (None, Some(_)) => {
+                return Err(ServerError::Config("tls.cert and tls.key have to be set together".into()))
+            },
+            (None, None) if !self.bind.is_loopback() && !self.allow_plaintext => {
+                return Err(ServerError::Config(format!(
+                    "refusing to serve plaintext ws:// on {}, set tls.cert and tls.key or allow_plaintext",
+                    self.bind
+                )))
+            },
+            (None, None) => {}
+        }
+
+        if let Err(e) = EnvFilter::try_new(&self.log.level) {
+            return Err(ServerError::Config(format!("log.level {:?} is not a valid filter: {}", self.log.level, e)))
+        }
+
+        for url in self.webhooks.urls.iter() {
+            match Url::parse(url) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {},
+                _ => return Err(ServerError::Config(format!("webhooks.urls has to be http:// or https:// urls, got {:?}", url)))
+            }
+        }
+        if self.webhooks.queue == 0 {
+            return Err(ServerError::Config("webhooks.queue must be at least 1".into()))
+        }
+        if self.webhooks.timeout_secs == 0 {
+            return Err(ServerError::Config("webhooks.timeout_secs must be at least 1".into()))
+        }
+
+        if self.admin.token.as_ref().is_some_and(|token| token.len() < MIN_ADMIN_TOKEN) {
+            return Err(ServerError::Config(format!("admin.token must be at least {} characters", MIN_ADMIN_TOKEN)))
+        }
+
+        if self.data_dir.exists() && !self.data_dir.is_dir() {
+            return Err(ServerError::Config(format!("data_dir {} is not a directory", self.data_dir.display())))
+        }
+        fs::create_dir_all(&self.data_dir)
+            .map_err(|e| ServerError::Config(format!("couldn't create data_dir {}: {}", self.data_dir.display(), e)))
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+
+    /// The certificate and key paths, if the server should speak TLS
+    pub fn tls(&self) -> Option<(&Path, &Path)> {
+        match (&self.tls.cert, &self.tls.key) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            _ => None
+        }
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.timeouts.idle_secs)
+    }
+
+    pub fn ping_interval(&self) -> Duration {
+        Duration::from_secs(self.timeouts.ping_secs)
+    }
+
+    pub fn pong_timeout(&self) -> Duration {
+        Duration::from_secs(self.timeouts.pong_secs)
+    }
+}
@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, net::{IpAddr, Ipv4Addr}, path::PathBuf};
+
+    use crate::{config::{cli::Cli, settings::{Config, Outbound}}, res::err::ServerError};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("wunos-config-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn partial_file_keeps_defaults() {
+        let config: Config = toml::from_str("port = 9000\n[rules]\nmatch_actions = false").unwrap();
+
+        assert_eq!(9000, config.port);
+        assert!(!config.rules.match_actions);
+        assert_eq!(1, config.rules.players_per_deck);
+        assert_eq!(Config::default().max_players, config.max_players);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("prot = 9000").is_err());
+        assert!(toml::from_str::<Config>("[timeouts]\nidle = 5").is_err());
+    }
+
+    #[test]
+    fn flags_override_file() {
+        let dir = scratch_dir("layers");
+        let path = dir.join("wunos.toml");
+        fs::write(&path, format!("port = 9000\nmax_rooms = 3\ndata_dir = {:?}", dir.join("data"))).unwrap();
+
+        let cli = Cli {
+            config: Some(path),
+            port: Some(9100),
+            bind: Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            allow_plaintext: true,
+            ..Cli::default()
+        };
+        let config = Config::from_cli(cli).unwrap();
+
+        assert_eq!(9100, config.port);
+        assert_eq!(3, config.max_rooms);
+        assert_eq!(IpAddr::V4(Ipv4Addr::UNSPECIFIED), config.bind);
+        assert!(dir.join("data").is_dir());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_settings_are_explained() {
+        let dir = scratch_dir("invalid");
+        let config = Config { max_players: 1, data_dir: dir.clone(), ..Config::default() };
+
+        match config.validate() {
+            Err(ServerError::Config(msg)) => assert!(msg.contains("max_players")),
+            other => panic!("Expected a config error, got {:?}", other)
+        }
+
+        let chatty = Config { outbound: Outbound { capacity: 4, chat_limit: 8 }, data_dir: dir.clone(), ..Config::default() };
+        assert!(matches!(chatty.validate(), Err(ServerError::Config(_))));
+
+        let noisy = Cli { log_level: Some("server=loud".into()), data_dir: Some(dir.clone()), ..Cli::default() };
+        match Config::from_cli(noisy) {
+            Err(ServerError::Config(msg)) => assert!(msg.contains("log.level")),
+            other => panic!("Expected a config error, got {:?}", other)
+        }
+
+        let hooked = Cli { webhooks: vec!["ftp://example.com".into()], data_dir: Some(dir.clone()), ..Cli::default() };
+        match Config::from_cli(hooked) {
+            Err(ServerError::Config(msg)) => assert!(msg.contains("webhooks.urls")),
+            other => panic!("Expected a config error, got {:?}", other)
+        }
+
+        let guessable = Cli { admin_token: Some("letmein".into()), data_dir: Some(dir.clone()), ..Cli::default() };
+        match Config::from_cli(guessable) {
+            Err(ServerError::Config(msg)) => assert!(msg.contains("admin.token")),
+            other => panic!("Expected a config error, got {:?}", other)
+        }
+
+        let missing = Cli { config: Some(dir.join("nope.toml")), ..Cli::default() };
+        assert!(matches!(Config::from_cli(missing), Err(ServerError::Config(_))));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tls_needs_both_files() {
+        let dir = scratch_dir("tls");
+        let cert = dir.join("cert.pem");
+        let key = dir.join("key.pem");
+        fs::write(&cert, "").unwrap();
+
+        let half = Cli { tls_cert: Some(cert.clone()), data_dir: Some(dir.clone()), ..Cli::default() };
+        assert!(matches!(Config::from_cli(half), Err(ServerError::Config(_))));
+
+        let missing = Cli { tls_cert: Some(cert.clone()), tls_key: Some(key.clone()), data_dir: Some(dir.clone()), ..Cli::default() };
+        assert!(matches!(Config::from_cli(missing), Err(ServerError::Config(_))));
+
+        fs::write(&key, "").unwrap();
+        let both = Cli { tls_cert: Some(cert.clone()), tls_key: Some(key.clone()), data_dir: Some(dir.clone()), ..Cli::default() };
+        let config = Config::from_cli(both).unwrap();
+        assert_eq!(Some((cert.as_path(), key.as_path())), config.tls());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn plaintext_stays_on_localhost() {
+        let dir = scratch_dir("plaintext");
+        let public = Config { bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED), data_dir: dir.clone(), ..Config::default() };
+
+        assert!(matches!(public.validate(), Err(ServerError::Config(_))));
+        assert!(Config { allow_plaintext: true, ..public }.validate().is_ok());
+        assert!(Config { data_dir: dir.clone(), ..Config::default() }.validate().is_ok());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,12 +2,28 @@ use std::{fmt::Display, slice::Iter};
 
 use serde::{Deserialize, Serialize};
 
+use super::rules::Rules;
+
 const SKIP: u8 = 10;
 const REVERSE: u8 = 11;
 const PLUS_TWO: u8 = 12;
 const PLUS_FOUR: u8 = 13;
+const PLUS_ONE: u8 = 14;
+const FLIP: u8 = 15;
+const WILD_PLUS_TWO: u8 = 16;
+const PLUS_FIVE: u8 = 17;
+const SKIP_EVERYONE: u8 = 18;
+const WILD_DRAW_COLOR: u8 = 19;
+const WILD_PLUS_SIX: u8 = 20;
+const WILD_PLUS_TEN: u8 = 21;
+const WILD_REVERSE_PLUS_FOUR: u8 = 22;
+const DISCARD_ALL: u8 = 23;
+const WILD_COLOR_ROULETTE: u8 = 24;
+const WILD_SWAP_HANDS: u8 = 25;
+const WILD_SHUFFLE_HANDS: u8 = 26;
+const WILD_CUSTOMIZABLE: u8 = 27;
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Card {
     Normal(Color, u8),
     DrawTwo(Color),
@@ -16,9 +32,67 @@ pub enum Card {
 
     Wild(Color),
     DrawFour(Color),
+
+    // The light side of UNO Flip
+    DrawOne(Color),
+    /// Turns every card on the table over to its other side
+    Flip(Color),
+    WildDrawTwo(Color),
+
+    // The dark side of UNO Flip
+    DrawFive(Color),
+    /// Skips everyone else, so whoever played it goes again
+    SkipEveryone(Color),
+    /// The next player draws until they get a card of the color it was played as
+    WildDrawColor(Color),
+
+    // No Mercy
+    WildDrawSix(Color),
+    WildDrawTen(Color),
+    /// Turns play around, then the next player that way draws four
+    WildReverseDrawFour(Color),
+    /// Goes down along with every other card of its color in the player's hand
+    DiscardAll(Color),
+    /// The next player picks a color, and draws until they get a card in it
+    WildColorRoulette(Color),
+
+    // Special wilds
+    /// Whoever played it trades hands with someone of their choosing
+    WildSwapHands(Color),
+    /// Everyone's hands are gathered up and dealt back out
+    WildShuffleHands(Color),
+    /// A blank wild, which does whatever the room's rules say
+    WildCustomizable(Color)
+}
+
+/// Which side of a double sided UNO Flip card is showing. Everything else is always light
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Side {
+    #[default]
+    Light,
+    Dark
+}
+
+impl Side {
+    pub fn other(&self) -> Self {
+        match self {
+            Side::Light => Side::Dark,
+            Side::Dark => Side::Light
+        }
+    }
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Side::Light => "light",
+            Side::Dark => "dark"
+        })
+    }
 }
 
 impl Display for Card {
+    #[allow(clippy::useless_format)]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match self {
             Card::Normal(color, number) => format!("{} {}", color, number),
@@ -38,7 +112,21 @@ impl Display for Card {
                 } else {
                     format!("{} Wild", color)
                 }
-            }
+            },
+            Card::DrawOne(color) => format!("{} Plus One", color),
+            Card::Flip(color) => format!("{} Flip", color),
+            Card::WildDrawTwo(_) => "Wild Plus Two".to_string(),
+            Card::DrawFive(color) => format!("{} Plus Five", color),
+            Card::SkipEveryone(color) => format!("{} Skip Everyone", color),
+            Card::WildDrawColor(_) => "Wild Draw Color".to_string(),
+            Card::WildDrawSix(_) => "Wild Plus Six".to_string(),
+            Card::WildDrawTen(_) => "Wild Plus Ten".to_string(),
+            Card::WildReverseDrawFour(_) => "Wild Reverse Plus Four".to_string(),
+            Card::DiscardAll(color) => format!("{} Discard All", color),
+            Card::WildColorRoulette(_) => "Wild Color Roulette".to_string(),
+            Card::WildSwapHands(_) => "Wild Swap Hands".to_string(),
+            Card::WildShuffleHands(_) => "Wild Shuffle Hands".to_string(),
+            Card::WildCustomizable(_) => "Wild Customizable".to_string()
         })
     }
 }
@@ -51,7 +139,21 @@ impl Card {
                 | Card::Skip(color) 
                 | Card::Wild(color) 
                 | Card::DrawFour(color) 
-                | Card::Reverse(color) => {
+                | Card::Reverse(color)
+                | Card::DrawOne(color)
+                | Card::Flip(color)
+                | Card::WildDrawTwo(color)
+                | Card::DrawFive(color)
+                | Card::SkipEveryone(color)
+                | Card::WildDrawColor(color)
+                | Card::WildDrawSix(color)
+                | Card::WildDrawTen(color)
+                | Card::WildReverseDrawFour(color)
+                | Card::DiscardAll(color)
+                | Card::WildColorRoulette(color)
+                | Card::WildSwapHands(color)
+                | Card::WildShuffleHands(color)
+                | Card::WildCustomizable(color) => {
                 *color
             }
         }
@@ -64,38 +166,144 @@ impl Card {
             Card::DrawFour(_) => PLUS_FOUR,
             Card::Reverse(_) => REVERSE,
             Card::Skip(_) => SKIP,
-            Card::DrawTwo(_) => PLUS_TWO
+            Card::DrawTwo(_) => PLUS_TWO,
+            Card::DrawOne(_) => PLUS_ONE,
+            Card::Flip(_) => FLIP,
+            Card::WildDrawTwo(_) => WILD_PLUS_TWO,
+            Card::DrawFive(_) => PLUS_FIVE,
+            Card::SkipEveryone(_) => SKIP_EVERYONE,
+            Card::WildDrawColor(_) => WILD_DRAW_COLOR,
+            Card::WildDrawSix(_) => WILD_PLUS_SIX,
+            Card::WildDrawTen(_) => WILD_PLUS_TEN,
+            Card::WildReverseDrawFour(_) => WILD_REVERSE_PLUS_FOUR,
+            Card::DiscardAll(_) => DISCARD_ALL,
+            Card::WildColorRoulette(_) => WILD_COLOR_ROULETTE,
+            Card::WildSwapHands(_) => WILD_SWAP_HANDS,
+            Card::WildShuffleHands(_) => WILD_SHUFFLE_HANDS,
+            Card::WildCustomizable(_) => WILD_CUSTOMIZABLE
+        }
+    }
+
+    /// Wilds can go on anything, and take whichever color they're played as
+    pub fn is_wild(&self) -> bool {
+        matches!(
+            self,
+            Card::Wild(_)
+                | Card::DrawFour(_)
+                | Card::WildDrawTwo(_)
+                | Card::WildDrawColor(_)
+                | Card::WildDrawSix(_)
+                | Card::WildDrawTen(_)
+                | Card::WildReverseDrawFour(_)
+                | Card::WildColorRoulette(_)
+                | Card::WildSwapHands(_)
+                | Card::WildShuffleHands(_)
+                | Card::WildCustomizable(_)
+        )
+    }
+
+    /// Wilds that make someone draw, which a game can't open on
+    pub fn is_wild_draw(&self) -> bool {
+        matches!(
+            self,
+            Card::DrawFour(_)
+                | Card::WildDrawTwo(_)
+                | Card::WildDrawColor(_)
+                | Card::WildDrawSix(_)
+                | Card::WildDrawTen(_)
+                | Card::WildReverseDrawFour(_)
+                | Card::WildColorRoulette(_)
+        )
+    }
+
+    /// What this is worth to whoever wins while it's still in someone else's hand
+    pub fn points(&self) -> u32 {
+        match self {
+            Card::Normal(_, number) => *number as u32,
+            Card::DrawOne(_) => 10,
+            Card::SkipEveryone(_) => 30,
+            Card::WildDrawColor(_) => 60,
+            wild if wild.is_wild() => 50,
+            _ => 20
         }
     }
-}
 
-impl PartialEq for Card {
-    fn eq(&self, other: &Self) -> bool {
+    /// How many cards this makes the next player draw, for the cards that always make them draw
+    /// the same number
+    pub fn penalty(&self) -> Option<usize> {
+        match self {
+            Card::DrawOne(_) => Some(1),
+            Card::DrawTwo(_) | Card::WildDrawTwo(_) => Some(2),
+            Card::DrawFour(_) | Card::WildReverseDrawFour(_) => Some(4),
+            Card::DrawFive(_) => Some(5),
+            Card::WildDrawSix(_) => Some(6),
+            Card::WildDrawTen(_) => Some(10),
+            _ => None
+        }
+    }
 
-        match other {
-            Card::Wild(_) | Card::DrawFour(_) => { return true },
-            _ => {}
-        };
+    /// Whether this card can legally go on top of `top`, given the color currently in play
+    pub fn can_play_on(&self, top: &Card, active_color: Color, rules: &Rules) -> bool {
+        if self.is_wild() {
+            return true
+        }
 
-        if self.color() == other.color() {
-            return true;
+        if self.color() == active_color {
+            return true
         }
-        
-        if self.number() == other.number() {
-            return true;
+
+        match (self, top) {
+            (Card::Normal(_, num), Card::Normal(_, top_num)) => num == top_num,
+            (Card::Skip(_), Card::Skip(_))
+                | (Card::Reverse(_), Card::Reverse(_))
+                | (Card::DrawTwo(_), Card::DrawTwo(_))
+                | (Card::DrawOne(_), Card::DrawOne(_))
+                | (Card::Flip(_), Card::Flip(_))
+                | (Card::DrawFive(_), Card::DrawFive(_))
+                | (Card::SkipEveryone(_), Card::SkipEveryone(_))
+                | (Card::DiscardAll(_), Card::DiscardAll(_)) => rules.match_actions,
+            _ => false
         }
+    }
+}
+
+/// Uniquely identifies a physical card within a deck
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CardId(pub u32);
+
+/// One actual card out of the deck. Two Red 5s share a face, but never an id
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PhysicalCard {
+    pub id: CardId,
+    pub card: Card
+}
 
-        false
+impl PhysicalCard {
+    pub fn new(id: u32, card: Card) -> Self {
+        Self { id: CardId(id), card }
     }
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+impl Display for PhysicalCard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.card)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum Color {
+    #[default]
     None,
     Red,
     Yellow,
     Green,
-    Blue
+    Blue,
+
+    // The dark side of UNO Flip
+    Pink,
+    Teal,
+    Orange,
+    Purple
 }
 
 impl Display for Color {
@@ -105,6 +313,10 @@ impl Display for Color {
             Color::Blue => "Blue",
             Color::Yellow => "Yellow",
             Color::Green => "Green",
+            Color::Pink => "Pink",
+            Color::Teal => "Teal",
+            Color::Orange => "Orange",
+            Color::Purple => "Purple",
             Color::None => "Wild"
         })
     }
@@ -116,5 +328,24 @@ impl Color {
 
         COLORS.iter()
     }
+
+    /// The colors cards come in on `side`
+    pub fn on(side: Side) -> Iter<'static, Color> {
+        static DARK: [Color; 4] = [Color::Pink, Color::Teal, Color::Orange, Color::Purple];
+
+        match side {
+            Side::Light => Self::iterator(),
+            Side::Dark => DARK.iter()
+        }
+    }
+
+    /// `None` for the color of a wild that hasn't been played yet
+    pub fn side(&self) -> Option<Side> {
+        match self {
+            Color::None => None,
+            Color::Red | Color::Yellow | Color::Green | Color::Blue => Some(Side::Light),
+            Color::Pink | Color::Teal | Color::Orange | Color::Purple => Some(Side::Dark)
+        }
+    }
 }
 
@@ -1,11 +1,25 @@
-use super::card::{Card, Color};
+use std::collections::HashMap;
+
+use super::{card::{Card, CardId, Color, PhysicalCard, Side}, decks::DeckDef, rules::Rules};
 use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
 
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Deck {
-    deck: Vec<Card>,
-    facing: Vec<Card>,
-    in_play: Vec<Card>
+    deck: Vec<PhysicalCard>,
+    facing: Vec<PhysicalCard>,
+    // Every card this deck was built with, and how many of those are currently in someone's hand
+    total: usize,
+    dealt: usize,
+    /// How many times the discard pile has gone back into the draw pile
+    #[serde(default)]
+    reshuffles: u64,
+    /// The face down side of every card, for UNO Flip. Empty for single sided decks
+    #[serde(default)]
+    backs: HashMap<CardId, Card>,
+    #[serde(default)]
+    side: Side
 }
 
 impl Default for Deck {
@@ -15,88 +29,240 @@ impl Default for Deck {
 }
 
 impl Deck {
+    /// The standard deck, `times` over
     pub fn new(times: usize) -> Self {
-        let mut deck = vec![];
+        Self::build(&DeckDef::standard(), times)
+    }
+
+    /// `copies` of the deck described by `def`, shuffled together
+    pub fn build(def: &DeckDef, copies: usize) -> Self {
+        Self::shuffled(def.cards().repeat(copies))
+    }
+
+    fn shuffled(deck: Vec<Card>) -> Self {
         let facing = vec![];
-        let in_play = vec![];
 
+        let mut deck: Vec<PhysicalCard> = deck.into_iter()
+            .enumerate()
+            .map(|(id, card)| PhysicalCard::new(id as u32, card))
+            .collect();
 
-        for _ in 0..times {
-            for color in Color::iterator() {
-                deck.push(Card::Normal(*color, 0));
+        deck.shuffle(&mut thread_rng());
+        let total = deck.len();
 
-                for i in 1..=9 {
-                    deck.push(Card::Normal(*color, i));
-                    deck.push(Card::Normal(*color, i));
-                }
-                deck.push(Card::DrawTwo(*color));
-                deck.push(Card::DrawTwo(*color));
+        Self { deck, facing, total, dealt: 0, reshuffles: 0, backs: HashMap::new(), side: Side::Light }
+    }
 
-                deck.push(Card::Reverse(*color));
-                deck.push(Card::Reverse(*color));
+    /// The 112 card UNO Flip deck, `times` over, light side up. Each light face is given a dark
+    /// one at random
+    pub fn double_sided(times: usize) -> Self {
+        let side = |side: Side, draw: fn(Color) -> Card, skip: fn(Color) -> Card, wild_draw: Card| {
+            let mut cards = vec![];
 
-                deck.push(Card::Skip(*color));
-                deck.push(Card::Skip(*color));
+            for color in Color::on(side) {
+                for i in 1..=9 {
+                    cards.push(Card::Normal(*color, i));
+                    cards.push(Card::Normal(*color, i));
+                }
+                for action in [draw, skip, Card::Reverse, Card::Flip] {
+                    cards.push(action(*color));
+                    cards.push(action(*color));
+                }
             }
 
             for _ in 0..4 {
-                deck.push(Card::Wild(Color::None));
-                deck.push(Card::Wild(Color::None));
+                cards.push(Card::Wild(Color::None));
+                cards.push(wild_draw);
             }
-        }
 
-        deck.shuffle(&mut thread_rng());
+            cards
+        };
 
-        Self { deck, facing, in_play }
+        let light = side(Side::Light, Card::DrawOne, Card::Skip, Card::WildDrawTwo(Color::None)).repeat(times);
+        let mut dark = side(Side::Dark, Card::DrawFive, Card::SkipEveryone, Card::WildDrawColor(Color::None)).repeat(times);
+        dark.shuffle(&mut thread_rng());
+
+        let mut deck = Self::shuffled(light);
+        deck.backs = deck.deck.iter().map(|card| card.id).zip(dark).collect();
+
+        deck
+    }
+
+    /// Which side every card is showing
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// Turns both piles over, UNO Flip style. The discard pile is flipped as a whole, so the
+    /// first card played ends up on top
+    pub fn flip(&mut self) {
+        self.side = self.side.other();
+
+        let deck: Vec<PhysicalCard> = std::mem::take(&mut self.deck).into_iter().rev().collect();
+        self.deck = deck.into_iter().map(|card| self.turn(card)).collect();
+
+        let facing: Vec<PhysicalCard> = std::mem::take(&mut self.facing).into_iter().rev().collect();
+        self.facing = facing.into_iter().map(|card| self.turn(card)).collect();
+    }
+
+    /// `card` with its other side up. Cards in someone's hand have to be turned as well whenever
+    /// the table flips
+    pub fn turn(&mut self, card: PhysicalCard) -> PhysicalCard {
+        match self.backs.get_mut(&card.id) {
+            Some(back) => PhysicalCard { id: card.id, card: std::mem::replace(back, card.card) },
+            None => card
+        }
     }
 
+    /// Moves the discard pile back into the draw pile, leaving the current top card where it is.
+    /// Wilds lose whatever color they were played as
     pub fn reshuffle(&mut self) {
-        self.deck.extend(self.facing.drain(0..self.facing.len()));
+        let Some(top) = self.facing.pop() else {
+            return
+        };
+
+        self.deck.extend(self.facing.drain(..).map(|mut played| {
+            played.card = match played.card {
+                Card::Wild(_) => Card::Wild(Color::None),
+                Card::DrawFour(_) => Card::DrawFour(Color::None),
+                Card::WildDrawTwo(_) => Card::WildDrawTwo(Color::None),
+                Card::WildDrawColor(_) => Card::WildDrawColor(Color::None),
+                Card::WildDrawSix(_) => Card::WildDrawSix(Color::None),
+                Card::WildDrawTen(_) => Card::WildDrawTen(Color::None),
+                Card::WildReverseDrawFour(_) => Card::WildReverseDrawFour(Color::None),
+                Card::WildColorRoulette(_) => Card::WildColorRoulette(Color::None),
+                Card::WildSwapHands(_) => Card::WildSwapHands(Color::None),
+                Card::WildShuffleHands(_) => Card::WildShuffleHands(Color::None),
+                Card::WildCustomizable(_) => Card::WildCustomizable(Color::None),
+                card => card
+            };
+            played
+        }));
+        self.facing.push(top);
 
         self.deck.shuffle(&mut thread_rng());
+        self.reshuffles += 1;
+    }
+
+    pub fn reshuffles(&self) -> u64 {
+        self.reshuffles
     }
 
     pub fn deck_size(&self) -> usize {
         self.deck.len()
     }
 
-    pub fn start_game(&mut self) {
-        if let Some(card) = self.deck.pop() {
-            self.facing.push(card)
+    pub fn discard_size(&self) -> usize {
+        self.facing.len()
+    }
+
+    /// Panics if a card has been created or lost somewhere between the draw pile, the discard pile
+    /// and player's hands. Only checked in debug builds
+    pub fn check_conservation(&self) {
+        debug_assert_eq!(
+            self.total,
+            self.deck.len() + self.facing.len() + self.dealt,
+            "Card conservation violated: {} in deck, {} discarded, {} dealt out of {}",
+            self.deck.len(), self.facing.len(), self.dealt, self.total
+        );
+    }
+
+    /// Flips the first card of the game onto the discard pile. A Wild Draw Four can't start a game,
+    /// nor can any other wild that makes someone draw, so those get shuffled back in until
+    /// something else comes up
+    pub fn start_game(&mut self) -> Option<Card> {
+        if self.deck.iter().all(|flipped| flipped.card.is_wild_draw()) {
+            return None
+        }
+
+        loop {
+            let flipped = self.deck.pop()?;
+
+            if flipped.card.is_wild_draw() {
+                self.deck.push(flipped);
+                self.deck.shuffle(&mut thread_rng());
+                continue;
+            }
+
+            self.facing.push(flipped);
+            return Some(flipped.card)
         }
     }
 
     pub fn get_facing(&self) -> Option<&Card> {
-        match self.facing.len() {
-            0 => None,
-            _ => Some(&self.facing[self.facing.len() - 1])
-        }
+        self.facing.last().map(|top| &top.card)
     }
 
     #[cfg(test)]
     pub fn set_facing(&mut self, face: Card) {
-        self.facing.push(face)
+        self.facing.push(PhysicalCard::new(self.total as u32, face));
+        self.total += 1;
     }
 
-    pub fn play(&mut self, to_play: Card) -> Option<&Card> {
+    /// Hands out a brand new card as if it had been drawn
+    #[cfg(test)]
+    pub fn forge(&mut self, face: Card) -> PhysicalCard {
+        let card = PhysicalCard::new(self.total as u32, face);
+        self.total += 1;
+        self.dealt += 1;
 
+        card
+    }
+
+    /// Hands out a brand new double sided card as if it had been drawn
+    #[cfg(test)]
+    pub fn forge_flip(&mut self, face: Card, back: Card) -> PhysicalCard {
+        let card = self.forge(face);
+        self.backs.insert(card.id, back);
+
+        card
+    }
+
+    /// Puts a card from someone's hand on the discard pile if it's a legal play
+    pub fn play(&mut self, to_play: PhysicalCard, active_color: Color, rules: &Rules) -> Option<&Card> {
         let curr = self.get_facing()?;
 
-        if curr == &to_play {
-            self.facing.push(to_play);
-            self.get_facing()
+        if to_play.card.can_play_on(curr, active_color, rules) {
+            Some(self.discard(to_play))
         } else {
             None
         }
     }
 
-    pub fn draw(&mut self) -> Card {
-        if let Some(card) = self.deck.pop() {
-            card
-        } else {
+    /// Puts a card from someone's hand on the discard pile whether it matches or not, for when
+    /// a house rule has already said it can go there
+    pub fn discard(&mut self, to_play: PhysicalCard) -> &Card {
+        self.dealt = self.dealt.saturating_sub(1);
+        self.facing.push(to_play);
+        &self.facing[self.facing.len() - 1].card
+    }
+
+    /// Slides cards from someone's hand into the discard pile just under the top card, so the top
+    /// card stays what it was
+    pub fn bury(&mut self, cards: &[PhysicalCard]) {
+        let under = self.facing.len().saturating_sub(1);
+        self.facing.splice(under..under, cards.iter().copied());
+        self.dealt = self.dealt.saturating_sub(cards.len());
+    }
+
+    /// Shuffles cards from someone's hand back into the draw pile
+    pub fn put_back(&mut self, cards: &[PhysicalCard]) {
+        self.deck.extend_from_slice(cards);
+        self.deck.shuffle(&mut thread_rng());
+        self.dealt = self.dealt.saturating_sub(cards.len());
+    }
+
+    /// Draws from the draw pile, reshuffling the discard pile into it once it runs out. Returns
+    /// `None` if there is nothing left in either
+    pub fn draw(&mut self) -> Option<PhysicalCard> {
+        if self.deck.is_empty() {
             self.reshuffle();
-            self.deck.pop().unwrap()
         }
+
+        let card = self.deck.pop()?;
+        self.dealt += 1;
+
+        Some(card)
     }
 
 }
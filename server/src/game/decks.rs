@@ -0,0 +1,310 @@
+use std::{collections::BTreeMap, fs, io::ErrorKind, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::res::err::{Result, ServerError};
+
+use super::{card::{Card, Color}, rules::Rules};
+
+/// Where deck definitions are kept, inside `data_dir`. Each `.toml` or `.json` file in it is a
+/// deck named after the file
+pub const DECK_DIR: &str = "decks";
+
+/// The deck every room plays with unless told otherwise
+pub const STANDARD: &str = "standard";
+
+/// The built in deck with the special wilds in it
+pub const SPECIAL: &str = "special";
+
+/// The most cards one copy of a deck can hold, so ids and memory stay sane
+const MAX_CARDS: usize = 10_000;
+
+/// Everything in one player's opening hand
+pub const HAND_SIZE: usize = 7;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Face {
+    Normal,
+    DrawTwo,
+    Reverse,
+    Skip,
+    Wild,
+    DrawFour,
+    SkipEveryone,
+    DiscardAll,
+    WildDrawSix,
+    WildDrawTen,
+    WildReverseDrawFour,
+    WildColorRoulette,
+    WildSwapHands,
+    WildShuffleHands,
+    WildCustomizable
+}
+
+impl Face {
+    /// Wilds have no color until they're played
+    fn is_wild(&self) -> bool {
+        self.card(Color::None, 0).is_wild()
+    }
+
+    fn card(&self, color: Color, number: u8) -> Card {
+        match self {
+            Face::Normal => Card::Normal(color, number),
+            Face::DrawTwo => Card::DrawTwo(color),
+            Face::Reverse => Card::Reverse(color),
+            Face::Skip => Card::Skip(color),
+            Face::Wild => Card::Wild(Color::None),
+            Face::DrawFour => Card::DrawFour(Color::None),
+            Face::SkipEveryone => Card::SkipEveryone(color),
+            Face::DiscardAll => Card::DiscardAll(color),
+            Face::WildDrawSix => Card::WildDrawSix(Color::None),
+            Face::WildDrawTen => Card::WildDrawTen(Color::None),
+            Face::WildReverseDrawFour => Card::WildReverseDrawFour(Color::None),
+            Face::WildColorRoulette => Card::WildColorRoulette(Color::None),
+            Face::WildSwapHands => Card::WildSwapHands(Color::None),
+            Face::WildShuffleHands => Card::WildShuffleHands(Color::None),
+            Face::WildCustomizable => Card::WildCustomizable(Color::None)
+        }
+    }
+}
+
+/// Some number of one kind of card, e.g. two of every number from 1 to 9 in every color
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CardSet {
+    pub face: Face,
+    /// Every color when left out. Wilds don't take any
+    #[serde(default)]
+    pub colors: Vec<Color>,
+    /// Only for `Normal` cards, which need at least one
+    #[serde(default)]
+    pub numbers: Vec<u8>,
+    /// Copies of each color and number
+    #[serde(default = "one")]
+    pub count: usize
+}
+
+fn one() -> usize {
+    1
+}
+
+/// What goes into a deck, and how it grows with the number of players
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeckDef {
+    pub cards: Vec<CardSet>,
+    /// How many players share one copy of the deck. Follows the room's `players_per_deck` when
+    /// left out
+    #[serde(default)]
+    pub players_per_copy: Option<usize>,
+    #[serde(default = "one")]
+    pub min_copies: usize,
+    #[serde(default)]
+    pub max_copies: Option<usize>
+}
+
+impl DeckDef {
+    /// The regular 108 card deck
+    pub fn standard() -> Self {
+        let set = |face, numbers: Vec<u8>, count| CardSet { face, colors: vec![], numbers, count };
+
+        Self {
+            cards: vec![
+                set(Face::Normal, vec![0], 1),
+                set(Face::Normal, (1..=9).collect(), 2),
+                set(Face::DrawTwo, vec![], 2),
+                set(Face::Reverse, vec![], 2),
+                set(Face::Skip, vec![], 2),
+                set(Face::Wild, vec![], 4),
+                set(Face::DrawFour, vec![], 4)
+            ],
+            players_per_copy: None,
+            min_copies: 1,
+            max_copies: None
+        }
+    }
+
+    /// The 112 card deck with special wilds, where one Wild makes way for a Wild Swap Hands, a
+    /// Wild Shuffle Hands and three blank Wild Customizables
+    pub fn special() -> Self {
+        let set = |face, count| CardSet { face, colors: vec![], numbers: vec![], count };
+
+        let mut def = Self::standard();
+        def.cards.retain(|set| set.face != Face::Wild);
+        def.cards.extend([
+            set(Face::Wild, 3),
+            set(Face::WildSwapHands, 1),
+            set(Face::WildShuffleHands, 1),
+            set(Face::WildCustomizable, 3)
+        ]);
+
+        def
+    }
+
+    /// The 160 card No Mercy deck. The real one has 168, the other 8 being colored Draw Fours.
+    /// A Draw Four is always wild here, so those are left out rather than played as wild Draw
+    /// Fours, and there are no plain Wilds or Draw Fours in it at all
+    pub fn no_mercy() -> Self {
+        let set = |face, numbers: Vec<u8>, count| CardSet { face, colors: vec![], numbers, count };
+
+        Self {
+            cards: vec![
+                set(Face::Normal, (0..=9).collect(), 2),
+                set(Face::DrawTwo, vec![], 3),
+                set(Face::Reverse, vec![], 3),
+                set(Face::Skip, vec![], 3),
+                set(Face::DiscardAll, vec![], 3),
+                set(Face::SkipEveryone, vec![], 2),
+                set(Face::WildReverseDrawFour, vec![], 8),
+                set(Face::WildDrawSix, vec![], 4),
+                set(Face::WildDrawTen, vec![], 4),
+                set(Face::WildColorRoulette, vec![], 8)
+            ],
+            players_per_copy: None,
+            min_copies: 1,
+            max_copies: None
+        }
+    }
+
+    /// Every card in one copy of the deck
+    pub fn cards(&self) -> Vec<Card> {
+        let mut cards = vec![];
+
+        for set in self.cards.iter() {
+            let colors: &[Color] = match (set.face.is_wild(), set.colors.is_empty()) {
+                (true, _) => &[Color::None],
+                (false, true) => Color::iterator().as_slice(),
+                (false, false) => &set.colors
+            };
+            let numbers: &[u8] = if set.face == Face::Normal { &set.numbers } else { &[0] };
+
+            for color in colors {
+                for number in numbers {
+                    cards.extend(std::iter::repeat_n(set.face.card(*color, *number), set.count));
+                }
+            }
+        }
+
+        cards
+    }
+
+    /// How many copies are shuffled together for a game of `players`
+    pub fn copies(&self, players: usize, rules: &Rules) -> usize {
+        let copies = match self.players_per_copy {
+            Some(per) => players.div_ceil(per),
+            None => rules.deck_copies(players)
+        };
+
+        copies.max(self.min_copies).min(self.max_copies.unwrap_or(usize::MAX))
+    }
+
+    /// Whether there's enough to deal everyone in a game of `players` a hand and still flip a card
+    pub fn can_deal(&self, players: usize, rules: &Rules) -> bool {
+        self.cards().len() * self.copies(players, rules) > players * HAND_SIZE
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.players_per_copy == Some(0) {
+            return Err("players_per_copy must be at least 1".into())
+        }
+        if self.min_copies == 0 || self.max_copies.is_some_and(|max| max < self.min_copies) {
+            return Err("min_copies must be at least 1 and no more than max_copies".into())
+        }
+
+        for set in self.cards.iter() {
+            if set.count == 0 {
+                return Err(format!("{:?} cards need a count of at least 1", set.face))
+            }
+            if set.colors.contains(&Color::None) {
+                return Err(format!("{:?} cards can't be colored None", set.face))
+            }
+
+            match set.face {
+                Face::Normal if set.numbers.is_empty() => return Err("Normal cards need at least one number".into()),
+                Face::Normal if set.numbers.iter().any(|number| *number > 9) => return Err("Normal cards are numbered 0 to 9".into()),
+                Face::Normal => {},
+                face if !set.numbers.is_empty() => return Err(format!("{:?} cards don't have numbers", face)),
+                face if face.is_wild() && !set.colors.is_empty() => return Err(format!("{:?} cards don't have colors", face)),
+                _ => {}
+            }
+        }
+
+        let cards = self.cards();
+        if cards.len() > MAX_CARDS {
+            return Err(format!("one copy can't have more than {} cards", MAX_CARDS))
+        }
+        // A game can't open on a wild that makes someone draw
+        if cards.iter().all(Card::is_wild_draw) {
+            return Err("there needs to be at least one card that isn't a wild draw".into())
+        }
+
+        Ok(())
+    }
+}
+
+/// Every deck rooms can pick from, by name. Always has the standard and special ones
+#[derive(Clone, Debug)]
+pub struct Decks {
+    decks: BTreeMap<String, DeckDef>
+}
+
+impl Default for Decks {
+    fn default() -> Self {
+        Self { decks: BTreeMap::from([(STANDARD.to_string(), DeckDef::standard()), (SPECIAL.to_string(), DeckDef::special())]) }
+    }
+}
+
+impl Decks {
+    /// The built in decks, plus every deck defined in `dir`. A file can replace a built in deck
+    /// by taking its name
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut decks = Self::default();
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(decks),
+            Err(e) => return Err(ServerError::Config(format!("couldn't read {}: {}", dir.display(), e)))
+        };
+
+        let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+        paths.sort();
+
+        for path in paths {
+            let (Some(name), Some(ext)) = (path.file_stem().and_then(|name| name.to_str()), path.extension().and_then(|ext| ext.to_str())) else {
+                continue
+            };
+
+            let source = || fs::read_to_string(&path)
+                .map_err(|e| ServerError::Config(format!("couldn't read {}: {}", path.display(), e)));
+            let def = match ext {
+                "toml" => toml::from_str(&source()?).map_err(|e| e.to_string()),
+                "json" => serde_json::from_str(&source()?).map_err(|e| e.to_string()),
+                _ => continue
+            };
+
+            def.and_then(|def| decks.insert(name, def))
+                .map_err(|e| ServerError::Config(format!("deck {}: {}", path.display(), e)))?;
+        }
+
+        info!(decks = ?decks.names(), "Loaded decks");
+        Ok(decks)
+    }
+
+    /// Adds a deck after checking it could actually be played with
+    pub fn insert(&mut self, name: &str, def: DeckDef) -> std::result::Result<(), String> {
+        def.validate()?;
+
+        self.decks.insert(name.to_string(), def);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DeckDef> {
+        self.decks.get(name)
+    }
+
+    /// Sorted
+    pub fn names(&self) -> Vec<&str> {
+        self.decks.keys().map(String::as_str).collect()
+    }
+}
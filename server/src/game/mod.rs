@@ -1,3 +1,6 @@
 pub mod deck;
+pub mod decks;
 pub mod card;
+pub mod rules;
+pub mod script;
 pub mod test;
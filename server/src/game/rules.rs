@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+
+use super::{card::{Card, Color}, decks::STANDARD};
+
+/// The knobs that decide how a game is played
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    /// Lets action cards be played on the same action of any color, e.g. a Red Skip on a Blue
+    /// Skip. Turning this off means action cards only ever match by color
+    pub match_actions: bool,
+    /// How many players share one copy of the deck, a game of 5 with this set to 2 is played with
+    /// 3 decks shuffled together
+    pub players_per_deck: usize,
+    /// The name of the deck to play with, out of the built in `standard` and `special` ones and
+    /// any defined in `data_dir`. Only classic games use it, other variants come with their own deck
+    pub deck: String,
+    pub variant: Variant,
+    /// What a Wild Customizable does when it's played
+    pub blank_wild: BlankWild,
+    pub teams: Teams
+}
+
+/// Which game of UNO is being played
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Variant {
+    #[default]
+    Classic,
+    /// Double sided cards, with a Flip card that turns the whole table over to the dark side and
+    /// back
+    Flip,
+    /// A meaner deck where draw cards stack, and anyone holding 25 cards is out
+    NoMercy
+}
+
+/// Whether everyone plays for themselves, or two against two
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Teams {
+    #[default]
+    Solo,
+    /// Partners sit across from each other, and win together when either goes out
+    Partners,
+    /// Partners, who can also see each other's hands
+    OpenPartners
+}
+
+/// Every effect a blank Wild Customizable can be given
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlankWild {
+    /// Nothing more than a wild
+    #[default]
+    Plain,
+    Skip,
+    Reverse,
+    DrawTwo,
+    DrawFour,
+    SwapHands,
+    ShuffleHands
+}
+
+impl BlankWild {
+    /// The card a blank played as `color` acts like
+    pub fn card(&self, color: Color) -> Card {
+        match self {
+            BlankWild::Plain => Card::Wild(color),
+            BlankWild::Skip => Card::Skip(color),
+            BlankWild::Reverse => Card::Reverse(color),
+            BlankWild::DrawTwo => Card::DrawTwo(color),
+            BlankWild::DrawFour => Card::DrawFour(color),
+            BlankWild::SwapHands => Card::WildSwapHands(color),
+            BlankWild::ShuffleHands => Card::WildShuffleHands(color)
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self { match_actions: true, players_per_deck: 1, deck: STANDARD.into(), variant: Variant::Classic, blank_wild: BlankWild::Plain, teams: Teams::Solo }
+    }
+}
+
+impl std::fmt::Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Variant::Classic => "classic",
+            Variant::Flip => "UNO Flip",
+            Variant::NoMercy => "No Mercy"
+        })
+    }
+}
+
+impl std::fmt::Display for BlankWild {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            BlankWild::Plain => "plain wild",
+            BlankWild::Skip => "Skip",
+            BlankWild::Reverse => "Reverse",
+            BlankWild::DrawTwo => "Draw Two",
+            BlankWild::DrawFour => "Draw Four",
+            BlankWild::SwapHands => "Swap Hands",
+            BlankWild::ShuffleHands => "Shuffle Hands"
+        })
+    }
+}
+
+impl Rules {
+    pub fn deck_copies(&self, players: usize) -> usize {
+        players.div_ceil(self.players_per_deck).max(1)
+    }
+}
@@ -0,0 +1,279 @@
+use std::{fs, io::ErrorKind, path::Path, sync::{Arc, Mutex}};
+
+use rhai::{module_resolvers::DummyModuleResolver, Array, CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use tracing::{debug, info, warn};
+
+use crate::res::err::{Result, ServerError};
+
+use super::card::{Card, Color};
+
+/// Where house rules are kept, inside `data_dir`. Every `.rhai` file in it is loaded at startup
+pub const SCRIPT_DIR: &str = "rules";
+
+/// How much a single hook can do before it's stopped, so a runaway loop can't hang a room
+const MAX_OPERATIONS: u64 = 100_000;
+/// The most cards a script can have someone draw at once
+pub const MAX_DRAW: usize = 20;
+
+/// Something a script asked the game to do, carried out once its hook has returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect {
+    Announce(String),
+    /// The player sitting in `seat` draws `count` cards
+    Draw { seat: usize, count: usize },
+    /// The player due to go next misses their turn
+    Skip,
+    Reverse
+}
+
+/// Everything a hook gets to see of the game, as `game`. Scripts never touch the game itself,
+/// they ask for [`Effect`]s through methods on this instead
+#[derive(Debug, Clone)]
+pub struct Table {
+    players: Array,
+    turn: Dynamic,
+    next: Dynamic,
+    top: Dynamic,
+    color: String,
+    direction: String,
+    effects: Arc<Mutex<Vec<Effect>>>
+}
+
+/// A player as scripts see them
+#[derive(Debug, Clone)]
+pub struct Seat {
+    pub name: Option<String>,
+    pub cards: usize,
+    pub spectator: bool
+}
+
+impl Table {
+    /// `turn` and `next` are indexes into `seats`
+    pub fn new(seats: Vec<Seat>, turn: Option<usize>, next: Option<usize>, top: Option<Card>, color: Color, direction: &str) -> Self {
+        let players: Array = seats.into_iter()
+            .enumerate()
+            .map(|(seat, player)| {
+                let mut map = Map::new();
+                map.insert("seat".into(), (seat as i64).into());
+                map.insert("name".into(), player.name.map_or(Dynamic::UNIT, Dynamic::from));
+                map.insert("cards".into(), (player.cards as i64).into());
+                map.insert("spectator".into(), player.spectator.into());
+                Dynamic::from_map(map)
+            })
+            .collect();
+
+        let seat = |seat: Option<usize>| seat.and_then(|seat| players.get(seat).cloned()).unwrap_or(Dynamic::UNIT);
+
+        Self {
+            turn: seat(turn),
+            next: seat(next),
+            top: top.map_or(Dynamic::UNIT, card),
+            color: color.to_string(),
+            direction: direction.to_string(),
+            players,
+            effects: Arc::default()
+        }
+    }
+
+    /// Everything asked for so far, in the order it was asked for
+    pub fn take_effects(&self) -> Vec<Effect> {
+        std::mem::take(&mut *self.effects.lock().unwrap())
+    }
+
+    fn player(&self, seat: usize) -> Dynamic {
+        self.players.get(seat).cloned().unwrap_or(Dynamic::UNIT)
+    }
+
+    fn ask(&mut self, effect: Effect) {
+        self.effects.lock().unwrap().push(effect);
+    }
+}
+
+/// A card as scripts see it, e.g. `#{face: "Normal", color: "Red", number: 7}`
+fn card(card: Card) -> Dynamic {
+    let (face, number) = match card {
+        Card::Normal(_, number) => ("Normal", Some(number)),
+        Card::DrawTwo(_) => ("DrawTwo", None),
+        Card::Reverse(_) => ("Reverse", None),
+        Card::Skip(_) => ("Skip", None),
+        Card::Wild(_) => ("Wild", None),
+        Card::DrawFour(_) => ("DrawFour", None),
+        Card::DrawOne(_) => ("DrawOne", None),
+        Card::Flip(_) => ("Flip", None),
+        Card::WildDrawTwo(_) => ("WildDrawTwo", None),
+        Card::DrawFive(_) => ("DrawFive", None),
+        Card::SkipEveryone(_) => ("SkipEveryone", None),
+        Card::WildDrawColor(_) => ("WildDrawColor", None),
+        Card::WildDrawSix(_) => ("WildDrawSix", None),
+        Card::WildDrawTen(_) => ("WildDrawTen", None),
+        Card::WildReverseDrawFour(_) => ("WildReverseDrawFour", None),
+        Card::DiscardAll(_) => ("DiscardAll", None),
+        Card::WildColorRoulette(_) => ("WildColorRoulette", None),
+        Card::WildSwapHands(_) => ("WildSwapHands", None),
+        Card::WildShuffleHands(_) => ("WildShuffleHands", None),
+        Card::WildCustomizable(_) => ("WildCustomizable", None)
+    };
+
+    let mut map = Map::new();
+    map.insert("face".into(), face.into());
+    map.insert("color".into(), card.color().to_string().into());
+    map.insert("number".into(), number.map_or(Dynamic::UNIT, |number| (number as i64).into()));
+    Dynamic::from_map(map)
+}
+
+struct Script {
+    name: String,
+    ast: AST
+}
+
+/// House rules written in Rhai. A script can define any of these, and each runs in every script
+/// that defines it, in file name order:
+///
+/// - `can_play(game, card, allowed)` returns whether `card` can go on `game.top`. `allowed` is
+///   what the built in rules, or the script before, decided
+/// - `on_play(game, player, card)` after a card has been played
+/// - `on_draw(game, player, card)` after a player draws
+/// - `on_turn_end(game, player)` once a player's turn is over, before the next one starts
+/// - `on_round_end(game, winner)` once a game is over, `winner` is `()` if nobody won
+///
+/// Scripts have no access to anything outside the game, and a hook that errors or runs too
+/// long is logged and skipped
+pub struct Scripts {
+    engine: Engine,
+    scripts: Vec<Script>
+}
+
+impl Scripts {
+    /// Loads every `.rhai` file in `dir`. `None` if there aren't any
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ServerError::Config(format!("couldn't read {}: {}", dir.display(), e)))
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+            .collect();
+        paths.sort();
+
+        let sources = paths.iter()
+            .map(|path| {
+                let source = fs::read_to_string(path)
+                    .map_err(|e| ServerError::Config(format!("couldn't read {}: {}", path.display(), e)))?;
+                Ok((path.file_name().unwrap_or_default().to_string_lossy().into_owned(), source))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if sources.is_empty() {
+            return Ok(None)
+        }
+
+        let scripts = Self::compile(sources)?;
+        info!(scripts = ?scripts.names(), "Loaded house rules");
+
+        Ok(Some(scripts))
+    }
+
+    /// Compiles `(name, source)` pairs, failing on the first that doesn't parse
+    pub fn compile(sources: Vec<(String, String)>) -> Result<Self> {
+        let engine = sandbox();
+
+        let scripts = sources.into_iter()
+            .map(|(name, source)| match engine.compile(&source) {
+                Ok(ast) => Ok(Script { name, ast }),
+                Err(e) => Err(ServerError::Config(format!("house rule {} doesn't compile: {}", name, e)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { engine, scripts })
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.scripts.iter().map(|script| script.name.as_str()).collect()
+    }
+
+    pub fn can_play(&self, game: &Table, played: Card, allowed: bool) -> bool {
+        self.scripts.iter().fold(allowed, |allowed, script| {
+            self.call(script, "can_play", vec![Dynamic::from(game.clone()), card(played), allowed.into()])
+                .and_then(|verdict| verdict.as_bool().ok())
+                .unwrap_or(allowed)
+        })
+    }
+
+    pub fn on_play(&self, game: &Table, seat: usize, played: Card) {
+        self.call_all("on_play", vec![Dynamic::from(game.clone()), game.player(seat), card(played)]);
+    }
+
+    pub fn on_draw(&self, game: &Table, seat: usize, drawn: Card) {
+        self.call_all("on_draw", vec![Dynamic::from(game.clone()), game.player(seat), card(drawn)]);
+    }
+
+    pub fn on_turn_end(&self, game: &Table, seat: usize) {
+        self.call_all("on_turn_end", vec![Dynamic::from(game.clone()), game.player(seat)]);
+    }
+
+    pub fn on_round_end(&self, game: &Table, winner: Option<usize>) {
+        let winner = winner.map_or(Dynamic::UNIT, |seat| game.player(seat));
+        self.call_all("on_round_end", vec![Dynamic::from(game.clone()), winner]);
+    }
+
+    fn call_all(&self, hook: &str, args: Vec<Dynamic>) {
+        for script in self.scripts.iter() {
+            self.call(script, hook, args.clone());
+        }
+    }
+
+    /// `None` if the script doesn't define `hook`, or it failed
+    fn call(&self, script: &Script, hook: &str, args: Vec<Dynamic>) -> Option<Dynamic> {
+        if !script.ast.iter_functions().any(|f| f.name == hook && f.params.len() == args.len()) {
+            return None
+        }
+
+        let options = CallFnOptions::new().eval_ast(false);
+        match self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &script.ast, hook, args) {
+            Ok(res) => Some(res),
+            Err(e) => {
+                warn!(script = %script.name, hook, error = %e, "House rule failed");
+                None
+            }
+        }
+    }
+}
+
+/// An engine that can only compute, with nothing to reach the filesystem, other scripts or the
+/// process through, and a cap on how much work any one call can do
+fn sandbox() -> Engine {
+    let mut engine = Engine::new();
+
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(4 * 1024);
+    engine.set_max_array_size(1024);
+    engine.set_max_map_size(256);
+
+    engine.on_print(|text| info!(text, "House rule printed"));
+    engine.on_debug(|text, source, pos| debug!(text, source, %pos, "House rule debug"));
+
+    engine.register_type_with_name::<Table>("Game")
+        .register_get("players", |game: &mut Table| game.players.clone())
+        .register_get("turn", |game: &mut Table| game.turn.clone())
+        .register_get("next", |game: &mut Table| game.next.clone())
+        .register_get("top", |game: &mut Table| game.top.clone())
+        .register_get("color", |game: &mut Table| game.color.clone())
+        .register_get("direction", |game: &mut Table| game.direction.clone())
+        .register_fn("announce", |game: &mut Table, text: &str| game.ask(Effect::Announce(text.to_string())))
+        .register_fn("skip", |game: &mut Table| game.ask(Effect::Skip))
+        .register_fn("reverse", |game: &mut Table| game.ask(Effect::Reverse))
+        .register_fn("draw", |game: &mut Table, seat: i64, count: i64| {
+            if let (Ok(seat), Ok(count)) = (usize::try_from(seat), usize::try_from(count)) {
+                game.ask(Effect::Draw { seat, count: count.min(MAX_DRAW) });
+            }
+        });
+
+    engine
+}
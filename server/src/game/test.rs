@@ -1,10 +1,17 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod test {
-    use crate::game::{card::{Card, Color}, deck::Deck};
+    use std::{collections::HashSet, env, fs};
+
+    use crate::game::{card::{Card, Color, PhysicalCard, Side}, deck::Deck, decks::{CardSet, DeckDef, Decks, Face, SPECIAL, STANDARD}, rules::Rules, script::{Effect, Scripts, Table, MAX_DRAW}};
+
+    fn in_hand(card: Card) -> PhysicalCard {
+        PhysicalCard::new(u32::MAX, card)
+    }
 
     #[test]
     fn ensure_deck_construction() {
-        let mut deck: Deck = Deck::new();
+        let mut deck: Deck = Deck::new(1);
 
         assert_eq!(108, deck.deck_size());
 
@@ -14,7 +21,7 @@ mod test {
 
     #[test]
     fn test_play_valid() {
-        let mut deck = Deck::new();
+        let mut deck = Deck::new(1);
 
         deck.set_facing(Card::Normal(Color::Red, 9));
 
@@ -31,35 +38,418 @@ mod test {
         let card_option_six = Card::DrawFour(Color::None);
         let card_option_seven = Card::Wild(Color::None);
 
-        assert!(deck.play(card_option_one).is_some());
+        assert!(deck.play(in_hand(card_option_one), Color::Red, &Rules::default()).is_some());
         deck.set_facing(Card::Normal(Color::Red, 9));
 
-        assert!(deck.play(card_option_two).is_some());
+        assert!(deck.play(in_hand(card_option_two), Color::Red, &Rules::default()).is_some());
         deck.set_facing(Card::Normal(Color::Red, 9));
 
-        assert!(deck.play(card_option_three).is_some());
+        assert!(deck.play(in_hand(card_option_three), Color::Red, &Rules::default()).is_some());
         deck.set_facing(Card::Normal(Color::Red, 9));
 
-        assert!(deck.play(card_option_four).is_some());
+        assert!(deck.play(in_hand(card_option_four), Color::Red, &Rules::default()).is_some());
         deck.set_facing(Card::Normal(Color::Red, 9));
 
-        assert!(deck.play(card_option_five).is_some());
+        assert!(deck.play(in_hand(card_option_five), Color::Red, &Rules::default()).is_some());
         deck.set_facing(Card::Normal(Color::Red, 9));
 
-        assert!(deck.play(card_option_six).is_some());
+        assert!(deck.play(in_hand(card_option_six), Color::Red, &Rules::default()).is_some());
         deck.set_facing(Card::Normal(Color::Red, 9));
 
-        assert!(deck.play(card_option_seven).is_some());
+        assert!(deck.play(in_hand(card_option_seven), Color::Red, &Rules::default()).is_some());
     }
 
     #[test]
     fn test_invalid_plays() {
-        let mut deck = Deck::new();
+        let mut deck = Deck::new(1);
 
         deck.set_facing(Card::Normal(Color::Red, 9));
 
         let invalid_one = Card::Normal(Color::Yellow, 1);
 
-        assert!(deck.play(invalid_one).is_none());
+        assert!(deck.play(in_hand(invalid_one), Color::Red, &Rules::default()).is_none());
+    }
+
+    #[test]
+    fn reshuffle_keeps_top_card() {
+        let mut deck = Deck::new(1);
+        deck.start_game();
+
+        deck.set_facing(Card::Wild(Color::Blue));
+        deck.set_facing(Card::Normal(Color::Green, 4));
+
+        deck.reshuffle();
+
+        assert_eq!(1, deck.discard_size());
+        assert!(matches!(deck.get_facing(), Some(Card::Normal(Color::Green, 4))));
+    }
+
+    #[test]
+    fn reshuffle_resets_wild_colors() {
+        let mut deck = Deck::new(1);
+        while deck.draw().is_some() {}
+
+        deck.set_facing(Card::Wild(Color::Blue));
+        deck.set_facing(Card::DrawFour(Color::Red));
+        deck.set_facing(Card::Normal(Color::Green, 4));
+
+        let first = deck.draw().unwrap();
+        let second = deck.draw().unwrap();
+
+        for card in [first, second] {
+            assert_eq!(Color::None, card.card.color());
+        }
+        assert!(matches!(deck.get_facing(), Some(Card::Normal(Color::Green, 4))));
+    }
+
+    #[test]
+    fn draw_exhausted_returns_none() {
+        let mut deck = Deck::new(1);
+        deck.start_game();
+
+        for _ in 0..107 {
+            assert!(deck.draw().is_some());
+        }
+
+        assert!(deck.draw().is_none());
+        assert!(deck.get_facing().is_some());
+    }
+
+    #[test]
+    fn cards_are_conserved() {
+        let mut deck = Deck::new(2);
+        deck.start_game();
+
+        // Enough draws to go through the draw pile a few times over
+        for _ in 0..1000 {
+            if let Some(card) = deck.draw() {
+                let _ = deck.play(card, Color::Red, &Rules::default());
+            }
+        }
+
+        deck.check_conservation();
+    }
+
+    #[test]
+    fn standard_wild_composition() {
+        let mut deck = Deck::new(1);
+        let mut cards = vec![];
+        while let Some(card) = deck.draw() {
+            cards.push(card.card);
+        }
+
+        assert_eq!(4, cards.iter().filter(|card| matches!(card, Card::Wild(_))).count());
+        assert_eq!(4, cards.iter().filter(|card| matches!(card, Card::DrawFour(_))).count());
+    }
+
+    #[test]
+    fn never_starts_on_draw_four() {
+        for _ in 0..100 {
+            let mut deck = Deck::new(1);
+            let first = deck.start_game().unwrap();
+
+            assert!(!matches!(first, Card::DrawFour(_)));
+            assert_eq!(107, deck.deck_size());
+        }
+    }
+
+    #[test]
+    fn card_equality_is_structural() {
+        assert_ne!(Card::Normal(Color::Red, 1), Card::Normal(Color::Red, 2));
+        assert_eq!(Card::Skip(Color::Blue), Card::Skip(Color::Blue));
+        assert_ne!(Card::Wild(Color::None), Card::Wild(Color::Red));
+
+        let hand = [Card::Normal(Color::Red, 1), Card::Normal(Color::Red, 2)];
+        assert_eq!(Some(1), hand.iter().position(|card| *card == Card::Normal(Color::Red, 2)));
+    }
+
+    #[test]
+    fn legality_follows_active_color() {
+        let rules = Rules::default();
+        let top = Card::Wild(Color::Green);
+
+        assert!(Card::Normal(Color::Green, 3).can_play_on(&top, Color::Green, &rules));
+        assert!(!Card::Normal(Color::Red, 3).can_play_on(&top, Color::Green, &rules));
+        assert!(Card::DrawFour(Color::None).can_play_on(&top, Color::Green, &rules));
+
+        let top = Card::Normal(Color::Red, 9);
+        assert!(Card::Normal(Color::Blue, 9).can_play_on(&top, Color::Red, &rules));
+        assert!(!Card::Skip(Color::Blue).can_play_on(&top, Color::Red, &rules));
+    }
+
+    #[test]
+    fn actions_only_match_by_color_without_rule() {
+        let top = Card::Skip(Color::Red);
+        let strict = Rules { match_actions: false, ..Rules::default() };
+
+        assert!(Card::Skip(Color::Blue).can_play_on(&top, Color::Red, &Rules::default()));
+        assert!(!Card::Skip(Color::Blue).can_play_on(&top, Color::Red, &strict));
+        assert!(Card::Skip(Color::Red).can_play_on(&top, Color::Red, &strict));
+    }
+
+    #[test]
+    fn physical_cards_are_distinguishable() {
+        let mut deck = Deck::new(2);
+        let mut ids = HashSet::new();
+        let mut faces = HashSet::new();
+
+        while let Some(card) = deck.draw() {
+            assert!(ids.insert(card.id));
+            faces.insert(card.card);
+        }
+
+        assert_eq!(216, ids.len());
+        assert!(faces.len() < ids.len());
+    }
+
+    fn house_rules(source: &str) -> Scripts {
+        Scripts::compile(vec![("test.rhai".into(), source.into())]).unwrap()
+    }
+
+    fn table() -> Table {
+        Table::new(vec![], None, None, Some(Card::Normal(Color::Red, 1)), Color::Red, "Forward")
+    }
+
+    #[test]
+    fn house_rules_fall_back_when_they_fail() {
+        let runaway = house_rules("fn can_play(game, card, allowed) { loop {} }");
+        assert!(runaway.can_play(&table(), Card::Normal(Color::Red, 2), true));
+        assert!(!runaway.can_play(&table(), Card::Normal(Color::Blue, 2), false));
+
+        let importer = house_rules(r#"fn can_play(game, card, allowed) { import "std" as std; true }"#);
+        assert!(!importer.can_play(&table(), Card::Normal(Color::Blue, 2), false));
+
+        assert!(Scripts::compile(vec![("eval.rhai".into(), r#"eval("1")"#.into())]).is_err());
+    }
+
+    #[test]
+    fn house_rules_ask_for_effects() {
+        let scripts = house_rules(r#"
+            fn can_play(game, card, allowed) { allowed || card.number == 0 }
+            fn on_play(game, player, card) {
+                if card.face == "Normal" && card.number == 7 {
+                    game.skip();
+                    game.draw(1, 1000);
+                    game.announce(`${game.color} ${card.number}`);
+                }
+            }
+        "#);
+        let table = table();
+
+        assert!(scripts.can_play(&table, Card::Normal(Color::Blue, 0), false));
+        assert!(!scripts.can_play(&table, Card::Skip(Color::Blue), false));
+
+        scripts.on_play(&table, 0, Card::Normal(Color::Green, 5));
+        assert!(table.take_effects().is_empty());
+
+        scripts.on_play(&table, 0, Card::Normal(Color::Green, 7));
+        assert_eq!(vec![
+            Effect::Skip,
+            Effect::Draw { seat: 1, count: MAX_DRAW },
+            Effect::Announce("Red 7".into())
+        ], table.take_effects());
+    }
+
+    #[test]
+    fn standard_definition_is_the_regular_deck() {
+        let cards = DeckDef::standard().cards();
+
+        assert_eq!(108, cards.len());
+        assert_eq!(1, cards.iter().filter(|card| **card == Card::Normal(Color::Red, 0)).count());
+        assert_eq!(2, cards.iter().filter(|card| **card == Card::Normal(Color::Green, 9)).count());
+        assert_eq!(2, cards.iter().filter(|card| **card == Card::Skip(Color::Blue)).count());
+        assert_eq!(4, cards.iter().filter(|card| **card == Card::DrawFour(Color::None)).count());
+    }
+
+    #[test]
+    fn special_definition_has_the_special_wilds() {
+        let cards = DeckDef::special().cards();
+
+        assert_eq!(112, cards.len());
+        assert_eq!(3, cards.iter().filter(|card| **card == Card::Wild(Color::None)).count());
+        assert_eq!(1, cards.iter().filter(|card| **card == Card::WildSwapHands(Color::None)).count());
+        assert_eq!(1, cards.iter().filter(|card| **card == Card::WildShuffleHands(Color::None)).count());
+        assert_eq!(3, cards.iter().filter(|card| **card == Card::WildCustomizable(Color::None)).count());
+        assert!(Decks::default().get(SPECIAL).is_some());
+    }
+
+    #[test]
+    fn decks_load_from_toml_and_json() {
+        let dir = env::temp_dir().join(format!("wunos-decks-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join("duel.toml"), r#"
+            players_per_copy = 4
+            max_copies = 2
+
+            [[cards]]
+            face = "Normal"
+            colors = ["Red", "Blue"]
+            numbers = [1, 2, 3, 4, 5]
+            count = 2
+
+            [[cards]]
+            face = "Wild"
+            count = 2
+        "#).unwrap();
+        fs::write(dir.join("reds.json"), r#"{"cards": [{"face": "Normal", "colors": ["Red"], "numbers": [0, 1, 2, 3, 4, 5, 6, 7, 8, 9], "count": 3}]}"#).unwrap();
+        fs::write(dir.join("notes.txt"), "not a deck").unwrap();
+
+        let decks = Decks::load(&dir).unwrap();
+        assert_eq!(vec!["duel", "reds", SPECIAL, STANDARD], decks.names());
+
+        let duel = decks.get("duel").unwrap();
+        assert_eq!(22, duel.cards().len());
+        assert_eq!(1, duel.copies(2, &Rules::default()));
+        assert_eq!(2, duel.copies(10, &Rules::default()));
+        assert!(duel.can_deal(2, &Rules::default()));
+        assert!(!duel.can_deal(7, &Rules::default()));
+
+        assert_eq!(44, Deck::build(duel, 2).deck_size());
+
+        fs::write(dir.join("broken.toml"), "[[cards]]\nface = \"Skip\"\nnumbers = [1]").unwrap();
+        assert!(Decks::load(&dir).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unplayable_decks_are_rejected() {
+        let mut decks = Decks::default();
+        let only = |face, colors: Vec<Color>, numbers: Vec<u8>, count| DeckDef {
+            cards: vec![CardSet { face, colors, numbers, count }],
+            players_per_copy: None,
+            min_copies: 1,
+            max_copies: None
+        };
+
+        assert!(decks.insert("plus_fours", only(Face::DrawFour, vec![], vec![], 10)).is_err());
+        assert!(decks.insert("tens", only(Face::Normal, vec![], vec![10], 1)).is_err());
+        assert!(decks.insert("colorless", only(Face::Skip, vec![Color::None], vec![], 1)).is_err());
+        assert!(decks.insert("empty", only(Face::Reverse, vec![], vec![], 0)).is_err());
+        assert!(decks.insert("colored_wilds", only(Face::Wild, vec![Color::Red], vec![], 1)).is_err());
+        assert!(decks.insert("blues", only(Face::Normal, vec![Color::Blue], vec![1, 2], 1)).is_ok());
+
+        assert_eq!(vec!["blues", SPECIAL, STANDARD], decks.names());
+    }
+
+    #[test]
+    fn flip_cards_have_a_dark_side() {
+        let mut deck = Deck::double_sided(1);
+        assert_eq!(112, deck.deck_size());
+        assert_eq!(Side::Light, deck.side());
+
+        let light: Vec<PhysicalCard> = std::iter::from_fn(|| deck.draw()).collect();
+        assert!(light.iter().all(|card| card.card.color().side() != Some(Side::Dark)));
+        assert_eq!(8, light.iter().filter(|card| matches!(card.card, Card::Flip(_))).count());
+        assert_eq!(4, light.iter().filter(|card| matches!(card.card, Card::WildDrawTwo(_))).count());
+
+        let dark: Vec<PhysicalCard> = light.iter().map(|card| deck.turn(*card)).collect();
+        assert!(dark.iter().all(|card| card.card.color().side() != Some(Side::Light)));
+        assert_eq!(8, dark.iter().filter(|card| matches!(card.card, Card::SkipEveryone(_))).count());
+        assert_eq!(4, dark.iter().filter(|card| matches!(card.card, Card::WildDrawColor(_))).count());
+        assert!(light.iter().zip(dark.iter()).all(|(light, dark)| light.id == dark.id));
+
+        // Turning a card twice puts it back how it was
+        let again: Vec<PhysicalCard> = dark.iter().map(|card| deck.turn(*card)).collect();
+        assert_eq!(light, again);
+    }
+
+    #[test]
+    fn flipping_turns_the_discard_pile_over() {
+        let mut deck = Deck::double_sided(1);
+        let first = deck.start_game().unwrap();
+        assert!(!first.is_wild_draw());
+
+        let played = deck.draw().unwrap();
+        deck.discard(played);
+        deck.flip();
+
+        assert_eq!(Side::Dark, deck.side());
+        assert_eq!(2, deck.discard_size());
+        // What was at the bottom is now on top, showing its other side
+        let top = *deck.get_facing().unwrap();
+        assert_ne!(Some(Side::Light), top.color().side());
+
+        deck.flip();
+        assert_eq!(Some(&played.card), deck.get_facing());
+        deck.check_conservation();
+    }
+
+    #[test]
+    fn flip_actions_match_their_own_kind() {
+        let rules = Rules::default();
+        let top = Card::DrawFive(Color::Pink);
+
+        assert!(Card::DrawFive(Color::Teal).can_play_on(&top, Color::Pink, &rules));
+        assert!(!Card::SkipEveryone(Color::Teal).can_play_on(&top, Color::Pink, &rules));
+        assert!(Card::WildDrawColor(Color::None).can_play_on(&top, Color::Pink, &rules));
+        assert!(Card::Flip(Color::Red).can_play_on(&Card::Flip(Color::Blue), Color::Blue, &rules));
+    }
+
+    #[test]
+    fn no_mercy_deck_composition() {
+        let cards = DeckDef::no_mercy().cards();
+        assert_eq!(160, cards.len());
+        let count = |face: fn(&Card) -> bool| cards.iter().filter(|card| face(card)).count();
+        assert_eq!(80, count(|card| matches!(card, Card::Normal(..))));
+        assert_eq!(12, count(|card| matches!(card, Card::DrawTwo(_))));
+        assert_eq!(12, count(|card| matches!(card, Card::Reverse(_))));
+        assert_eq!(12, count(|card| matches!(card, Card::Skip(_))));
+        assert_eq!(12, count(|card| matches!(card, Card::DiscardAll(_))));
+        assert_eq!(8, count(|card| matches!(card, Card::SkipEveryone(_))));
+        assert_eq!(8, count(|card| matches!(card, Card::WildReverseDrawFour(_))));
+        assert_eq!(4, count(|card| matches!(card, Card::WildDrawSix(_))));
+        assert_eq!(4, count(|card| matches!(card, Card::WildDrawTen(_))));
+        assert_eq!(8, count(|card| matches!(card, Card::WildColorRoulette(_))));
+        // The colored Draw Fours of the real deck are left out, and no plain wilds take their place
+        assert_eq!(0, count(|card| matches!(card, Card::DrawFour(_) | Card::Wild(_))));
+        // Every number shows up twice in every color
+        for color in Color::iterator() {
+            for number in 0..=9 {
+                assert_eq!(2, cards.iter().filter(|card| **card == Card::Normal(*color, number)).count());
+            }
+        }
+        assert!(cards.iter().filter(|card| card.is_wild()).all(|card| card.color() == Color::None));
+
+        // Only draw cards have a penalty, and a bigger one can always go on a smaller one
+        assert_eq!(None, Card::Skip(Color::Red).penalty());
+        assert_eq!(None, Card::WildColorRoulette(Color::None).penalty());
+        assert!(Card::WildReverseDrawFour(Color::None).penalty() > Card::DrawTwo(Color::Red).penalty());
+        assert_eq!(Some(10), Card::WildDrawTen(Color::None).penalty());
+
+        let mut deck = Deck::build(&DeckDef::no_mercy(), 1);
+        assert!(!deck.start_game().unwrap().is_wild_draw());
+    }
+
+    #[test]
+    fn buried_cards_go_under_the_top_card() {
+        let mut deck = Deck::new(1);
+        deck.start_game().unwrap();
+        let top = *deck.get_facing().unwrap();
+
+        let cards: Vec<PhysicalCard> = (0..3).filter_map(|_| deck.draw()).collect();
+        deck.bury(&cards);
+        assert_eq!(Some(&top), deck.get_facing());
+        assert_eq!(4, deck.discard_size());
+
+        let cards: Vec<PhysicalCard> = (0..3).filter_map(|_| deck.draw()).collect();
+        deck.put_back(&cards);
+        assert_eq!(104, deck.deck_size());
+        deck.check_conservation();
+    }
+
+    #[test]
+    fn special_wilds_open_like_plain_ones() {
+        for card in [Card::WildSwapHands(Color::None), Card::WildShuffleHands(Color::None), Card::WildCustomizable(Color::None)] {
+            assert!(card.is_wild());
+            assert!(!card.is_wild_draw());
+            assert_eq!(None, card.penalty());
+        }
+
+        let mut decks = Decks::default();
+        let set = |face| CardSet { face, colors: vec![], numbers: vec![], count: 4 };
+        let def = DeckDef { cards: vec![set(Face::WildSwapHands), set(Face::WildShuffleHands), set(Face::WildCustomizable)], players_per_copy: None, min_copies: 1, max_copies: None };
+        assert!(decks.insert("specials", def).is_ok());
     }
 }
@@ -2,3 +2,7 @@ pub mod state;
 pub mod res;
 pub mod client;
 pub mod game;
+pub mod config;
+pub mod app;
+
+pub use app::{context::ServerHandle, server::{WunosServer, WunosServerBuilder}};
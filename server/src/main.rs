@@ -1,27 +1,69 @@
-use std::sync::{Arc, RwLock};
-
-use server::{client::handler::handle_connection, res::err::Result, state::state_man::GameState};
-use warp::Filter;
+use server::{app::{console, logging::{self, LogHandle}}, config::settings::Config, res::err::Result, WunosServer};
+use tracing::info;
 
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let state = Arc::new(RwLock::new(GameState::new()));
+    let started = Config::load().and_then(|config| {
+        let logs = logging::init(&config.log)?;
+        Ok((WunosServer::new(config)?, logs))
+    });
 
-    let routes = warp::path::end()
-        .and(warp::ws())
-        .and(with_state(state.clone()))
-        .map(|ws: warp::ws::Ws, state: Arc<RwLock<GameState>>| {
-            ws.on_upgrade(move |socket| handle_connection(socket, state))
-        });
+    let (server, logs) = match started {
+        Ok(started) => started,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    println!("Listening on 127.0.0.1:8080");
-    warp::serve(routes).run(([127,0,0,1], 8080)).await;
+    let handle = server.handle();
+    tokio::spawn(async move {
+        stop_signal().await;
+        info!("Shutting down");
+        handle.shutdown();
+    });
 
+    tokio::spawn(console::run(server.handle(), logs.clone()));
+    tokio::spawn(reload_on_hangup(logs));
 
-    Ok(())
+    server.run().await
 }
 
-fn with_state(state: Arc<RwLock<GameState>>) -> impl Filter<Extract = (Arc<RwLock<GameState>>,), Error = std::convert::Infallible> + Clone {
-    warp::any().map(move || state.clone())
+/// Ctrl-C, or the SIGTERM sent when the host goes down for a reboot
+async fn stop_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("Couldn't listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Picks up a new `log.level` from the config on SIGHUP, so logging can be turned up on a running
+/// server without losing its games
+async fn reload_on_hangup(logs: LogHandle) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        use tracing::warn;
+
+        let mut hangup = signal(SignalKind::hangup()).expect("Couldn't listen for SIGHUP");
+        while hangup.recv().await.is_some() {
+            match Config::load().and_then(|config| logs.set_level(&config.log.level)) {
+                Ok(()) => info!(level = %logs.level(), "Log level reloaded"),
+                Err(e) => warn!(error = %e, "Couldn't reload the log level")
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = logs;
 }
@@ -1,9 +1,40 @@
-use warp::filters::ws::Message;
-
 #[derive(Debug, thiserror::Error)]
 pub enum ServerError {
-    #[error("Tokio Send Error: {0}")]
-    SendError(#[from] tokio::sync::mpsc::error::SendError<Message>),
+    #[error("Connection closed")]
+    Disconnected,
+    #[error("Client fell too far behind")]
+    SlowClient,
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+    #[error("Room is full")]
+    RoomFull,
+    #[error("No more rooms can be opened")]
+    TooManyRooms,
+    #[error("Room has closed")]
+    RoomClosed,
+    #[error("There's no room called {0}")]
+    NoSuchRoom(String),
+    #[error("Couldn't save or restore rooms: {0}")]
+    Persist(String),
+    #[error("Couldn't start the server: {0}")]
+    Bind(#[from] warp::Error)
+}
+
+impl ServerError {
+    /// A short name for what went wrong, for labelling metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerError::Disconnected => "disconnected",
+            ServerError::SlowClient => "slow_client",
+            ServerError::Config(_) => "config",
+            ServerError::RoomFull => "room_full",
+            ServerError::TooManyRooms => "too_many_rooms",
+            ServerError::RoomClosed => "room_closed",
+            ServerError::NoSuchRoom(_) => "no_such_room",
+            ServerError::Persist(_) => "persist",
+            ServerError::Bind(_) => "bind"
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, ServerError>;
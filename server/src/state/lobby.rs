@@ -0,0 +1,188 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::Serialize;
+use tokio::{sync::watch, task::JoinHandle, time::Instant};
+
+use crate::{app::{metrics::Metrics, webhooks::Notifier}, config::settings::Config, game::{card::{Card, Color}, decks::Decks, rules::Rules, script::Scripts}, res::err::{Result, ServerError}};
+
+use super::{player::Role, room::RoomHandle, save::SavedRoom, state_man::{Direction, GameState}};
+
+/// The room anyone connecting to `/` ends up in
+pub const DEFAULT_ROOM: &str = "default";
+
+/// Every open room on the server, keyed by name. Rooms are opened the first time someone joins
+/// them and close themselves once the last player leaves
+pub struct Lobby {
+    rooms: HashMap<String, RoomHandle>,
+    /// The rooms' tasks, each ending with its game if the server shut down while it was running
+    tasks: Vec<JoinHandle<Option<SavedRoom>>>,
+    shutdown: watch::Receiver<bool>,
+    metrics: Arc<Metrics>,
+    notifier: Notifier,
+    /// House rules every room plays by, if there are any
+    scripts: Option<Arc<Scripts>>,
+    /// Every deck rooms can pick from
+    decks: Arc<Decks>,
+    max_rooms: usize,
+    max_players: usize,
+    rules: Rules
+}
+
+/// A snapshot of a room, for anyone looking in from outside the game
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RoomSummary {
+    pub name: String,
+    pub players: usize,
+    pub spectators: usize,
+    pub in_game: bool,
+    pub max_players: Option<usize>
+}
+
+/// Everything public about a room and the game in it. Nobody's cards, only how many they hold
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RoomDetail {
+    #[serde(flatten)]
+    pub summary: RoomSummary,
+    /// In the order they sit
+    pub seats: Vec<SeatDetail>,
+    /// The rest are only set while a game is in progress
+    pub turn: Option<String>,
+    pub top_card: Option<Card>,
+    pub active_color: Option<Color>,
+    pub direction: Option<Direction>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SeatDetail {
+    pub name: Option<String>,
+    pub role: Role,
+    pub cards: usize,
+    /// Held since a restart, waiting for its player to come back
+    pub away: bool
+}
+
+impl RoomDetail {
+    pub(crate) fn of(name: &str, state: &GameState) -> Self {
+        let seats = state.seated()
+            .map(|(_, player)| SeatDetail {
+                name: player.get_name().map(String::from),
+                role: player.role,
+                cards: player.hand_size(),
+                away: player.is_away()
+            })
+            .collect();
+
+        let in_game = state.in_game;
+        Self {
+            summary: RoomSummary::of(name, state),
+            seats,
+            turn: state.name_of(&state.turn).filter(|_| in_game),
+            top_card: state.deck.get_facing().copied().filter(|_| in_game),
+            active_color: in_game.then_some(state.active_color),
+            direction: in_game.then(|| state.direction())
+        }
+    }
+}
+
+impl RoomSummary {
+    pub(crate) fn of(name: &str, state: &GameState) -> Self {
+        let spectators = state.players.values().filter(|player| player.is_spectator()).count();
+
+        Self {
+            name: name.to_string(),
+            players: state.num_players() - spectators,
+            spectators,
+            in_game: state.in_game,
+            max_players: state.max_players
+        }
+    }
+}
+
+impl Lobby {
+    pub fn new(config: &Config, shutdown: watch::Receiver<bool>, metrics: Arc<Metrics>, notifier: Notifier, scripts: Option<Arc<Scripts>>, decks: Arc<Decks>) -> Self {
+        Self {
+            rooms: HashMap::new(),
+            tasks: vec![],
+            shutdown,
+            metrics,
+            notifier,
+            scripts,
+            decks,
+            max_rooms: config.max_rooms,
+            max_players: config.max_players,
+            rules: config.rules.clone()
+        }
+    }
+
+    /// Finds the room called `name`, opening it if there's space for another room
+    pub fn room(&mut self, name: &str) -> Result<RoomHandle> {
+        self.rooms.retain(|_, room| !room.is_closed());
+        self.tasks.retain(|task| !task.is_finished());
+
+        if let Some(room) = self.rooms.get(name) {
+            return Ok(room.clone())
+        }
+
+        if self.rooms.len() >= self.max_rooms {
+            return Err(ServerError::TooManyRooms)
+        }
+
+        let mut state = GameState::with_settings(self.rules.clone(), self.max_players);
+        state.set_notifier(self.notifier.for_room(name));
+        state.set_scripts(self.scripts.clone());
+        state.set_decks(self.decks.clone());
+
+        let (room, task) = RoomHandle::spawn(name, state, self.shutdown.clone(), self.metrics.clone(), None);
+        self.rooms.insert(name.to_string(), room.clone());
+        self.tasks.push(task);
+
+        Ok(room)
+    }
+
+    /// Reopens rooms saved at the last shutdown. Their players get until `held_until` to come
+    /// back for their seats
+    pub fn restore(&mut self, saved: Vec<SavedRoom>, held_until: Instant) {
+        for room in saved {
+            let name = room.name.clone();
+            let mut state = GameState::restore(room);
+            state.set_notifier(self.notifier.for_room(&name));
+            state.set_scripts(self.scripts.clone());
+            state.set_decks(self.decks.clone());
+
+            let (room, task) = RoomHandle::spawn(&name, state, self.shutdown.clone(), self.metrics.clone(), Some(held_until));
+
+            self.rooms.insert(name, room);
+            self.tasks.push(task);
+        }
+    }
+
+    /// Every room task started so far, for waiting on their games once the server shuts down
+    pub fn take_tasks(&mut self) -> Vec<JoinHandle<Option<SavedRoom>>> {
+        std::mem::take(&mut self.tasks)
+    }
+
+    /// The room called `name`, if it's open
+    pub fn get(&self, name: &str) -> Option<RoomHandle> {
+        self.rooms.get(name).filter(|room| !room.is_closed()).cloned()
+    }
+
+    fn open_rooms(&self) -> impl Iterator<Item = &RoomHandle> {
+        self.rooms.values().filter(|room| !room.is_closed())
+    }
+
+    pub fn num_rooms(&self) -> usize {
+        self.open_rooms().count()
+    }
+
+    pub fn summary(&self, name: &str) -> Option<RoomSummary> {
+        self.rooms.get(name).filter(|room| !room.is_closed()).map(RoomHandle::summary)
+    }
+
+    /// Every open room, sorted by name
+    pub fn summaries(&self) -> Vec<RoomSummary> {
+        let mut rooms: Vec<RoomSummary> = self.open_rooms().map(RoomHandle::summary).collect();
+        rooms.sort_by(|a, b| a.name.cmp(&b.name));
+
+        rooms
+    }
+}
@@ -1,3 +1,8 @@
 pub mod player;
 pub mod state_man;
 pub mod msg;
+pub mod view;
+pub mod lobby;
+pub mod room;
+pub mod save;
+pub mod test;
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use warp::filters::ws::Message;
 
-use crate::game::card::Card;
+use crate::game::{card::{Card, Color, PhysicalCard, Side}, rules::{BlankWild, Teams, Variant}};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DynMessage {
@@ -18,7 +18,7 @@ impl DynMessage {
         Self { sender: None, action: Action::Message(text.into()) }
     }
 
-    pub fn draw(drawn: Card) -> Self {
+    pub fn draw(drawn: PhysicalCard) -> Self {
         Self { sender: None, action: Action::DrawnCard(drawn) }
     }
     pub fn top_card(top: Card) -> Self {
@@ -36,19 +36,86 @@ impl From<DynMessage> for Message {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Action {
     Message(String),
-    PlayCard(Card),
+    PlayCard { card: PhysicalCard, chosen_color: Option<Color> },
     AcceptPlayCard,
     DenyPlayCard,
     Win,
+    /// The game stopped without a winner. Whatever's left of the hand is thrown away
+    GameOver,
     DrawCard,
-    DrawnCard(Card),
+    DrawnCard(PhysicalCard),
+    CardsDrawn(usize),
     Start,
-    Started([Card; 7]),
+    Started([PhysicalCard; 7]),
     TopCard(Card),
     SetName(String),
     YourTurn,
+    PickColor,
+    ChooseColor(Color),
+    ColorChosen(Color),
     Skipped,
-    DrawTwo([Card; 2]),
-    DrawFour([Card; 4])
+    DrawTwo([PhysicalCard; 2]),
+    DrawFour([PhysicalCard; 4]),
+    /// Everything in a player's hand, replacing whatever they had. Sent when rejoining a game
+    Hand(Vec<PhysicalCard>),
+    /// Picks which deck the next game is played with. Only the admin can, between games
+    UseDeck(String),
+    /// Picks which variant the next game is, also only for the admin between games
+    UseVariant(Variant),
+    /// Every card in the game now shows this side. Followed by the player's turned over hand
+    Flipped(Side),
+    /// Asks who to swap hands with, out of these players
+    PickTarget(Vec<String>),
+    /// The name of the player to swap hands with
+    ChooseTarget(String),
+    /// Picks what blank wilds do from the next game on, only for the admin between games
+    UseBlankWild(BlankWild),
+    /// Picks whether the next game is played in teams, again only for the admin between games
+    UseTeams(Teams),
+    /// Everything in the sender's hand, sent to their partner when hands are open
+    PartnerHand(Vec<PhysicalCard>),
+    /// The token for the player's seat, which takes it back after a restart through `Rejoin`
+    SeatToken(String),
+    /// Takes back the seat held for `name` since a restart, which only works with its token
+    Rejoin { name: String, token: String }
+}
+
+impl Action {
+    /// A short name for the kind of action, for labelling metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Action::Message(_) => "message",
+            Action::PlayCard { .. } => "play_card",
+            Action::AcceptPlayCard => "accept_play_card",
+            Action::DenyPlayCard => "deny_play_card",
+            Action::Win => "win",
+            Action::GameOver => "game_over",
+            Action::DrawCard => "draw_card",
+            Action::DrawnCard(_) => "drawn_card",
+            Action::CardsDrawn(_) => "cards_drawn",
+            Action::Start => "start",
+            Action::Started(_) => "started",
+            Action::TopCard(_) => "top_card",
+            Action::SetName(_) => "set_name",
+            Action::YourTurn => "your_turn",
+            Action::PickColor => "pick_color",
+            Action::ChooseColor(_) => "choose_color",
+            Action::ColorChosen(_) => "color_chosen",
+            Action::Skipped => "skipped",
+            Action::DrawTwo(_) => "draw_two",
+            Action::DrawFour(_) => "draw_four",
+            Action::Hand(_) => "hand",
+            Action::UseDeck(_) => "use_deck",
+            Action::UseVariant(_) => "use_variant",
+            Action::Flipped(_) => "flipped",
+            Action::PickTarget(_) => "pick_target",
+            Action::ChooseTarget(_) => "choose_target",
+            Action::UseBlankWild(_) => "use_blank_wild",
+            Action::UseTeams(_) => "use_teams",
+            Action::PartnerHand(_) => "partner_hand",
+            Action::SeatToken(_) => "seat_token",
+            Action::Rejoin { .. } => "rejoin"
+        }
+    }
 }
 
@@ -1,38 +1,71 @@
-use std::cmp::Ordering;
-
 use rand::Rng;
-use tokio::sync::mpsc::UnboundedSender;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{game::card::Card, res::err::Result};
+use crate::{client::outbox::Outbox, game::card::{CardId, PhysicalCard}, res::err::{Result, ServerError}};
 
-use super::msg::DynMessage;
+use super::{msg::DynMessage, save::SavedPlayer};
 
 #[derive(Clone, Debug)]
 pub struct Player {
     id: Uuid,
     pub role: Role,
     txt_color: (u8, u8, u8),
-    connection: UnboundedSender<warp::ws::Message>,
+    /// `None` for a seat restored from disk that nobody has come back to yet
+    connection: Option<Outbox>,
     pub name: Option<String>,
-    hand: Vec<Card>
+    hand: Vec<PhysicalCard>,
+    /// Proves a connection is the one this seat belongs to, for taking it back after a restart
+    token: String
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Role {
     Admin,
-    User
+    User,
+    Spectator
 }
 
 impl Player {
-    pub fn new(connection: UnboundedSender<warp::ws::Message>) -> Self {
-        Self { id: Uuid::new_v4(), connection, name: None, txt_color: gen_color(), hand: vec![], role: Role::User }
+    pub fn new(connection: Outbox) -> Self {
+        Self { id: Uuid::new_v4(), connection: Some(connection), name: None, txt_color: gen_color(), hand: vec![], role: Role::User, token: new_token() }
+    }
+
+    /// Puts a saved player back in their seat, waiting for them to reconnect
+    pub fn restore(saved: SavedPlayer) -> Self {
+        Self { id: saved.id, connection: None, name: saved.name, txt_color: saved.txt_color, hand: saved.hand, role: saved.role, token: saved.token }
+    }
+
+    pub fn save(&self, id: Uuid) -> SavedPlayer {
+        SavedPlayer { id, name: self.name.clone(), role: self.role, txt_color: self.txt_color, hand: self.hand.clone(), token: self.token.clone() }
+    }
+
+    /// Whether this seat was restored and is still waiting on its player
+    pub fn is_away(&self) -> bool {
+        self.connection.is_none()
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Hands this seat over to whoever just proved they're the one sitting in it
+    pub fn reconnect(&mut self, newcomer: Player) {
+        self.connection = newcomer.connection;
     }
 
     pub fn set_admin(&mut self) {
         self.role = Role::Admin
     }
 
+    pub fn set_spectator(&mut self) {
+        self.role = Role::Spectator
+    }
+
+    pub fn is_spectator(&self) -> bool {
+        self.role == Role::Spectator
+    }
+
     pub fn get_color(&self) -> (u8, u8, u8) {
         self.txt_color
     }
@@ -51,21 +84,35 @@ impl Player {
         tmp_name
     }
 
-    pub fn send_msg(&self, message: &DynMessage) -> Result<()> {
-        self.connection.send(message.clone().into())?;
+    /// Disconnects the player once they've been sent everything already queued for them
+    pub fn kick(&self) {
+        if let Some(connection) = &self.connection {
+            connection.kick();
+        }
+    }
 
-        Ok(())
+    pub(super) fn send_msg(&self, message: &DynMessage) -> Result<()> {
+        match &self.connection {
+            Some(connection) => connection.send(message),
+            None => Err(ServerError::Disconnected)
+        }
     }
 
-    pub fn give_card(&mut self, card: Card) {
+    pub fn give_card(&mut self, card: PhysicalCard) {
         self.hand.push(card)
     }
 
-    pub fn take(&mut self, loc: usize) -> Option<Card> {
-        match self.hand.len().cmp(&loc) {
-            Ordering::Less => Some(self.hand.remove(loc)),
-            _ => None
-        }
+    pub fn take(&mut self, id: CardId) -> Option<PhysicalCard> {
+        let loc = self.hand.iter().position(|card| card.id == id)?;
+        Some(self.hand.remove(loc))
+    }
+
+    pub fn clear_hand(&mut self) {
+        self.hand.clear()
+    }
+
+    pub fn hand(&self) -> &[PhysicalCard] {
+        &self.hand
     }
 
     pub fn hand_size(&self) -> usize {
@@ -79,6 +126,11 @@ impl PartialEq for Player {
     }
 }
 
+/// A fresh seat token, too long to guess
+pub fn new_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
 fn gen_color() -> (u8, u8, u8) {
     let mut rng = rand::thread_rng();
 
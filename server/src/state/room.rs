@@ -0,0 +1,306 @@
+use std::sync::Arc;
+
+use tokio::{sync::{broadcast, mpsc, oneshot, watch}, task::JoinHandle, time::Instant};
+use tracing::{debug, debug_span, info, info_span, warn, Instrument};
+use uuid::Uuid;
+
+use crate::{app::metrics::Metrics, res::err::{Result, ServerError}};
+
+use super::{lobby::{RoomDetail, RoomSummary}, msg::Action, player::Player, save::SavedRoom, state_man::GameState, view::FeedEvent};
+
+/// How many commands can be waiting on a room before senders have to wait their turn
+const COMMAND_QUEUE: usize = 64;
+
+/// Everything a connection can ask of the room it's in
+#[derive(Debug)]
+pub enum RoomCommand {
+    Join { id: Uuid, player: Player, joined: oneshot::Sender<Result<()>> },
+    Act { id: Uuid, action: Action },
+    Leave { id: Uuid },
+    /// Asks for a look at the room from outside the game
+    Inspect { detail: oneshot::Sender<RoomDetail> },
+    /// Asks for the room as it is now and everything public that happens in it from then on
+    Watch { feed: oneshot::Sender<(RoomDetail, broadcast::Receiver<FeedEvent>)> },
+    /// Asks when `id`'s turn started, if it's their turn
+    Turn { id: Uuid, since: oneshot::Sender<Option<Instant>> },
+    /// The rest come from the server operator
+    Kick { name: String, kicked: oneshot::Sender<bool> },
+    End { ended: oneshot::Sender<bool> },
+    Announce { text: String },
+    Dump { state: oneshot::Sender<SavedRoom> }
+}
+
+/// A game running in its own task. The task is the only thing that ever touches the
+/// [`GameState`], everyone else sends it commands through a handle. Once the last player
+/// leaves the task ends and the room is closed. If the server shuts down first, the task ends
+/// with the game so it can be saved
+#[derive(Debug, Clone)]
+pub struct RoomHandle {
+    commands: mpsc::Sender<RoomCommand>,
+    summary: watch::Receiver<RoomSummary>
+}
+
+impl RoomHandle {
+    /// Seats held since a restart are given up on at `held_until`
+    pub fn spawn(
+        name: &str,
+        state: GameState,
+        shutdown: watch::Receiver<bool>,
+        metrics: Arc<Metrics>,
+        held_until: Option<Instant>
+    ) -> (Self, JoinHandle<Option<SavedRoom>>) {
+        let (commands, rx) = mpsc::channel(COMMAND_QUEUE);
+        let (summary_tx, summary) = watch::channel(RoomSummary::of(name, &state));
+
+        let span = info_span!(parent: None, "room", room = %name);
+        let task = tokio::spawn(run(name.to_string(), state, rx, summary_tx, shutdown, metrics, held_until).instrument(span));
+
+        (Self { commands, summary }, task)
+    }
+
+    pub async fn join(&self, id: Uuid, player: Player) -> Result<()> {
+        let (joined, reply) = oneshot::channel();
+        self.send(RoomCommand::Join { id, player, joined }).await?;
+
+        reply.await.map_err(|_| ServerError::RoomClosed)?
+    }
+
+    pub async fn act(&self, id: Uuid, action: Action) -> Result<()> {
+        self.send(RoomCommand::Act { id, action }).await
+    }
+
+    pub async fn leave(&self, id: Uuid) -> Result<()> {
+        self.send(RoomCommand::Leave { id }).await
+    }
+
+    /// Waits for the room to get through everything queued before this
+    pub async fn detail(&self) -> Result<RoomDetail> {
+        let (detail, reply) = oneshot::channel();
+        self.send(RoomCommand::Inspect { detail }).await?;
+
+        reply.await.map_err(|_| ServerError::RoomClosed)
+    }
+
+    /// The room as it is now, then its public events as they happen. The events stop once the
+    /// room closes, and a watcher that falls too far behind skips what it missed
+    pub async fn watch(&self) -> Result<(RoomDetail, broadcast::Receiver<FeedEvent>)> {
+        let (feed, reply) = oneshot::channel();
+        self.send(RoomCommand::Watch { feed }).await?;
+
+        reply.await.map_err(|_| ServerError::RoomClosed)
+    }
+
+    /// When the turn `id` is on started, `None` if it isn't theirs or there's no game
+    pub async fn turn_since(&self, id: Uuid) -> Result<Option<Instant>> {
+        let (since, reply) = oneshot::channel();
+        self.send(RoomCommand::Turn { id, since }).await?;
+
+        reply.await.map_err(|_| ServerError::RoomClosed)
+    }
+
+    /// Removes the player called `name`, `false` if there's nobody by that name
+    pub async fn kick(&self, name: &str) -> Result<bool> {
+        let (kicked, reply) = oneshot::channel();
+        self.send(RoomCommand::Kick { name: name.to_string(), kicked }).await?;
+
+        reply.await.map_err(|_| ServerError::RoomClosed)
+    }
+
+    /// Stops the game without a winner, `false` if there wasn't one going
+    pub async fn end(&self) -> Result<bool> {
+        let (ended, reply) = oneshot::channel();
+        self.send(RoomCommand::End { ended }).await?;
+
+        reply.await.map_err(|_| ServerError::RoomClosed)
+    }
+
+    pub async fn announce(&self, text: &str) -> Result<()> {
+        self.send(RoomCommand::Announce { text: text.to_string() }).await
+    }
+
+    /// Everything about the room, hands and deck included
+    pub async fn dump(&self) -> Result<SavedRoom> {
+        let (state, reply) = oneshot::channel();
+        self.send(RoomCommand::Dump { state }).await?;
+
+        reply.await.map_err(|_| ServerError::RoomClosed)
+    }
+
+    /// How the room looked after the last command it handled
+    pub fn summary(&self) -> RoomSummary {
+        self.summary.borrow().clone()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.commands.is_closed()
+    }
+
+    async fn send(&self, command: RoomCommand) -> Result<()> {
+        self.commands.send(command).await.map_err(|_| ServerError::RoomClosed)
+    }
+}
+
+async fn run(
+    name: String,
+    mut state: GameState,
+    mut commands: mpsc::Receiver<RoomCommand>,
+    summary: watch::Sender<RoomSummary>,
+    mut shutdown: watch::Receiver<bool>,
+    metrics: Arc<Metrics>,
+    mut held_until: Option<Instant>
+) -> Option<SavedRoom> {
+    // Whose turn it is, and since when
+    let mut turn = (None, Instant::now());
+
+    loop {
+        let mut reply = None;
+
+        tokio::select! {
+            // Checked first, so nobody's leave gets handled between the server stopping and the save
+            biased;
+
+            _ = stopped(&mut shutdown) => {
+                info!(players = state.num_players(), in_game = state.in_game, "Handing over the game to be saved");
+                return Some(state.save(&name))
+            },
+            command = commands.recv() => match command {
+                Some(RoomCommand::Join { id, player, joined }) => {
+                    let res = state.join(id, player);
+                    match &res {
+                        Ok(()) => info!(player = %id, "Player joined"),
+                        Err(e) => info!(player = %id, error = %e, "Player turned away")
+                    }
+                    reply = Some((joined, res));
+                },
+                Some(RoomCommand::Act { id, action }) => {
+                    let kind = action.kind();
+                    let reshuffles = state.deck.reshuffles();
+                    let started = Instant::now();
+
+                    // Anything logged while handling this, right down to a panic, can be traced
+                    // back to who sent it
+                    let span = debug_span!("action", player = %id, player_name = state.name_of(&id).as_deref(), action = kind);
+                    let _handling = span.enter();
+                    debug!(?action, "Handling");
+
+                    if let Err(e) = apply(&mut state, &id, action) {
+                        warn!(error = %e, "Failed to handle action");
+                        metrics.error(e.kind());
+                    }
+
+                    metrics.handled(kind, started.elapsed());
+                    // A game starting brings a fresh deck that's never been reshuffled
+                    metrics.reshuffled(state.deck.reshuffles().saturating_sub(reshuffles));
+                },
+                Some(RoomCommand::Inspect { detail }) => {
+                    let _ = detail.send(RoomDetail::of(&name, &state));
+                },
+                Some(RoomCommand::Watch { feed }) => {
+                    let _ = feed.send((RoomDetail::of(&name, &state), state.watch()));
+                },
+                Some(RoomCommand::Turn { id, since }) => {
+                    let _ = since.send(Some(turn.1).filter(|_| turn.0 == Some(id)));
+                },
+                Some(RoomCommand::Kick { name, kicked }) => match state.kick(&name) {
+                    Ok(found) => { let _ = kicked.send(found); },
+                    Err(e) => warn!(error = %e, "Failed to kick")
+                },
+                Some(RoomCommand::End { ended }) => match state.end() {
+                    Ok(stopped) => { let _ = ended.send(stopped); },
+                    Err(e) => warn!(error = %e, "Failed to end the game")
+                },
+                Some(RoomCommand::Announce { text }) => {
+                    info!(text, "Operator announcement");
+                    if let Err(e) = state.announce(&text) {
+                        warn!(error = %e, "Failed to announce");
+                    }
+                },
+                Some(RoomCommand::Dump { state: dump }) => {
+                    let _ = dump.send(state.save(&name));
+                },
+                Some(RoomCommand::Leave { id }) => {
+                    info!(player = %id, "Player left");
+                    if let Err(e) = state.leave(&id) {
+                        warn!(error = %e, "Failed to announce leave");
+                    }
+                },
+                None => return None
+            },
+            _ = held(held_until) => {
+                held_until = None;
+                info!("Giving up on seats nobody came back for");
+                if let Err(e) = state.drop_away() {
+                    warn!(error = %e, "Failed to announce leave");
+                }
+            }
+        }
+
+        let holder = Some(state.turn).filter(|_| state.in_game);
+        if holder != turn.0 {
+            turn = (holder, Instant::now());
+        }
+
+        // Published before replying, so whoever joined sees themselves in the summary
+        summary.send_replace(RoomSummary::of(&name, &state));
+        if let Some((joined, res)) = reply {
+            let _ = joined.send(res);
+        }
+
+        // Anyone still queued up gets to join before we give up on the room
+        if state.num_players() == 0 && commands.is_empty() {
+            info!("Closing, everyone has left");
+            return None
+        }
+    }
+}
+
+/// Resolves once the server starts shutting down, or never if nothing can shut it down
+async fn stopped(shutdown: &mut watch::Receiver<bool>) {
+    if shutdown.wait_for(|stop| *stop).await.is_err() {
+        std::future::pending::<()>().await
+    }
+}
+
+async fn held(until: Option<Instant>) {
+    match until {
+        Some(until) => tokio::time::sleep_until(until).await,
+        None => std::future::pending().await
+    }
+}
+
+fn apply(state: &mut GameState, player_id: &Uuid, action: Action) -> Result<()> {
+    match action {
+        Action::Message(txt) => state.chat(player_id, &txt),
+        Action::Start => state.start(player_id),
+        Action::Win => state.win(player_id),
+        Action::DrawCard => state.draw(player_id),
+        Action::PlayCard { card, chosen_color } => state.play(player_id, card, chosen_color),
+        Action::ChooseColor(color) => state.choose_color(player_id, color),
+        Action::SetName(name) => state.set_name(player_id, &name),
+        Action::Rejoin { name, token } => state.rejoin(player_id, &name, &token),
+        Action::UseDeck(name) => state.use_deck(player_id, &name),
+        Action::UseVariant(variant) => state.use_variant(player_id, variant),
+        Action::ChooseTarget(name) => state.choose_target(player_id, &name),
+        Action::UseBlankWild(effect) => state.use_blank_wild(player_id, effect),
+        Action::UseTeams(teams) => state.use_teams(player_id, teams),
+        // Everything else only ever travels from the server to a client
+        Action::DrawnCard(_)
+            | Action::CardsDrawn(_)
+            | Action::TopCard(_)
+            | Action::Started(_)
+            | Action::GameOver
+            | Action::AcceptPlayCard
+            | Action::DenyPlayCard
+            | Action::YourTurn
+            | Action::PickColor
+            | Action::ColorChosen(_)
+            | Action::DrawFour(_)
+            | Action::Skipped
+            | Action::DrawTwo(_)
+            | Action::Hand(_)
+            | Action::Flipped(_)
+            | Action::PickTarget(_)
+            | Action::PartnerHand(_)
+            | Action::SeatToken(_) => Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use std::{collections::HashSet, fs, io::ErrorKind, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{game::{card::{Color, PhysicalCard}, deck::Deck, rules::Rules}, res::err::{Result, ServerError}};
+
+use super::{player::{new_token, Role}, state_man::Direction};
+
+/// Where the rooms open at shutdown are written, inside `data_dir`
+pub const SAVE_FILE: &str = "rooms.json";
+
+/// Everything needed to pick a room back up after a restart
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedRoom {
+    pub name: String,
+    pub in_game: bool,
+    pub turn: Uuid,
+    pub deck: Deck,
+    /// In the order they sit around the table
+    pub players: Vec<SavedPlayer>,
+    pub awaiting_color: Option<Uuid>,
+    #[serde(default)]
+    pub awaiting_target: Option<Uuid>,
+    pub active_color: Color,
+    #[serde(default)]
+    pub pending_draw: usize,
+    #[serde(default)]
+    pub eliminated: HashSet<Uuid>,
+    pub rules: Rules,
+    pub max_players: Option<usize>,
+    pub direction: Direction,
+    #[serde(default)]
+    pub teams: Vec<[Uuid; 2]>,
+    #[serde(default)]
+    pub team_scores: [u32; 2]
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub role: Role,
+    pub txt_color: (u8, u8, u8),
+    pub hand: Vec<PhysicalCard>,
+    /// Seats saved before there were tokens get one nobody knows, so they can't be taken back
+    #[serde(default = "new_token")]
+    pub token: String
+}
+
+/// Writes `rooms` to the save file, leaving whatever is already there alone if there's nothing
+/// to save
+pub fn store(dir: &Path, rooms: &[SavedRoom]) -> Result<()> {
+    if rooms.is_empty() {
+        return Ok(())
+    }
+
+    let path = dir.join(SAVE_FILE);
+    let json = serde_json::to_vec(rooms).map_err(|e| ServerError::Persist(e.to_string()))?;
+
+    // Written next to the real file and moved over it, so a crash mid-write can't leave half a save
+    let partial = path.with_extension("json.partial");
+    fs::write(&partial, json)
+        .and_then(|()| fs::rename(&partial, &path))
+        .map_err(|e| ServerError::Persist(format!("couldn't write {}: {}", path.display(), e)))
+}
+
+/// Reads back the rooms saved at the last shutdown and removes the save, so they're only ever
+/// restored once. A save that can't be read is moved aside rather than keeping the server down
+pub fn load(dir: &Path) -> Result<Vec<SavedRoom>> {
+    let path = dir.join(SAVE_FILE);
+
+    let json = match fs::read(&path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(ServerError::Persist(format!("couldn't read {}: {}", path.display(), e)))
+    };
+
+    let rooms = match serde_json::from_slice(&json) {
+        Ok(rooms) => rooms,
+        Err(e) => {
+            let aside = path.with_extension("json.bad");
+            warn!(error = %e, moved_to = %aside.display(), "Ignoring unreadable {}", path.display());
+
+            fs::rename(&path, &aside)
+                .map_err(|e| ServerError::Persist(format!("couldn't move {} aside: {}", path.display(), e)))?;
+            return Ok(vec![])
+        }
+    };
+
+    fs::remove_file(&path).map_err(|e| ServerError::Persist(format!("couldn't remove {}: {}", path.display(), e)))?;
+
+    Ok(rooms)
+}
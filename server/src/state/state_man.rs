@@ -1,11 +1,23 @@
+use std::{collections::{HashMap, HashSet}, sync::Arc};
 
-use std::collections::HashMap;
-
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
-use crate::{game::deck::Deck, res::err::Result};
+use crate::{app::webhooks::{HandCount, HookEvent, RoomNotifier}, game::{card::{Card, Color, PhysicalCard}, deck::Deck, decks::{DeckDef, Decks, HAND_SIZE}, rules::{BlankWild, Rules, Teams, Variant}, script::{Effect, Scripts, Seat, Table}}, res::err::{Result, ServerError}};
+
+use super::{player::{Player, Role}, save::SavedRoom, view::{DrawReason, FeedEvent, GameEvent, Viewer}};
+
+/// How many feed events a watcher can fall behind by before it misses some
+const FEED_QUEUE: usize = 256;
 
-use super::{msg::DynMessage, player::Player};
+/// Anyone holding this many cards in a No Mercy game is out
+pub const MERCY_LIMIT: usize = 25;
+
+/// Team games are always two against two
+const PARTNER_GAME: usize = 4;
 
 
 #[derive(Default)]
@@ -13,11 +25,39 @@ pub struct GameState {
     pub in_game: bool,
     pub turn: Uuid,
     pub deck: Deck,
-    pub players: HashMap<Uuid, Player>,        
-    direction: Direction
+    pub players: HashMap<Uuid, Player>,
+    /// Everyone in the room in the order they sat down, which is the order turns go around in
+    seats: Vec<Uuid>,
+    /// Set when the game opened on a wild and we're waiting on the first player to pick its color
+    pub awaiting_color: Option<Uuid>,
+    /// Set when someone played a Wild Swap Hands and we're waiting on them to say who with
+    pub awaiting_target: Option<Uuid>,
+    /// The color the next card has to match. Usually the top card's, unless a wild set it
+    pub active_color: Color,
+    /// Cards stacked up by No Mercy draw cards, for whoever can't add to them
+    pub pending_draw: usize,
+    /// Players knocked out of a No Mercy game, who sit out until the next one
+    pub eliminated: HashSet<Uuid>,
+    pub rules: Rules,
+    /// How many players can join, `None` for no limit
+    pub max_players: Option<usize>,
+    direction: Direction,
+    /// Both pairs of partners in a team game, empty otherwise
+    pub teams: Vec<[Uuid; 2]>,
+    /// What each team has scored over every round played in teams, in the order of `teams`
+    pub team_scores: [u32; 2],
+    /// The public side of every event, for anyone watching from outside. Only set up once
+    /// someone first watches
+    feed: Option<broadcast::Sender<FeedEvent>>,
+    /// Lifecycle events for webhooks
+    notifier: RoomNotifier,
+    /// House rules, run at each hook on top of the built in ones
+    scripts: Option<Arc<Scripts>>,
+    /// Every deck `rules.deck` can name
+    decks: Arc<Decks>
 }
 
-#[derive(Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Direction {
     #[default]
     Forward,
@@ -29,6 +69,64 @@ impl GameState {
         Self::default()
     }
 
+    pub fn with_settings(rules: Rules, max_players: usize) -> Self {
+        Self { rules, max_players: Some(max_players), ..Self::default() }
+    }
+
+    /// Picks a saved room back up. Every player starts out away until they reconnect
+    pub fn restore(saved: SavedRoom) -> Self {
+        let seats = saved.players.iter().map(|player| player.id).collect();
+        let players = saved.players.into_iter()
+            .map(|player| (player.id, Player::restore(player)))
+            .collect();
+
+        Self {
+            in_game: saved.in_game,
+            turn: saved.turn,
+            deck: saved.deck,
+            players,
+            seats,
+            awaiting_color: saved.awaiting_color,
+            awaiting_target: saved.awaiting_target,
+            active_color: saved.active_color,
+            pending_draw: saved.pending_draw,
+            eliminated: saved.eliminated,
+            rules: saved.rules,
+            max_players: saved.max_players,
+            direction: saved.direction,
+            teams: saved.teams,
+            team_scores: saved.team_scores,
+            feed: None,
+            notifier: RoomNotifier::default(),
+            scripts: None,
+            decks: Arc::default()
+        }
+    }
+
+    pub fn save(&self, name: &str) -> SavedRoom {
+        let players = self.seats.iter()
+            .filter_map(|id| self.players.get(id).map(|player| player.save(*id)))
+            .collect();
+
+        SavedRoom {
+            name: name.to_string(),
+            in_game: self.in_game,
+            turn: self.turn,
+            deck: self.deck.clone(),
+            players,
+            awaiting_color: self.awaiting_color,
+            awaiting_target: self.awaiting_target,
+            active_color: self.active_color,
+            pending_draw: self.pending_draw,
+            eliminated: self.eliminated.clone(),
+            rules: self.rules.clone(),
+            max_players: self.max_players,
+            direction: self.direction,
+            teams: self.teams.clone(),
+            team_scores: self.team_scores
+        }
+    }
+
     pub fn reverse(&mut self) {
         self.direction = match &self.direction {
             Direction::Forward => Direction::Backward,
@@ -36,65 +134,1140 @@ impl GameState {
         }
     }
 
+    /// Whoever plays after `curr`, going round the table. `curr` doesn't have to be playing
+    /// anymore, so someone just knocked out can still pass the turn on
     pub fn after(&self, curr: &Uuid) -> Option<Uuid> {
-        if !self.players.contains_key(curr) {
+        let mut seats = self.seats.clone();
+
+        if let Direction::Backward = self.direction {
+            seats.reverse();
+        }
+
+        let pos = seats.iter().position(|id| id == curr)?;
+        seats.iter()
+            .cycle()
+            .skip(pos + 1)
+            .take(seats.len())
+            .find(|id| self.is_playing(id))
+            .copied()
+    }
+
+    /// Dealt into the game in progress and not knocked out of it
+    pub fn is_playing(&self, id: &Uuid) -> bool {
+        self.players.get(id).is_some_and(|player| !player.is_spectator()) && !self.eliminated.contains(id)
+    }
+
+    pub fn add_player(&mut self, id: Uuid, player: &mut Player) {
+        if self.num_players() == 0 {
+            player.set_admin();
+        }
+
+        if self.players.insert(id, player.clone()).is_none() {
+            self.seats.push(id);
+        }
+    }
+
+    /// Everyone in the room, in the order they sit
+    pub fn seated(&self) -> impl Iterator<Item = (&Uuid, &Player)> {
+        self.seats.iter().filter_map(|id| self.players.get_key_value(id))
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn set_notifier(&mut self, notifier: RoomNotifier) {
+        self.notifier = notifier;
+    }
+
+    pub fn set_scripts(&mut self, scripts: Option<Arc<Scripts>>) {
+        self.scripts = scripts;
+    }
+
+    pub fn set_decks(&mut self, decks: Arc<Decks>) {
+        self.decks = decks;
+    }
+
+    /// Every public event from now on, for as long as the game is around
+    pub fn watch(&mut self) -> broadcast::Receiver<FeedEvent> {
+        self.feed.get_or_insert_with(|| broadcast::channel(FEED_QUEUE).0).subscribe()
+    }
+
+    pub fn num_players(&self) -> usize {
+        self.players.len()
+    }
+
+    /// The only way anything reaches a player's socket. Each recipient is sent their own
+    /// projection of the event. Someone who can't be reached is left to their connection to
+    /// clean up, the game carries on for everyone else
+    pub fn emit(&self, event: GameEvent) -> Result<()> {
+        let subject = event.subject();
+        let partner = subject.and_then(|subject| self.open_partner(&subject));
+
+        if let Some(feed) = self.feed.as_ref().filter(|feed| feed.receiver_count() > 0) {
+            if let Some(public) = event.public(self) {
+                let _ = feed.send(public);
+            }
+        }
+
+        for (id, player) in self.players.iter() {
+            let viewer = if subject == Some(*id) {
+                Viewer::Owner
+            } else if player.is_spectator() {
+                Viewer::Spectator
+            } else {
+                Viewer::Opponent
+            };
+
+            let mut msgs = event.project(viewer);
+            if partner == Some(*id) {
+                msgs.extend(event.to_partner(self));
+            }
+
+            for msg in msgs {
+                if player.send_msg(&msg).is_err() {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Which team `id` is playing for, as an index into `teams`
+    pub fn team_of(&self, id: &Uuid) -> Option<usize> {
+        self.teams.iter().position(|team| team.contains(id))
+    }
+
+    /// Whoever gets to see `id`'s hand, when partners play with their hands open
+    fn open_partner(&self, id: &Uuid) -> Option<Uuid> {
+        if self.rules.teams != Teams::OpenPartners {
             return None
         }
-        match self.direction {
-            Direction::Forward => {
-            
-                let mut key_cycle = self.players.keys().cycle();
 
-                loop {
-                    let key_cmp = key_cycle.next().unwrap();
-                    if key_cmp == curr {
-                        return Some(*key_cycle.next().unwrap());
-                    }
+        self.team_of(id).and_then(|team| self.teams[team].iter().find(|partner| *partner != id).copied())
+    }
+
+    /// Both partners on a team, by name
+    fn team_name(&self, team: usize) -> String {
+        self.teams[team].iter()
+            .map(|id| self.name_of(id).unwrap_or_else(|| "Someone".into()))
+            .collect::<Vec<_>>()
+            .join(" & ")
+    }
+
+    pub(crate) fn name_of(&self, id: &Uuid) -> Option<String> {
+        self.players.get(id).and_then(|player| player.get_name().map(String::from))
+    }
+
+    pub fn join(&mut self, id: Uuid, mut player: Player) -> Result<()> {
+        // Anyone could be coming back for a seat held since the last restart
+        let holding_seats = self.players.values().any(Player::is_away);
+        if self.max_players.is_some_and(|max| self.num_players() >= max) && !holding_seats {
+            return Err(ServerError::RoomFull)
+        }
+
+        if self.in_game {
+            player.set_spectator();
+        }
+        self.add_player(id, &mut player);
+
+        match self.players[&id].role {
+            Role::Admin => self.emit(GameEvent::Notice { to: id, text: "You're admin! Please type START to start the game when you'd like".into() }),
+            Role::Spectator => self.emit(GameEvent::Notice { to: id, text: "A game is in progress, you'll be dealt in next round".into() }),
+            Role::User => Ok(())
+        }
+    }
+
+    pub fn leave(&mut self, id: &Uuid) -> Result<()> {
+        if !self.players.contains_key(id) {
+            return Ok(())
+        }
+
+        // Leaving ends their turn, which house rules hear about while they're still seated
+        let mut next = self.after(id);
+        let staying = self.seats.iter().filter(|seat| *seat != id && self.is_playing(seat)).count();
+        if let Some(next) = next.as_mut().filter(|_| self.in_game && self.turn == *id && staying >= 2) {
+            self.end_turn(id, next)?;
+            if *next == *id {
+                *next = self.after(id).expect("Next player invalid");
+            }
+        }
+
+        let Some(player) = self.players.remove(id) else {
+            return Ok(())
+        };
+        self.seats.retain(|seat| seat != id);
+        self.eliminated.remove(id);
+
+        if let Some(name) = player.name {
+            self.notifier.send(HookEvent::PlayerLeft { player: name.clone() });
+            self.emit(GameEvent::Left { name })?;
+        }
+
+        // A round needs at least two people to play it
+        if self.in_game && self.seats.iter().filter(|seat| self.is_playing(seat)).count() < 2 {
+            return self.abandon()
+        }
+
+        // Whoever's turn it already is doesn't have to wait on them anymore
+        if self.awaiting_target == Some(*id) {
+            self.awaiting_target = None;
+            if self.in_game && self.turn != *id {
+                self.emit(GameEvent::TurnStarted { player: self.turn })?;
+            }
+        }
+
+        if self.turn == *id {
+            if let Some(next) = next.filter(|next| next != id) {
+                self.turn = next;
+                if self.in_game && self.awaiting_color != Some(*id) {
+                    self.emit(GameEvent::TurnStarted { player: next })?;
                 }
             }
-            Direction::Backward => {
-                let mut key_cycle: Vec<Uuid> = self.players.clone().keys().map(|key| *key).collect();
-                key_cycle.reverse();
+        }
 
-                let mut key_cycle = key_cycle.iter().cycle();
+        // The wild on top still needs a color, so whoever's turn it is now picks it
+        if self.awaiting_color == Some(*id) {
+            self.awaiting_color = Some(self.turn).filter(|turn| turn != id);
+            if let Some(player) = self.awaiting_color.filter(|_| self.in_game) {
+                self.emit(GameEvent::ColorRequested { player })?;
+            }
+        }
 
-                loop {
-                    let key_cmp = key_cycle.next().unwrap();
-                    if key_cmp == curr {
-                        return Some(*key_cycle.next().unwrap());
-                    }
+        Ok(())
+    }
+
+    /// Ends a round there aren't enough players left for. Whoever's still in wins it
+    fn abandon(&mut self) -> Result<()> {
+        let left: Vec<Uuid> = self.seats.iter().filter(|seat| self.is_playing(seat)).copied().collect();
+        if let [last] = left[..] {
+            return self.crown(&last)
+        }
+
+        info!("Game abandoned");
+        self.notifier.send(HookEvent::RoundEnded { winner: None, hands: self.hands() });
+        self.finish();
+        self.round_ended(None)
+    }
+
+    /// Names a player, handing them their seat token the first time. Names of seats held since
+    /// the last restart are taken until their players come back with `rejoin`
+    pub fn set_name(&mut self, id: &Uuid, name: &str) -> Result<()> {
+        if self.held_seat(id, name).is_some() {
+            let text = format!("{} is being kept for someone coming back, rejoin with that seat's token to take it", name);
+            return self.emit(GameEvent::Notice { to: *id, text })
+        }
+
+        let Some(player) = self.players.get_mut(id) else {
+            return Ok(())
+        };
+        let first = player.get_name().is_none();
+        player.set_name(name);
+
+        if !first {
+            return Ok(())
+        }
+        self.notifier.send(HookEvent::PlayerJoined { player: name.to_string() });
+        let token = player.token().to_string();
+        self.emit(GameEvent::SeatToken { player: *id, token })
+    }
+
+    /// Takes back the seat held for `name` since the last restart, as long as `token` is its token
+    pub fn rejoin(&mut self, id: &Uuid, name: &str, token: &str) -> Result<()> {
+        match self.held_seat(id, name) {
+            Some(seat) if self.players[&seat].token() == token => self.reclaim(seat, id),
+            _ => {
+                warn!(player = %id, name, "Tried to take a seat without its token");
+                self.emit(GameEvent::Notice { to: *id, text: format!("There's no seat kept for {} with that token", name) })
+            }
+        }
+    }
+
+    /// The seat held for `name` since the last restart, if someone other than `id` holds it
+    fn held_seat(&self, id: &Uuid, name: &str) -> Option<Uuid> {
+        self.players.iter()
+            .find(|(seat, player)| *seat != id && player.is_away() && player.get_name() == Some(name))
+            .map(|(seat, _)| *seat)
+    }
+
+    /// Moves the held seat over to the connection `id`, along with its hand, role and turn
+    fn reclaim(&mut self, seat: Uuid, id: &Uuid) -> Result<()> {
+        let Some(newcomer) = self.players.remove(id) else {
+            return Ok(())
+        };
+        let mut player = self.players.remove(&seat).expect("Held seat was just found");
+
+        info!(seat = %seat, player = %id, "Seat reclaimed after a restart");
+        player.reconnect(newcomer);
+        self.players.insert(*id, player);
+
+        self.seats.retain(|sat| sat != id);
+        for sat in self.seats.iter_mut().filter(|sat| **sat == seat) {
+            *sat = *id;
+        }
+        if self.turn == seat {
+            self.turn = *id;
+        }
+        if self.awaiting_color == Some(seat) {
+            self.awaiting_color = Some(*id);
+        }
+        if self.awaiting_target == Some(seat) {
+            self.awaiting_target = Some(*id);
+        }
+        if self.eliminated.remove(&seat) {
+            self.eliminated.insert(*id);
+        }
+        for partner in self.teams.iter_mut().flatten().filter(|partner| **partner == seat) {
+            *partner = *id;
+        }
+
+        let hand = self.players[id].hand().to_vec();
+        let top = self.deck.get_facing().copied().filter(|_| self.in_game);
+        self.emit(GameEvent::Rejoined { player: *id, name: self.name_of(id), hand, top, color: self.active_color, side: self.deck.side() })?;
+
+        if !self.in_game {
+            return Ok(())
+        }
+
+        if self.awaiting_color == Some(*id) {
+            self.emit(GameEvent::ColorRequested { player: *id })
+        } else if self.awaiting_target == Some(*id) {
+            self.emit(GameEvent::TargetRequested { player: *id, targets: self.targets(id) })
+        } else if self.turn == *id && self.awaiting_color.is_none() && self.awaiting_target.is_none() {
+            self.emit(GameEvent::TurnStarted { player: *id })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Removes the player called `name` from the room and closes their connection. `false` if
+    /// nobody here goes by that name
+    pub fn kick(&mut self, name: &str) -> Result<bool> {
+        let Some(id) = self.players.iter().find(|(_, player)| player.get_name() == Some(name)).map(|(id, _)| *id) else {
+            return Ok(false)
+        };
+
+        info!(player = %id, name, "Kicked");
+        self.emit(GameEvent::Notice { to: id, text: "You've been removed by the server operator".into() })?;
+        if let Some(player) = self.players.get(&id) {
+            player.kick();
+        }
+
+        self.leave(&id).map(|()| true)
+    }
+
+    /// Stops the game in progress without a winner, leaving everyone seated for the next one.
+    /// `false` if there was no game to end
+    pub fn end(&mut self) -> Result<bool> {
+        if !self.in_game {
+            return Ok(false)
+        }
+
+        info!("Game ended by the operator");
+        self.notifier.send(HookEvent::RoundEnded { winner: None, hands: self.hands() });
+        self.finish();
+
+        self.emit(GameEvent::Announcement("The game was ended by the server operator".into()))?;
+        self.emit(GameEvent::Ended)?;
+        self.round_ended(None).map(|()| true)
+    }
+
+    /// Says something to everyone in the room on the server's behalf
+    pub fn announce(&self, text: &str) -> Result<()> {
+        self.emit(GameEvent::Announcement(text.into()))
+    }
+
+    /// How many cards everyone dealt in is holding
+    fn hands(&self) -> Vec<HandCount> {
+        self.seated()
+            .filter(|(_, player)| !player.is_spectator())
+            .map(|(_, player)| HandCount { player: player.get_name().map(String::from), cards: player.hand_size() })
+            .collect()
+    }
+
+    /// Puts the room back to waiting for the next game
+    fn finish(&mut self) {
+        self.in_game = false;
+        self.awaiting_color = None;
+        self.awaiting_target = None;
+        self.pending_draw = 0;
+        self.eliminated.clear();
+        self.teams.clear();
+
+        // Anyone who was watching gets dealt in next time around
+        for player in self.players.values_mut().filter(|player| player.is_spectator()) {
+            player.role = Role::User;
+        }
+    }
+
+    /// Gives up on every seat still held since the last restart
+    pub fn drop_away(&mut self) -> Result<()> {
+        let away: Vec<Uuid> = self.seats.iter()
+            .filter(|id| self.players.get(id).is_some_and(Player::is_away))
+            .copied()
+            .collect();
+
+        for id in away {
+            self.leave(&id)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn chat(&self, from: &Uuid, text: &str) -> Result<()> {
+        // Only named players get to talk
+        match self.name_of(from) {
+            Some(name) => self.emit(GameEvent::Chat { from: *from, name, text: text.into() }),
+            None => Ok(())
+        }
+    }
+
+    /// Switches the room over to the deck called `name` for the next game
+    pub fn use_deck(&mut self, by: &Uuid, name: &str) -> Result<()> {
+        if self.in_game || self.players.get(by).map(|player| player.role) != Some(Role::Admin) {
+            return Ok(())
+        }
+
+        if self.decks.get(name).is_none() {
+            let text = format!("There's no deck called {}, try one of {}", name, self.decks.names().join(", "));
+            return self.emit(GameEvent::Notice { to: *by, text })
+        }
+
+        self.rules.deck = name.to_string();
+        self.emit(GameEvent::Announcement(format!("The next game will be played with the {} deck", name)))
+    }
+
+    /// Switches the room over to another variant of UNO for the next game
+    pub fn use_variant(&mut self, by: &Uuid, variant: Variant) -> Result<()> {
+        if self.in_game || self.players.get(by).map(|player| player.role) != Some(Role::Admin) {
+            return Ok(())
+        }
+
+        self.rules.variant = variant;
+        self.emit(GameEvent::Announcement(format!("The next game will be {}", variant)))
+    }
+
+    /// Decides what blank wilds do from the next game on
+    pub fn use_blank_wild(&mut self, by: &Uuid, effect: BlankWild) -> Result<()> {
+        if self.in_game || self.players.get(by).map(|player| player.role) != Some(Role::Admin) {
+            return Ok(())
+        }
+
+        self.rules.blank_wild = effect;
+        self.emit(GameEvent::Announcement(format!("Blank wilds will be played as a {}", effect)))
+    }
+
+    /// Decides whether the next game is played in teams. Scores start over whenever this changes
+    pub fn use_teams(&mut self, by: &Uuid, teams: Teams) -> Result<()> {
+        if self.in_game || self.players.get(by).map(|player| player.role) != Some(Role::Admin) {
+            return Ok(())
+        }
+
+        if self.rules.teams != teams {
+            self.team_scores = [0; 2];
+        }
+        self.rules.teams = teams;
+
+        self.emit(GameEvent::Announcement(match teams {
+            Teams::Solo => "The next game is every player for themselves".into(),
+            Teams::Partners => "The next game is two against two, partners sitting across from each other".into(),
+            Teams::OpenPartners => "The next game is two against two, and partners can see each other's hands".into()
+        }))
+    }
+
+    /// The deck the room's rules ask for. Rooms restored after the deck was taken away go back to
+    /// the standard one
+    fn deck_def(&self) -> DeckDef {
+        match self.decks.get(&self.rules.deck) {
+            Some(def) => def.clone(),
+            None => {
+                warn!(deck = %self.rules.deck, "Deck is gone, playing with the standard one");
+                DeckDef::standard()
+            }
+        }
+    }
+
+    pub fn start(&mut self, by: &Uuid) -> Result<()> {
+        // Double check they are admin, if so start game
+        if self.in_game || self.players.get(by).map(|player| player.role) != Some(Role::Admin) {
+            return Ok(())
+        }
+
+        let seated: Vec<Uuid> = self.players.iter()
+            .filter(|(_, player)| !player.is_spectator())
+            .map(|(id, _)| *id)
+            .collect();
+
+        // Seats alternate between the two teams, so partners never play one after the other
+        let teams = match (self.rules.teams, seated.len()) {
+            (Teams::Solo, _) => vec![],
+            (_, PARTNER_GAME) => {
+                let order: Vec<Uuid> = self.seats.iter().filter(|id| seated.contains(id)).copied().collect();
+                vec![[order[0], order[2]], [order[1], order[3]]]
+            },
+            (_, players) => {
+                let text = format!("Team games need exactly {} players, there are {}", PARTNER_GAME, players);
+                return self.emit(GameEvent::Notice { to: *by, text })
+            }
+        };
+
+        // Ensures we have enough copies of uno for all of our friends to play
+        let deck = match self.rules.variant {
+            Variant::Classic => {
+                let def = self.deck_def();
+                if !def.can_deal(seated.len(), &self.rules) {
+                    let text = format!("The {} deck doesn't have enough cards for {} players", self.rules.deck, seated.len());
+                    return self.emit(GameEvent::Notice { to: *by, text })
                 }
+
+                Deck::build(&def, def.copies(seated.len(), &self.rules))
+            },
+            // These bring their own decks, which always have enough to go around
+            Variant::Flip => Deck::double_sided(self.rules.deck_copies(seated.len())),
+            Variant::NoMercy => Deck::build(&DeckDef::no_mercy(), self.rules.deck_copies(seated.len()))
+        };
+
+        self.in_game = true;
+        self.direction = Direction::Forward;
+        self.pending_draw = 0;
+        self.eliminated.clear();
+        self.teams = teams;
+        self.deck = deck;
+        info!(players = seated.len(), variant = ?self.rules.variant, deck = %self.rules.deck, cards = self.deck.deck_size(), "Game started");
+
+        for id in seated.iter() {
+            // Draw 7 cards per player
+            let hand: [PhysicalCard; HAND_SIZE] = std::array::from_fn(|_| {
+                self.deck.draw().expect("A fresh deck always has enough cards to deal")
+            });
+
+            if let Some(player) = self.players.get_mut(id) {
+                player.clear_hand();
             }
+            self.give(id, &hand);
+
+            self.emit(GameEvent::Dealt { player: *id, name: self.name_of(id), hand })?;
+        }
+
+        let players = self.seated()
+            .filter(|(_, player)| !player.is_spectator())
+            .map(|(_, player)| player.get_name().map(String::from))
+            .collect();
+        self.notifier.send(HookEvent::GameStarted { players });
+
+        let top = self.deck.start_game().expect("A fresh deck always has a card to flip");
+        self.deck.check_conservation();
+        self.emit(GameEvent::TopCard { played_by: None, card: top })?;
+
+        if !self.teams.is_empty() {
+            self.emit(GameEvent::Announcement(format!("{} are playing against {}", self.team_name(0), self.team_name(1))))?;
+        }
+
+        let first = *seated.choose(&mut thread_rng()).expect("The admin is always seated");
+        if let Some(name) = self.name_of(&first) {
+            self.emit(GameEvent::Announcement(format!("{} goes first", name)))?;
+        }
+
+        self.open_with(first, top)
+    }
+
+    /// Applies the effect of the first card flipped to the first player's turn
+    pub(crate) fn open_with(&mut self, first: Uuid, top: Card) -> Result<()> {
+        self.turn = first;
+        self.active_color = top.color();
+
+        match top {
+            wild if wild.is_wild() => {
+                // Nobody can play until the first player decides what color this is
+                self.awaiting_color = Some(first);
+                return self.emit(GameEvent::ColorRequested { player: first })
+            },
+            Card::Skip(_) => {
+                self.emit(GameEvent::Skipped { player: first })?;
+                self.turn = self.after(&first).expect("Next player invalid");
+            },
+            Card::Reverse(_) => {
+                // The dealer, sitting before the first player, ends up going first instead
+                self.reverse();
+                self.turn = self.after(&first).expect("Next player invalid");
+            },
+            Card::DrawTwo(_) => {
+                self.force_draw(&first, 2, DrawReason::DrawTwo)?;
+                self.turn = self.after(&first).expect("Next player invalid");
+            },
+            Card::DrawOne(_) => {
+                self.force_draw(&first, 1, DrawReason::Penalty)?;
+                self.turn = self.after(&first).expect("Next player invalid");
+            },
+            Card::Flip(_) => {
+                // The table turns over before anyone plays, and the card's other side opens instead.
+                // A Flip on both sides just stays put
+                self.flip(None)?;
+                let top = *self.deck.get_facing().expect("The opening card is still on the table");
+                if !matches!(top, Card::Flip(_)) {
+                    return self.open_with(first, top)
+                }
+            },
+            _ => {}
+        }
+
+        self.emit(GameEvent::TurnStarted { player: self.turn })
+    }
+
+    pub fn choose_color(&mut self, by: &Uuid, color: Color) -> Result<()> {
+        if self.awaiting_color != Some(*by) || self.awaiting_target.is_some() || color.side() != Some(self.deck.side()) {
+            return Ok(())
+        }
+
+        self.active_color = color;
+        self.awaiting_color = None;
+
+        self.emit(GameEvent::ColorChosen { chosen_by: self.name_of(by), color })?;
+
+        // Picking for a Color Roulette means drawing until that color turns up, which is their turn
+        if let Some(Card::WildColorRoulette(_)) = self.deck.get_facing() {
+            self.draw_until(by, color)?;
+            self.deck.check_conservation();
+            if !self.in_game {
+                return Ok(())
+            }
+
+            return self.advance_turn(by)
+        }
+
+        self.emit(GameEvent::TurnStarted { player: *by })
+    }
+
+    pub fn draw(&mut self, by: &Uuid) -> Result<()> {
+        // Draw card for user and send it back as a drawn card
+        if !self.in_game || self.turn != *by || self.awaiting_color.is_some() || self.awaiting_target.is_some() {
+            return Ok(())
+        }
+
+        // Nothing left to stack, so they take the lot and that's their turn
+        if self.pending_draw > 0 {
+            let count = std::mem::take(&mut self.pending_draw);
+            self.force_draw(by, count, DrawReason::Penalty)?;
+            self.deck.check_conservation();
+            if !self.in_game {
+                return Ok(())
+            }
+
+            return self.advance_turn(by)
+        }
+
+        let Some(card) = self.deck.draw() else {
+            return self.emit(GameEvent::Notice { to: *by, text: "There are no cards left to draw".into() })
         };
+        self.deck.check_conservation();
+        self.give(by, &[card]);
+
+        self.emit(GameEvent::Drew { player: *by, name: self.name_of(by), cards: vec![card], reason: DrawReason::Draw })?;
+        self.mercy(by)?;
+
+        if let Some((scripts, seat)) = self.scripts.clone().zip(self.seat_of(by)) {
+            let table = self.table(self.after(by));
+            scripts.on_draw(&table, seat, card.card);
+            self.apply(table.take_effects(), by, None)?;
+        }
+
+        // Drawing their way out of the game ends their turn
+        if self.in_game && self.eliminated.contains(&self.turn) {
+            return self.advance_turn(by)
+        }
+
+        Ok(())
     }
 
-    pub fn send_msg(&mut self, player_id: &Uuid, msg: &DynMessage) -> Result<()> {
-        self.players[player_id].send_msg(&msg)
+    pub fn play(&mut self, by: &Uuid, card: PhysicalCard, chosen_color: Option<Color>) -> Result<()> {
+        // Check if card can be played on top of current deck, if so do it and return a success.
+        // If not then return a failure
+        if !self.in_game || self.turn != *by || self.awaiting_color.is_some() || self.awaiting_target.is_some() {
+            return Ok(())
+        }
+
+        // Only cards actually in the player's hand can be played
+        let Some(held) = self.players.get_mut(by).and_then(|player| player.take(card.id)) else {
+            debug!(card = ?card.id, "Tried to play a card they don't hold");
+            return self.emit(GameEvent::PlayDenied { player: *by })
+        };
+
+        // Wilds aren't playable without saying what color they are, out of the colors on the side
+        // that's showing. Everything else has its own
+        let color = match (held.card, chosen_color) {
+            // Whoever's next picks the color for this one
+            (Card::WildColorRoulette(_), _) => Color::None,
+            (wild, Some(color)) if wild.is_wild() && color.side() == Some(self.deck.side()) => color,
+            (wild, _) if wild.is_wild() => {
+                self.give(by, &[held]);
+                debug!(card = %held, "Wild played without a color");
+                return self.emit(GameEvent::PlayDenied { player: *by })
+            },
+            (face, _) => face.color()
+        };
+
+        let allowed = match self.deck.get_facing() {
+            // With draw cards stacked up, the only way out is to add one at least as big
+            Some(top) if self.pending_draw > 0 => held.card.penalty().is_some_and(|penalty| Some(penalty) >= top.penalty()),
+            Some(top) => held.card.can_play_on(top, self.active_color, &self.rules),
+            None => false
+        };
+        let allowed = match &self.scripts {
+            Some(scripts) if self.deck.get_facing().is_some() => scripts.can_play(&self.table(self.after(by)), held.card, allowed),
+            _ => allowed
+        };
+
+        if !allowed {
+            self.give(by, &[held]);
+            debug!(card = %held, "Card doesn't go on the top card");
+            return self.emit(GameEvent::PlayDenied { player: *by })
+        }
+        let top = *self.deck.discard(held);
+        self.active_color = color;
+
+        let mut next = self.after(by).expect("Next player invalid");
+
+        // Blank wilds stay blank on the discard pile, but play like whatever the rules say
+        let effect = match top {
+            Card::WildCustomizable(_) => self.rules.blank_wild.card(color),
+            card => card
+        };
+
+        match effect {
+            // No Mercy draw cards stack up instead, until someone can't add to them
+            card if self.rules.variant == Variant::NoMercy && card.penalty().is_some() => {
+                if let Card::WildReverseDrawFour(_) = card {
+                    self.reverse();
+                    next = self.after(by).expect("Next player invalid");
+                }
+                self.pending_draw += card.penalty().unwrap_or_default();
+            },
+            Card::Skip(_) => {
+                self.emit(GameEvent::Skipped { player: next })?;
+                next = self.after(&next).expect("Next player invalid");
+            },
+            Card::DrawTwo(_) => {
+                self.force_draw(&next, 2, DrawReason::DrawTwo)?;
+                next = self.after(&next).expect("Next player invalid");
+            },
+            Card::DrawFour(_) => {
+                self.force_draw(&next, 4, DrawReason::DrawFour)?;
+                next = self.after(&next).expect("Next player invalid");
+            },
+            Card::Reverse(_) => {
+                self.reverse();
+                next = self.after(by).expect("Next player invalid");
+            },
+            Card::DrawOne(_) => {
+                self.force_draw(&next, 1, DrawReason::Penalty)?;
+                next = self.after(&next).expect("Next player invalid");
+            },
+            Card::WildDrawTwo(_) => {
+                self.force_draw(&next, 2, DrawReason::DrawTwo)?;
+                next = self.after(&next).expect("Next player invalid");
+            },
+            Card::DrawFive(_) => {
+                self.force_draw(&next, 5, DrawReason::Penalty)?;
+                next = self.after(&next).expect("Next player invalid");
+            },
+            Card::WildDrawColor(_) => {
+                self.draw_until(&next, color)?;
+                next = self.after(&next).expect("Next player invalid");
+            },
+            Card::WildDrawSix(_) => {
+                self.force_draw(&next, 6, DrawReason::Penalty)?;
+                next = self.after(&next).expect("Next player invalid");
+            },
+            Card::WildDrawTen(_) => {
+                self.force_draw(&next, 10, DrawReason::Penalty)?;
+                next = self.after(&next).expect("Next player invalid");
+            },
+            Card::WildReverseDrawFour(_) => {
+                self.reverse();
+                next = self.after(by).expect("Next player invalid");
+                self.force_draw(&next, 4, DrawReason::DrawFour)?;
+                next = self.after(&next).expect("Next player invalid");
+            },
+            Card::SkipEveryone(_) => {
+                let skipped: Vec<Uuid> = self.seats.iter()
+                    .filter(|id| *id != by && self.is_playing(id))
+                    .copied()
+                    .collect();
+                for player in skipped {
+                    self.emit(GameEvent::Skipped { player })?;
+                }
+                next = *by;
+            },
+            _ => {}
+        }
+
+        self.deck.check_conservation();
+
+        self.emit(GameEvent::PlayAccepted { player: *by })?;
+        self.emit(GameEvent::TopCard { played_by: self.name_of(by), card: top })?;
+        if top.color() == Color::None && color != Color::None {
+            self.emit(GameEvent::ColorChosen { chosen_by: self.name_of(by), color })?;
+        }
+        match top {
+            Card::Flip(_) => self.flip(Some(by))?,
+            Card::DiscardAll(color) => self.discard_all(by, color)?,
+            _ => {}
+        }
+
+        if let Some((scripts, seat)) = self.scripts.clone().zip(self.seat_of(by)) {
+            let table = self.table(Some(next));
+            scripts.on_play(&table, seat, top);
+            self.apply(table.take_effects(), by, Some(&mut next))?;
+        }
+
+        // Going out ends the round before the turn does
+        if self.is_playing(by) && self.players.get(by).map(|player| player.hand_size()) == Some(0) {
+            return self.win(by)
+        }
+        self.end_turn(by, &mut next)?;
+
+        // A house rule can have someone draw their way out of the game
+        if !self.in_game {
+            return Ok(())
+        }
+        self.turn = next;
+
+        match effect {
+            // Nobody goes until they've picked who to swap with
+            Card::WildSwapHands(_) => {
+                let targets = self.targets(by);
+                if !targets.is_empty() {
+                    self.awaiting_target = Some(*by);
+                    return self.emit(GameEvent::TargetRequested { player: *by, targets })
+                }
+            },
+            Card::WildShuffleHands(_) => self.shuffle_hands(by)?,
+            _ => {}
+        }
+
+        // A Color Roulette, or flipping to a wild, leaves a wild on top with no color yet, which
+        // whoever's next gets to pick
+        if self.active_color == Color::None {
+            self.awaiting_color = Some(next);
+            return self.emit(GameEvent::ColorRequested { player: next })
+        }
+
+        if self.pending_draw > 0 {
+            let text = format!("{} has to stack a draw card or take {} cards", self.name_of(&next).as_deref().unwrap_or("Someone"), self.pending_draw);
+            self.emit(GameEvent::Announcement(text))?;
+        }
+        self.emit(GameEvent::TurnStarted { player: next })
     }
 
-    pub fn add_player(&mut self, id: Uuid, player: &mut Player) {
-        if self.num_players() == 0 {
-            player.set_admin();
+    /// Lets house rules know `by`'s turn is over, which may change who goes `next`
+    fn end_turn(&mut self, by: &Uuid, next: &mut Uuid) -> Result<()> {
+        if !self.in_game {
+            return Ok(())
+        }
+
+        if let Some((scripts, seat)) = self.scripts.clone().zip(self.seat_of(by)) {
+            let table = self.table(Some(*next));
+            scripts.on_turn_end(&table, seat);
+            self.apply(table.take_effects(), by, Some(next))?;
         }
 
-        self.players.insert(id, player.clone());
+        if self.in_game && self.eliminated.contains(next) {
+            *next = self.after(next).expect("Next player invalid");
+        }
+        Ok(())
     }
 
-    pub fn num_players(&self) -> usize {
-        self.players.len()
+    /// Ends `by`'s turn and starts whoever's after them
+    fn advance_turn(&mut self, by: &Uuid) -> Result<()> {
+        let mut next = self.after(by).expect("Next player invalid");
+        self.end_turn(by, &mut next)?;
+        if !self.in_game {
+            return Ok(())
+        }
+
+        self.turn = next;
+        self.emit(GameEvent::TurnStarted { player: next })
     }
 
-    pub fn broadcast(&self, msg: DynMessage) -> Result<()> {
-        self.broadcast_but(msg, &[])    
+    /// Turns every card on the table over, the piles and everyone's hands alike
+    fn flip(&mut self, by: Option<&Uuid>) -> Result<()> {
+        self.deck.flip();
+
+        let mut turned = vec![];
+        for (id, player) in self.players.iter_mut() {
+            let hand: Vec<PhysicalCard> = player.hand().iter().map(|card| self.deck.turn(*card)).collect();
+            player.clear_hand();
+            for card in hand.iter() {
+                player.give_card(*card);
+            }
+            turned.push((*id, hand));
+        }
+
+        let top = *self.deck.get_facing().expect("The card just played is still on the table");
+        self.active_color = top.color();
+        info!(side = %self.deck.side(), "Table flipped");
+
+        self.emit(GameEvent::Flipped { flipped_by: by.and_then(|by| self.name_of(by)), side: self.deck.side(), top })?;
+        for (player, hand) in turned {
+            self.emit(GameEvent::HandChanged { player, hand })?;
+        }
+
+        Ok(())
     }
 
-    pub fn broadcast_but(&self, msg: DynMessage, without: &[Uuid]) -> Result<()> {
-        let connections = &self.players;
+    /// Swaps hands with the player called `name`, for whoever just played a Wild Swap Hands
+    pub fn choose_target(&mut self, by: &Uuid, name: &str) -> Result<()> {
+        if !self.in_game || self.awaiting_target != Some(*by) {
+            return Ok(())
+        }
+
+        let target = self.seats.iter()
+            .find(|id| *id != by && self.is_playing(id) && self.name_of(id).as_deref() == Some(name))
+            .copied();
+        let Some(target) = target else {
+            let text = format!("You can't swap with {}, pick one of {}", name, self.targets(by).join(", "));
+            return self.emit(GameEvent::Notice { to: *by, text })
+        };
+        self.awaiting_target = None;
+
+        let mine = self.players[by].hand().to_vec();
+        let theirs = self.players[&target].hand().to_vec();
+        self.set_hand(by, theirs.clone());
+        self.set_hand(&target, mine.clone());
+
+        self.emit(GameEvent::Announcement(format!("{} swapped hands with {}", self.name_of(by).as_deref().unwrap_or("Someone"), name)))?;
+        self.emit(GameEvent::HandChanged { player: *by, hand: theirs })?;
+        self.emit(GameEvent::HandChanged { player: target, hand: mine })?;
 
-        for (_, player) in connections.iter().filter(|(id, _)| !without.contains(id)) {
-            player.send_msg(&msg)?;
+        self.emit(GameEvent::TurnStarted { player: self.turn })
+    }
+
+    /// Everyone `by` could swap hands with, by name
+    fn targets(&self, by: &Uuid) -> Vec<String> {
+        self.seats.iter()
+            .filter(|id| *id != by && self.is_playing(id))
+            .filter_map(|id| self.name_of(id))
+            .collect()
+    }
+
+    /// Gathers up everyone's hands and deals them back out one at a time, starting with whoever
+    /// plays after `by`
+    fn shuffle_hands(&mut self, by: &Uuid) -> Result<()> {
+        let mut order = vec![];
+        let mut seat = self.after(by);
+        while let Some(id) = seat.filter(|id| !order.contains(id)) {
+            order.push(id);
+            seat = self.after(&id);
+        }
+
+        let mut cards: Vec<PhysicalCard> = order.iter()
+            .filter_map(|id| self.players.get(id))
+            .flat_map(|player| player.hand().iter().copied())
+            .collect();
+        cards.shuffle(&mut thread_rng());
+
+        let mut hands = vec![vec![]; order.len()];
+        for (i, card) in cards.into_iter().enumerate() {
+            hands[i % order.len()].push(card);
+        }
+
+        self.emit(GameEvent::Announcement(format!("{} shuffled everyone's hands together", self.name_of(by).as_deref().unwrap_or("Someone"))))?;
+        for (id, hand) in order.into_iter().zip(hands) {
+            self.set_hand(&id, hand.clone());
+            self.emit(GameEvent::HandChanged { player: id, hand })?;
         }
 
         Ok(())
     }
+
+    /// Replaces everything in a player's hand
+    fn set_hand(&mut self, id: &Uuid, hand: Vec<PhysicalCard>) {
+        if let Some(player) = self.players.get_mut(id) {
+            player.clear_hand();
+        }
+        self.give(id, &hand);
+    }
+
+    /// Puts every other card of `color` in `by`'s hand down along with the Discard All they played
+    fn discard_all(&mut self, by: &Uuid, color: Color) -> Result<()> {
+        let Some(player) = self.players.get_mut(by) else {
+            return Ok(())
+        };
+
+        let matching: Vec<_> = player.hand().iter()
+            .filter(|held| held.card.color() == color)
+            .map(|held| held.id)
+            .collect();
+        let discarded: Vec<PhysicalCard> = matching.into_iter().filter_map(|id| player.take(id)).collect();
+        let hand = player.hand().to_vec();
+
+        self.deck.bury(&discarded);
+        self.deck.check_conservation();
+
+        let name = self.name_of(by);
+        self.emit(GameEvent::Announcement(format!("{} discarded {} more {} cards", name.as_deref().unwrap_or("Someone"), discarded.len(), color)))?;
+        self.emit(GameEvent::HandChanged { player: *by, hand })
+    }
+
+    /// Where `id` sits, counting from the first seat taken
+    fn seat_of(&self, id: &Uuid) -> Option<usize> {
+        self.seats.iter().position(|seat| seat == id)
+    }
+
+    /// The game as house rules see it, partway through the turn of whoever's turn it is
+    fn table(&self, next: Option<Uuid>) -> Table {
+        let seats = self.seats.iter()
+            .filter_map(|id| self.players.get(id))
+            .map(|player| Seat { name: player.get_name().map(String::from), cards: player.hand_size(), spectator: player.is_spectator() })
+            .collect();
+
+        Table::new(
+            seats,
+            self.seat_of(&self.turn).filter(|_| self.in_game),
+            next.and_then(|next| self.seat_of(&next)),
+            self.deck.get_facing().copied().filter(|_| self.in_game),
+            self.active_color,
+            &format!("{:?}", self.direction)
+        )
+    }
+
+    /// Carries out what a house rule asked for during `by`'s turn. Skips and reverses change who
+    /// goes `next`, so they're only honoured while a turn is ending
+    fn apply(&mut self, effects: Vec<Effect>, by: &Uuid, mut next: Option<&mut Uuid>) -> Result<()> {
+        for effect in effects {
+            match (effect, next.as_deref_mut()) {
+                (Effect::Announce(text), _) => self.emit(GameEvent::Announcement(text))?,
+                (Effect::Draw { seat, count }, _) if self.in_game => {
+                    let victim = self.seats.get(seat).copied().filter(|id| self.is_playing(id));
+                    if let Some(victim) = victim {
+                        self.force_draw(&victim, count, DrawReason::Draw)?;
+                        self.deck.check_conservation();
+                    }
+                },
+                (Effect::Skip, Some(next)) => {
+                    self.emit(GameEvent::Skipped { player: *next })?;
+                    *next = self.after(next).expect("Next player invalid");
+                },
+                (Effect::Reverse, Some(next)) => {
+                    self.reverse();
+                    *next = self.after(by).expect("Next player invalid");
+                },
+                (effect, _) => debug!(?effect, "House rule effect doesn't apply here")
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lets house rules know the game is over, once everyone's been told
+    fn round_ended(&mut self, winner: Option<&Uuid>) -> Result<()> {
+        let Some(scripts) = self.scripts.clone() else {
+            return Ok(())
+        };
+
+        let table = self.table(None);
+        scripts.on_round_end(&table, winner.and_then(|winner| self.seat_of(winner)));
+
+        // Nothing to skip or draw now, only announcements are left to carry out
+        let turn = self.turn;
+        self.apply(table.take_effects(), &turn, None)
+    }
+
+    fn give(&mut self, to: &Uuid, cards: &[PhysicalCard]) {
+        if let Some(player) = self.players.get_mut(to) {
+            for card in cards {
+                player.give_card(*card);
+            }
+        }
+    }
+
+    fn force_draw(&mut self, victim: &Uuid, count: usize, reason: DrawReason) -> Result<()> {
+        // If both piles are exhausted they just get away with fewer cards
+        let cards: Vec<PhysicalCard> = (0..count).filter_map(|_| self.deck.draw()).collect();
+        self.give(victim, &cards);
+
+        self.emit(GameEvent::Drew { player: *victim, name: self.name_of(victim), cards, reason })?;
+        self.mercy(victim)
+    }
+
+    /// Has `victim` draw until they get a card in `color`, or there's nothing left to draw
+    fn draw_until(&mut self, victim: &Uuid, color: Color) -> Result<()> {
+        let mut cards = vec![];
+        while let Some(card) = self.deck.draw() {
+            cards.push(card);
+            if card.card.color() == color {
+                break
+            }
+        }
+        self.give(victim, &cards);
+
+        self.emit(GameEvent::Drew { player: *victim, name: self.name_of(victim), cards, reason: DrawReason::Penalty })?;
+        self.mercy(victim)
+    }
+
+    /// Knocks `id` out of a No Mercy game once they're holding too many cards, shuffling their
+    /// hand back into the draw pile. Whoever's left last wins
+    fn mercy(&mut self, id: &Uuid) -> Result<()> {
+        if !self.in_game || self.rules.variant != Variant::NoMercy || !self.is_playing(id) {
+            return Ok(())
+        }
+        let Some(player) = self.players.get_mut(id).filter(|player| player.hand_size() >= MERCY_LIMIT) else {
+            return Ok(())
+        };
+
+        let hand = player.hand().to_vec();
+        player.clear_hand();
+        self.deck.put_back(&hand);
+        self.deck.check_conservation();
+        self.eliminated.insert(*id);
+
+        info!(player = %id, cards = hand.len(), "Eliminated");
+        self.emit(GameEvent::Eliminated { player: *id, name: self.name_of(id), cards: hand.len() })?;
+
+        let left: Vec<Uuid> = self.seats.iter().filter(|seat| self.is_playing(seat)).copied().collect();
+        match left[..] {
+            [last] => self.crown(&last),
+            _ => Ok(())
+        }
+    }
+
+    pub fn win(&mut self, by: &Uuid) -> Result<()> {
+        // Check game exists, then what players hand size is
+        if !self.in_game || self.players.get(by).map(|player| player.hand_size()) != Some(0) {
+            return Ok(())
+        }
+
+        self.crown(by)
+    }
+
+    /// Ends the game with `by` as the winner
+    fn crown(&mut self, by: &Uuid) -> Result<()> {
+        info!(winner = %by, "Game won");
+
+        // Partners win together, scoring whatever's left in the other team's hands
+        let scored = self.team_of(by).map(|team| {
+            let points: u32 = self.teams[1 - team].iter()
+                .filter_map(|id| self.players.get(id))
+                .flat_map(|player| player.hand().iter().map(|held| held.card.points()))
+                .sum();
+            self.team_scores[team] += points;
+
+            format!(
+                "{} win the round and score {} points. {} have {}, {} have {}",
+                self.team_name(team), points, self.team_name(0), self.team_scores[0], self.team_name(1), self.team_scores[1]
+            )
+        });
+
+        let winner = self.name_of(by);
+        self.notifier.send(HookEvent::Won { winner: winner.clone() });
+        self.notifier.send(HookEvent::RoundEnded { winner, hands: self.hands() });
+        self.finish();
+
+        self.emit(GameEvent::Won { player: *by, name: self.name_of(by) })?;
+        if let Some(text) = scored {
+            self.emit(GameEvent::Announcement(text))?;
+        }
+        self.round_ended(Some(by))
+    }
 }
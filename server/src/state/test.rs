@@ -0,0 +1,1171 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use tokio::sync::watch;
+    use uuid::Uuid;
+
+    use crate::{app::webhooks::Notifier, client::outbox::{Outbox, OutboxReader}, config::settings::{Config, Outbound}, game::{card::{Card, CardId, Color, PhysicalCard, Side}, deck::Deck, decks::{CardSet, DeckDef, Decks, Face, SPECIAL}, rules::{BlankWild, Teams, Variant}, script::Scripts}, res::err::ServerError, state::{lobby::Lobby, msg::{Action, DynMessage}, player::{Player, Role}, state_man::{GameState, MERCY_LIMIT}, view::FeedEvent}};
+
+    struct TestPlayer {
+        id: Uuid,
+        rx: OutboxReader
+    }
+
+    fn outbox() -> (Outbox, OutboxReader) {
+        Outbox::new(Outbound::default(), Arc::default())
+    }
+
+    impl TestPlayer {
+        fn join(state: &mut GameState, name: &str) -> Self {
+            let (tx, rx) = outbox();
+            let id = Uuid::new_v4();
+
+            state.join(id, Player::new(tx)).unwrap();
+            state.set_name(&id, name).unwrap();
+
+            Self { id, rx }
+        }
+
+        /// Comes back on a new connection for the seat held for `name`
+        fn rejoin(state: &mut GameState, name: &str, token: &str) -> Self {
+            let (tx, rx) = outbox();
+            let id = Uuid::new_v4();
+
+            state.join(id, Player::new(tx)).unwrap();
+            state.rejoin(&id, name, token).unwrap();
+
+            Self { id, rx }
+        }
+
+        fn received(&mut self) -> Vec<DynMessage> {
+            let mut msgs = vec![];
+            while let Some(msg) = self.rx.try_recv() {
+                msgs.push(serde_json::from_str(msg.to_str().unwrap()).unwrap());
+            }
+
+            msgs
+        }
+    }
+
+    /// Slips a card into a player's hand as if they had drawn it
+    fn hand_card(state: &mut GameState, id: &Uuid, face: Card) -> PhysicalCard {
+        let card = state.deck.forge(face);
+        state.players.get_mut(id).unwrap().give_card(card);
+
+        card
+    }
+
+    /// Puts a card on the discard pile as if it had just been played
+    fn set_top(state: &mut GameState, face: Card) {
+        state.deck.set_facing(face);
+        state.active_color = face.color();
+    }
+
+    /// Any action that carries the identity of a card in someone's hand
+    fn reveals_hand(action: &Action) -> bool {
+        matches!(action, Action::Started(_) | Action::DrawnCard(_) | Action::DrawTwo(_) | Action::DrawFour(_) | Action::PartnerHand(_))
+    }
+
+    fn three_player_game() -> (GameState, Vec<TestPlayer>) {
+        let mut state = GameState::new();
+        let players = vec![
+            TestPlayer::join(&mut state, "alice"),
+            TestPlayer::join(&mut state, "bob"),
+            TestPlayer::join(&mut state, "carol"),
+        ];
+
+        (state, players)
+    }
+
+    /// Starts a game and hands the first turn to the admin, whatever card was flipped
+    fn started_game() -> (GameState, Vec<TestPlayer>) {
+        let (mut state, mut players) = three_player_game();
+        let admin = players[0].id;
+
+        state.start(&admin).unwrap();
+        state.turn = admin;
+        state.awaiting_color = None;
+        players.iter_mut().for_each(|player| { player.received(); });
+
+        (state, players)
+    }
+
+    #[test]
+    fn first_player_is_admin() {
+        let (state, players) = three_player_game();
+
+        assert_eq!(Role::Admin, state.players[&players[0].id].role);
+        assert_eq!(Role::User, state.players[&players[1].id].role);
+    }
+
+    #[test]
+    fn seat_tokens_only_go_to_their_owner() {
+        let (state, mut players) = three_player_game();
+        let tokens: Vec<String> = players.iter().map(|player| state.players[&player.id].token().to_string()).collect();
+        assert_ne!(tokens[0], tokens[1]);
+
+        for (player, token) in players.iter_mut().zip(tokens.iter()) {
+            let sent: Vec<String> = player.received().into_iter()
+                .filter_map(|msg| match msg.action {
+                    Action::SeatToken(token) => Some(token),
+                    _ => None
+                })
+                .collect();
+            assert_eq!(vec![token.clone()], sent);
+        }
+    }
+
+    #[test]
+    fn deal_only_reveals_own_hand() {
+        let (mut state, mut players) = three_player_game();
+        let admin = players[0].id;
+
+        state.start(&admin).unwrap();
+
+        for player in players.iter_mut() {
+            let msgs = player.received();
+            let own_hands = msgs.iter().filter(|msg| matches!(msg.action, Action::Started(_))).count();
+            let deals_seen = msgs.iter().filter(|msg| matches!(msg.action, Action::CardsDrawn(7))).count();
+
+            assert_eq!(1, own_hands);
+            assert_eq!(2, deals_seen);
+        }
+    }
+
+    #[test]
+    fn forced_draws_are_private() {
+        let (mut state, mut players) = started_game();
+        let admin = players[0].id;
+
+        let victim = state.after(&admin).unwrap();
+
+        set_top(&mut state, Card::Normal(Color::Red, 1));
+        let card = hand_card(&mut state, &admin, Card::DrawTwo(Color::Red));
+        state.play(&admin, card, None).unwrap();
+
+        for player in players.iter_mut() {
+            let msgs = player.received();
+            let revealed = msgs.iter().any(|msg| reveals_hand(&msg.action));
+
+            assert_eq!(player.id == victim, revealed);
+        }
+    }
+
+    #[test]
+    fn drawn_card_is_private() {
+        let (mut state, mut players) = started_game();
+        let admin = players[0].id;
+
+        state.draw(&admin).unwrap();
+
+        for player in players.iter_mut() {
+            let msgs = player.received();
+            let revealed = msgs.iter().any(|msg| reveals_hand(&msg.action));
+
+            assert_eq!(player.id == admin, revealed);
+            if player.id != admin {
+                assert!(msgs.iter().any(|msg| matches!(msg.action, Action::CardsDrawn(1))));
+            }
+        }
+    }
+
+    #[test]
+    fn spectators_only_see_public_info() {
+        let (mut state, players) = started_game();
+        let admin = players[0].id;
+
+        let mut spectator = TestPlayer::join(&mut state, "dave");
+        assert_eq!(Role::Spectator, state.players[&spectator.id].role);
+
+        let victim = state.after(&admin).unwrap();
+        set_top(&mut state, Card::Normal(Color::Red, 1));
+        let card = hand_card(&mut state, &admin, Card::DrawFour(Color::None));
+        state.play(&admin, card, Some(Color::Red)).unwrap();
+        state.draw(&state.turn.clone()).unwrap();
+
+        let msgs = spectator.received();
+        assert!(!msgs.iter().any(|msg| reveals_hand(&msg.action)));
+        assert!(msgs.iter().any(|msg| matches!(msg.action, Action::TopCard(_))));
+
+        // Spectators are never handed the turn
+        assert_ne!(spectator.id, state.after(&victim).unwrap());
+    }
+
+    #[test]
+    fn watchers_see_card_counts_not_cards() {
+        let (mut state, players) = started_game();
+        let admin = players[0].id;
+        let mut feed = state.watch();
+
+        let next = state.after(&admin).unwrap();
+        set_top(&mut state, Card::Normal(Color::Red, 1));
+        let card = hand_card(&mut state, &admin, Card::DrawTwo(Color::Red));
+        // The opening card may already have made someone draw, so count from what they hold now
+        let held = state.players[&admin].hand().len() - 1;
+        let next_held = state.players[&next].hand().len() + 2;
+        state.play(&admin, card, None).unwrap();
+        state.chat(&admin, "gg").unwrap();
+
+        let mut events = vec![];
+        while let Ok(event) = feed.try_recv() {
+            events.push(event);
+        }
+
+        let alice = Some("alice".to_string());
+        assert_eq!(vec![
+            FeedEvent::Hand { player: state.name_of(&next), cards: next_held },
+            FeedEvent::Hand { player: alice.clone(), cards: held },
+            FeedEvent::TopCard { played_by: alice.clone(), card: Card::DrawTwo(Color::Red) },
+            FeedEvent::Turn { player: state.name_of(&state.turn) },
+            FeedEvent::Chat { from: "alice".into(), text: "gg".into() }
+        ], events);
+    }
+
+    #[test]
+    fn opening_wild_waits_for_color() {
+        let (mut state, mut players) = started_game();
+        let first = players[1].id;
+
+        state.deck.set_facing(Card::Wild(Color::None));
+        state.open_with(first, Card::Wild(Color::None)).unwrap();
+
+        assert!(players[1].received().iter().any(|msg| matches!(msg.action, Action::PickColor)));
+
+        // Nothing can happen until a color is picked, and only the first player gets to pick it
+        let card = hand_card(&mut state, &first, Card::Normal(Color::Red, 1));
+        state.play(&first, card, None).unwrap();
+        state.choose_color(&players[0].id, Color::Red).unwrap();
+        state.choose_color(&first, Color::None).unwrap();
+        assert!(players[1].received().is_empty());
+        assert_eq!(Some(first), state.awaiting_color);
+
+        state.choose_color(&first, Color::Green).unwrap();
+
+        assert!(state.awaiting_color.is_none());
+        assert_eq!(Color::Green, state.active_color);
+        let msgs = players[1].received();
+        assert!(msgs.iter().any(|msg| matches!(msg.action, Action::ColorChosen(Color::Green))));
+        assert!(msgs.iter().any(|msg| matches!(msg.action, Action::YourTurn)));
+    }
+
+    #[test]
+    fn leaving_during_a_color_pick_passes_it_on() {
+        let (mut state, mut players) = started_game();
+        let first = players[1].id;
+        let next = state.after(&first).unwrap();
+
+        state.deck.set_facing(Card::Wild(Color::None));
+        state.open_with(first, Card::Wild(Color::None)).unwrap();
+        state.leave(&first).unwrap();
+
+        assert_eq!(next, state.turn);
+        assert_eq!(Some(next), state.awaiting_color);
+        let next_player = players.iter_mut().find(|player| player.id == next).unwrap();
+        assert!(next_player.received().iter().any(|msg| matches!(msg.action, Action::PickColor)));
+
+        state.choose_color(&next, Color::Blue).unwrap();
+        assert!(state.awaiting_color.is_none());
+        assert_eq!(Color::Blue, state.active_color);
+
+        let card = hand_card(&mut state, &next, Card::Normal(Color::Blue, 1));
+        state.play(&next, card, None).unwrap();
+        assert_eq!(Some(&Card::Normal(Color::Blue, 1)), state.deck.get_facing());
+    }
+
+    #[test]
+    fn opening_action_cards_apply_to_first_turn() {
+        let (mut state, mut players) = started_game();
+        let first = players[1].id;
+        let after_first = state.after(&first).unwrap();
+
+        state.open_with(first, Card::Skip(Color::Red)).unwrap();
+        assert_eq!(after_first, state.turn);
+        assert!(players[1].received().iter().any(|msg| matches!(msg.action, Action::Skipped)));
+
+        state.open_with(first, Card::DrawTwo(Color::Red)).unwrap();
+        assert_eq!(after_first, state.turn);
+        assert!(players[1].received().iter().any(|msg| matches!(msg.action, Action::DrawTwo(_))));
+
+        let before_first = state.players.keys().copied()
+            .find(|id| *id != first && *id != after_first)
+            .unwrap();
+        state.open_with(first, Card::Reverse(Color::Red)).unwrap();
+        assert_eq!(before_first, state.turn);
+
+        state.open_with(first, Card::Normal(Color::Red, 5)).unwrap();
+        assert_eq!(first, state.turn);
+    }
+
+    #[test]
+    fn cannot_play_cards_not_in_hand() {
+        let (mut state, mut players) = started_game();
+        let admin = players[0].id;
+        set_top(&mut state, Card::Normal(Color::Red, 1));
+
+        let forged = PhysicalCard { id: CardId(u32::MAX), card: Card::Normal(Color::Red, 2) };
+        state.play(&admin, forged, None).unwrap();
+
+        assert!(players[0].received().iter().any(|msg| matches!(msg.action, Action::DenyPlayCard)));
+        assert_eq!(admin, state.turn);
+    }
+
+    #[test]
+    fn server_decides_what_card_was_played() {
+        let (mut state, mut players) = started_game();
+        let admin = players[0].id;
+        set_top(&mut state, Card::Normal(Color::Red, 1));
+
+        // Claiming a held Blue 7 is actually a Red wild doesn't make it one
+        let card = hand_card(&mut state, &admin, Card::Normal(Color::Blue, 7));
+        state.play(&admin, PhysicalCard { card: Card::Wild(Color::Red), ..card }, Some(Color::Red)).unwrap();
+
+        assert!(players[0].received().iter().any(|msg| matches!(msg.action, Action::DenyPlayCard)));
+        assert!(state.players[&admin].hand().contains(&card));
+    }
+
+    #[test]
+    fn emptying_hand_wins() {
+        let (mut state, mut players) = started_game();
+        let admin = players[0].id;
+
+        // Declaring a win early does nothing
+        state.win(&admin).unwrap();
+        assert!(state.in_game);
+
+        state.players.get_mut(&admin).unwrap().clear_hand();
+        set_top(&mut state, Card::Normal(Color::Red, 1));
+        let card = hand_card(&mut state, &admin, Card::Normal(Color::Red, 2));
+        state.play(&admin, card, None).unwrap();
+
+        assert!(!state.in_game);
+        assert!(players[1].received().iter().any(|msg| matches!(msg.action, Action::Win)));
+    }
+
+    #[test]
+    fn last_player_left_wins() {
+        let (mut state, mut players) = started_game();
+
+        state.leave(&players[1].id).unwrap();
+        assert!(state.in_game);
+
+        state.leave(&players[2].id).unwrap();
+        assert!(!state.in_game);
+        let msgs = players[0].received();
+        assert!(msgs.iter().any(|msg| matches!(msg.action, Action::Win)));
+        assert!(!msgs.iter().any(|msg| matches!(msg.action, Action::YourTurn)));
+
+        // And the room can go again once others join
+        TestPlayer::join(&mut state, "dave");
+        state.start(&players[0].id).unwrap();
+        assert!(state.in_game);
+    }
+
+    #[test]
+    fn ending_a_game_clears_every_hand() {
+        let (mut state, mut players) = started_game();
+        let mut feed = state.watch();
+
+        assert!(state.end().unwrap());
+        assert!(!state.in_game);
+        for player in players.iter_mut() {
+            assert!(player.received().iter().any(|msg| matches!(msg.action, Action::GameOver)));
+        }
+        let mut ended = false;
+        while let Ok(event) = feed.try_recv() {
+            ended |= event == FeedEvent::Ended;
+        }
+        assert!(ended);
+
+        // A fresh deal replaces whatever was left of the old hands
+        assert!(!state.end().unwrap());
+        state.start(&players[0].id).unwrap();
+        let dealt = players[1].received().into_iter().find_map(|msg| match msg.action {
+            Action::Started(hand) => Some(hand),
+            _ => None
+        });
+        assert_eq!(Some(&state.players[&players[1].id].hand()[..7]), dealt.as_ref().map(|hand| &hand[..]));
+    }
+
+    #[test]
+    fn wilds_need_a_color() {
+        let (mut state, mut players) = started_game();
+        let admin = players[0].id;
+        set_top(&mut state, Card::Normal(Color::Red, 1));
+
+        let wild = hand_card(&mut state, &admin, Card::Wild(Color::None));
+        state.play(&admin, wild, None).unwrap();
+        state.play(&admin, wild, Some(Color::None)).unwrap();
+
+        assert_eq!(2, players[0].received().iter().filter(|msg| matches!(msg.action, Action::DenyPlayCard)).count());
+        assert!(state.players[&admin].hand().contains(&wild));
+
+        state.play(&admin, wild, Some(Color::Blue)).unwrap();
+
+        // The wild itself is left alone, the color lives on the game
+        assert_eq!(Some(&Card::Wild(Color::None)), state.deck.get_facing());
+        assert_eq!(Color::Blue, state.active_color);
+        assert!(players[2].received().iter().any(|msg| matches!(msg.action, Action::ColorChosen(Color::Blue))));
+    }
+
+    #[test]
+    fn active_color_decides_legality() {
+        let (mut state, mut players) = started_game();
+        let admin = players[0].id;
+        state.deck.set_facing(Card::Wild(Color::None));
+        state.active_color = Color::Yellow;
+
+        let red = hand_card(&mut state, &admin, Card::Normal(Color::Red, 4));
+        state.play(&admin, red, None).unwrap();
+        assert!(players[0].received().iter().any(|msg| matches!(msg.action, Action::DenyPlayCard)));
+
+        let yellow = hand_card(&mut state, &admin, Card::Normal(Color::Yellow, 4));
+        state.play(&admin, yellow, None).unwrap();
+        assert!(players[0].received().iter().any(|msg| matches!(msg.action, Action::AcceptPlayCard)));
+        assert_eq!(Color::Yellow, state.active_color);
+    }
+
+    #[test]
+    fn house_rules_change_play() {
+        let (mut state, mut players) = started_game();
+        let admin = players[0].id;
+        let scripts = Scripts::compile(vec![("sevens.rhai".into(), r#"
+            fn can_play(game, card, allowed) { allowed || card.number == 0 }
+            fn on_play(game, player, card) {
+                if card.number == 7 {
+                    game.skip();
+                    game.announce(player.name + " played a 7");
+                }
+            }
+        "#.into())]).unwrap();
+        state.set_scripts(Some(Arc::new(scripts)));
+
+        // Zeros go on anything
+        set_top(&mut state, Card::Normal(Color::Red, 1));
+        let zero = hand_card(&mut state, &admin, Card::Normal(Color::Blue, 0));
+        state.play(&admin, zero, None).unwrap();
+        assert_eq!(Some(&Card::Normal(Color::Blue, 0)), state.deck.get_facing());
+
+        let by = state.turn;
+        let skipped = state.after(&by).unwrap();
+        set_top(&mut state, Card::Normal(Color::Red, 2));
+        let seven = hand_card(&mut state, &by, Card::Normal(Color::Red, 7));
+        state.play(&by, seven, None).unwrap();
+
+        assert_eq!(state.after(&skipped).unwrap(), state.turn);
+        let announced = format!("{} played a 7", state.name_of(&by).unwrap());
+        let msgs = players[0].received();
+        assert!(msgs.iter().any(|msg| matches!(&msg.action, Action::Message(text) if *text == announced)));
+    }
+
+    #[test]
+    fn saved_games_pick_up_where_they_left_off() {
+        let (mut state, players) = started_game();
+        let ids: Vec<Uuid> = players.iter().map(|player| player.id).collect();
+        let bob = ids[1];
+        state.turn = bob;
+
+        let hands: Vec<Vec<PhysicalCard>> = ids.iter().map(|id| state.players[id].hand().to_vec()).collect();
+        let order = [ids[0], state.after(&ids[0]).unwrap(), state.after(&state.after(&ids[0]).unwrap()).unwrap()];
+        let deck_size = state.deck.deck_size();
+        let token = state.players[&bob].token().to_string();
+
+        // Through JSON, the same way it goes to disk
+        let json = serde_json::to_string(&state.save("saved")).unwrap();
+        let mut state = GameState::restore(serde_json::from_str(&json).unwrap());
+
+        assert!(state.players.values().all(Player::is_away));
+        assert_eq!(deck_size, state.deck.deck_size());
+        assert_eq!(Role::Admin, state.players[&ids[0]].role);
+
+        // Going by his name alone isn't enough to get his cards
+        let mut impostor = TestPlayer::join(&mut state, "bob");
+        assert!(state.players[&impostor.id].get_name().is_none());
+        assert!(!impostor.received().iter().any(|msg| reveals_hand(&msg.action) || matches!(msg.action, Action::Hand(_))));
+        state.leave(&impostor.id).unwrap();
+
+        let mut guesser = TestPlayer::rejoin(&mut state, "bob", "not the token");
+        assert!(state.players[&bob].is_away());
+        assert!(!guesser.received().iter().any(|msg| reveals_hand(&msg.action) || matches!(msg.action, Action::Hand(_))));
+        state.leave(&guesser.id).unwrap();
+
+        // Bob comes back on a new connection with his token and gets his seat, hand and turn back
+        let mut returning = TestPlayer::rejoin(&mut state, "bob", &token);
+        assert_eq!(3, state.num_players());
+        assert_eq!(hands[1], state.players[&returning.id].hand());
+        assert_eq!(returning.id, state.turn);
+        assert_eq!(Role::User, state.players[&returning.id].role);
+
+        let received = returning.received();
+        assert!(received.iter().any(|msg| matches!(&msg.action, Action::Hand(hand) if *hand == hands[1])));
+        assert!(received.iter().any(|msg| matches!(msg.action, Action::YourTurn)));
+
+        // Everyone still sits where they did
+        let order = order.map(|id| if id == bob { returning.id } else { id });
+        assert_eq!(order[1], state.after(&order[0]).unwrap());
+        assert_eq!(order[2], state.after(&order[1]).unwrap());
+
+        state.draw(&returning.id).unwrap();
+        assert_eq!(hands[1].len() + 1, state.players[&returning.id].hand_size());
+
+        // Nobody else came back in time
+        state.drop_away().unwrap();
+        assert_eq!(1, state.num_players());
+        assert_eq!(returning.id, state.turn);
+    }
+
+    #[test]
+    fn admin_picks_the_deck() {
+        let (mut state, mut players) = three_player_game();
+        let (admin, other) = (players[0].id, players[1].id);
+
+        let mut decks = Decks::default();
+        let tiny = |count| DeckDef {
+            cards: vec![CardSet { face: Face::Normal, colors: vec![Color::Green], numbers: vec![3], count }],
+            players_per_copy: None,
+            min_copies: 1,
+            max_copies: Some(1)
+        };
+        decks.insert("tiny", tiny(10)).unwrap();
+        decks.insert("greens", tiny(30)).unwrap();
+        state.set_decks(Arc::new(decks));
+
+        state.use_deck(&other, "greens").unwrap();
+        assert_eq!("standard", state.rules.deck);
+
+        state.use_deck(&admin, "missing").unwrap();
+        assert_eq!("standard", state.rules.deck);
+        assert!(players[0].received().iter().any(|msg| matches!(&msg.action, Action::Message(text) if text.starts_with("There's no deck called missing"))));
+
+        // Ten cards can't deal three hands
+        state.use_deck(&admin, "tiny").unwrap();
+        state.start(&admin).unwrap();
+        assert!(!state.in_game);
+
+        state.use_deck(&admin, "greens").unwrap();
+        state.start(&admin).unwrap();
+        assert!(state.in_game);
+        assert_eq!(30 - 3 * 7 - 1, state.deck.deck_size());
+        assert!(state.players[&admin].hand().iter().all(|card| card.card == Card::Normal(Color::Green, 3)));
+
+        // Not while a game is going
+        state.use_deck(&admin, "tiny").unwrap();
+        assert_eq!("greens", state.rules.deck);
+    }
+
+    /// Starts an UNO Flip game and hands the first turn to the admin
+    fn flip_game() -> (GameState, Vec<TestPlayer>) {
+        let (mut state, mut players) = three_player_game();
+        let admin = players[0].id;
+
+        state.use_variant(&admin, Variant::Flip).unwrap();
+        state.start(&admin).unwrap();
+        // An opening Flip leaves the dark side up, so turn everything back
+        if state.deck.side() == Side::Dark {
+            state.deck.flip();
+            for player in state.players.values_mut() {
+                let hand: Vec<PhysicalCard> = player.hand().iter().map(|card| state.deck.turn(*card)).collect();
+                player.clear_hand();
+                hand.into_iter().for_each(|card| player.give_card(card));
+            }
+        }
+        state.turn = admin;
+        state.awaiting_color = None;
+        state.active_color = state.deck.get_facing().unwrap().color();
+        players.iter_mut().for_each(|player| { player.received(); });
+
+        (state, players)
+    }
+
+    #[test]
+    fn flip_turns_the_whole_table_over() {
+        let (mut state, mut players) = flip_game();
+        let admin = players[0].id;
+        let held: usize = state.players.values().map(|player| player.hand_size()).sum();
+        assert_eq!(112 * state.rules.deck_copies(3), state.deck.deck_size() + state.deck.discard_size() + held);
+
+        let next = state.after(&admin).unwrap();
+        set_top(&mut state, Card::Normal(Color::Red, 4));
+        let flip = state.deck.forge_flip(Card::Flip(Color::Red), Card::Normal(Color::Teal, 2));
+        state.players.get_mut(&admin).unwrap().give_card(flip);
+        state.play(&admin, flip, None).unwrap();
+
+        assert_eq!(Side::Dark, state.deck.side());
+        for player in players.iter_mut() {
+            let hand = state.players[&player.id].hand().to_vec();
+            assert!(hand.iter().all(|card| card.card.color().side() != Some(Side::Light)));
+
+            let msgs = player.received();
+            assert!(msgs.iter().any(|msg| matches!(msg.action, Action::Flipped(Side::Dark))));
+            assert!(msgs.iter().any(|msg| matches!(&msg.action, Action::Hand(turned) if *turned == hand)));
+        }
+
+        // The opening card is back on top, dark side up
+        match state.deck.get_facing().unwrap().color() {
+            Color::None => assert_eq!(Some(next), state.awaiting_color),
+            color => {
+                assert_eq!(Some(Side::Dark), color.side());
+                assert_eq!(color, state.active_color);
+                assert_eq!(next, state.turn);
+            }
+        }
+
+        // Light colors mean nothing on the dark side
+        state.turn = admin;
+        state.awaiting_color = None;
+        let wild = hand_card(&mut state, &admin, Card::Wild(Color::None));
+        state.play(&admin, wild, Some(Color::Red)).unwrap();
+        assert!(state.players[&admin].hand().contains(&wild));
+        state.play(&admin, wild, Some(Color::Purple)).unwrap();
+        assert_eq!(Color::Purple, state.active_color);
+
+        // Which side is up, and what's on the other side of every card, survives a restart
+        let saved = serde_json::to_string(&state.save("flip")).unwrap();
+        let mut restored = GameState::restore(serde_json::from_str(&saved).unwrap());
+        assert_eq!(Side::Dark, restored.deck.side());
+        let teal = PhysicalCard { id: flip.id, card: Card::Normal(Color::Teal, 2) };
+        assert_eq!(Card::Flip(Color::Red), restored.deck.turn(teal).card);
+    }
+
+    #[test]
+    fn opening_flip_turns_the_table_over() {
+        let (mut state, mut players) = flip_game();
+        let first = players[1].id;
+
+        // A fresh table with nothing on it but the opening card
+        state.deck = Deck::double_sided(1);
+        state.players.values_mut().for_each(|player| player.clear_hand());
+        let flip = state.deck.forge_flip(Card::Flip(Color::Red), Card::Reverse(Color::Teal));
+        state.deck.discard(flip);
+        state.open_with(first, Card::Flip(Color::Red)).unwrap();
+
+        // Whatever was on the back opens the game in its place
+        assert_eq!(Side::Dark, state.deck.side());
+        assert_eq!(Some(&Card::Reverse(Color::Teal)), state.deck.get_facing());
+        assert_eq!(Color::Teal, state.active_color);
+        assert_eq!(state.after(&first).unwrap(), state.turn);
+        assert_ne!(first, state.turn);
+        for player in players.iter_mut() {
+            assert!(player.received().iter().any(|msg| matches!(msg.action, Action::Flipped(Side::Dark))));
+        }
+    }
+
+    #[test]
+    fn dark_side_actions() {
+        let (mut state, _players) = flip_game();
+        let admin = state.turn;
+        let next = state.after(&admin).unwrap();
+        state.deck.flip();
+
+        set_top(&mut state, Card::Normal(Color::Pink, 4));
+        let skip = hand_card(&mut state, &admin, Card::SkipEveryone(Color::Pink));
+        state.play(&admin, skip, None).unwrap();
+        assert_eq!(admin, state.turn);
+
+        let before = state.players[&next].hand_size();
+        let five = hand_card(&mut state, &admin, Card::DrawFive(Color::Pink));
+        state.play(&admin, five, None).unwrap();
+        assert_eq!(before + 5, state.players[&next].hand_size());
+        assert_eq!(state.after(&next).unwrap(), state.turn);
+
+        let admin = state.turn;
+        let victim = state.after(&admin).unwrap();
+        let before = state.players[&victim].hand_size();
+        let draw_color = hand_card(&mut state, &admin, Card::WildDrawColor(Color::None));
+        state.play(&admin, draw_color, Some(Color::Orange)).unwrap();
+
+        let drawn = &state.players[&victim].hand()[before..];
+        assert!(!drawn.is_empty());
+        // Stops at the first card in the chosen color, unless the deck ran dry first
+        assert!(drawn[..drawn.len() - 1].iter().all(|card| card.card.color() != Color::Orange));
+        state.deck.check_conservation();
+    }
+
+    /// Starts a No Mercy game and hands the first turn to the admin
+    fn no_mercy_game() -> (GameState, Vec<TestPlayer>) {
+        let (mut state, mut players) = three_player_game();
+        let admin = players[0].id;
+
+        state.use_variant(&admin, Variant::NoMercy).unwrap();
+        state.start(&admin).unwrap();
+        state.turn = admin;
+        state.awaiting_color = None;
+        set_top(&mut state, Card::Normal(Color::Red, 4));
+        players.iter_mut().for_each(|player| { player.received(); });
+
+        (state, players)
+    }
+
+    #[test]
+    fn no_mercy_draw_cards_stack() {
+        let (mut state, _players) = no_mercy_game();
+        let admin = state.turn;
+        let second = state.after(&admin).unwrap();
+        let third = state.after(&second).unwrap();
+        let held: usize = state.players.values().map(|player| player.hand_size()).sum();
+        // Less the top card slipped in for the test
+        assert_eq!(160 * state.rules.deck_copies(3), state.deck.deck_size() + state.deck.discard_size() + held - 1);
+
+        let two = hand_card(&mut state, &admin, Card::DrawTwo(Color::Red));
+        state.play(&admin, two, None).unwrap();
+        assert_eq!(2, state.pending_draw);
+        assert_eq!(second, state.turn);
+
+        // Only something at least as big goes on the stack, whatever its color
+        let small = hand_card(&mut state, &second, Card::Normal(Color::Red, 2));
+        state.play(&second, small, None).unwrap();
+        assert!(state.players[&second].hand().contains(&small));
+        let six = hand_card(&mut state, &second, Card::WildDrawSix(Color::None));
+        state.play(&second, six, Some(Color::Blue)).unwrap();
+        assert_eq!(8, state.pending_draw);
+        assert_eq!(third, state.turn);
+
+        let four = hand_card(&mut state, &third, Card::WildReverseDrawFour(Color::None));
+        state.play(&third, four, Some(Color::Green)).unwrap();
+        assert!(state.players[&third].hand().contains(&four));
+
+        // Whoever can't stack takes the lot, and play moves on
+        let before = state.players[&third].hand_size();
+        state.draw(&third).unwrap();
+        assert_eq!(before + 8, state.players[&third].hand_size());
+        assert_eq!(0, state.pending_draw);
+        assert_eq!(admin, state.turn);
+        state.deck.check_conservation();
+    }
+
+    #[test]
+    fn taking_a_stack_ends_the_turn_for_house_rules() {
+        let (mut state, mut players) = no_mercy_game();
+        let admin = state.turn;
+        let second = state.after(&admin).unwrap();
+        let scripts = Scripts::compile(vec![("turns.rhai".into(), r#"
+            fn on_turn_end(game, player) { game.announce(player.name + " is done"); }
+        "#.into())]).unwrap();
+        state.set_scripts(Some(Arc::new(scripts)));
+
+        let two = hand_card(&mut state, &admin, Card::DrawTwo(Color::Red));
+        state.play(&admin, two, None).unwrap();
+        players.iter_mut().for_each(|player| { player.received(); });
+
+        state.draw(&second).unwrap();
+
+        let announced = format!("{} is done", state.name_of(&second).unwrap());
+        let msgs = players[0].received();
+        assert!(msgs.iter().any(|msg| matches!(&msg.action, Action::Message(text) if *text == announced)));
+        assert_eq!(state.after(&second).unwrap(), state.turn);
+    }
+
+    /// Has every end of a turn announced, so tests can tell whose turn the house rules saw end
+    fn announce_turn_ends(state: &mut GameState) {
+        let scripts = Scripts::compile(vec![("turns.rhai".into(), r#"
+            fn on_turn_end(game, player) { game.announce(player.name + " is done"); }
+        "#.into())]).unwrap();
+        state.set_scripts(Some(Arc::new(scripts)));
+    }
+
+    fn heard_done(player: &mut TestPlayer, name: &str) -> bool {
+        let announced = format!("{} is done", name);
+        player.received().iter().any(|msg| matches!(&msg.action, Action::Message(text) if *text == announced))
+    }
+
+    #[test]
+    fn leaving_on_your_turn_ends_it_for_house_rules() {
+        let (mut state, mut players) = started_game();
+        announce_turn_ends(&mut state);
+
+        let next = state.after(&players[0].id).unwrap();
+        state.leave(&players[0].id).unwrap();
+        assert!(heard_done(&mut players[1], "alice"));
+        assert_eq!(next, state.turn);
+
+        // Leaving only one player behind ends the round, with no turn left to end
+        let stayer = players.iter().position(|player| player.id != next && player.id != players[0].id).unwrap();
+        players[stayer].received();
+        let name = state.name_of(&next).unwrap();
+        state.leave(&next).unwrap();
+        assert!(!state.in_game);
+        assert!(!heard_done(&mut players[stayer], &name));
+    }
+
+    #[test]
+    fn winning_card_skips_the_end_of_turn() {
+        let (mut state, mut players) = started_game();
+        let admin = players[0].id;
+        announce_turn_ends(&mut state);
+
+        state.players.get_mut(&admin).unwrap().clear_hand();
+        set_top(&mut state, Card::Normal(Color::Red, 1));
+        let card = hand_card(&mut state, &admin, Card::Normal(Color::Red, 2));
+        state.play(&admin, card, None).unwrap();
+
+        assert!(!state.in_game);
+        assert!(!heard_done(&mut players[1], "alice"));
+    }
+
+    #[test]
+    fn too_many_cards_knocks_you_out() {
+        let (mut state, mut players) = no_mercy_game();
+        let admin = state.turn;
+        let second = state.after(&admin).unwrap();
+        let third = state.after(&second).unwrap();
+
+        for _ in state.players[&second].hand_size()..MERCY_LIMIT - 2 {
+            hand_card(&mut state, &second, Card::Normal(Color::Blue, 1));
+        }
+        let two = hand_card(&mut state, &admin, Card::DrawTwo(Color::Red));
+        state.play(&admin, two, None).unwrap();
+        state.draw(&second).unwrap();
+
+        // Their cards go back in the deck and they're skipped from now on
+        assert!(state.eliminated.contains(&second));
+        assert_eq!(0, state.players[&second].hand_size());
+        assert_eq!(third, state.turn);
+        assert_eq!(admin, state.after(&third).unwrap());
+        assert_eq!(third, state.after(&second).unwrap());
+        state.deck.check_conservation();
+        let second_player = players.iter_mut().find(|player| player.id == second).unwrap();
+        assert!(second_player.received().iter().any(|msg| matches!(&msg.action, Action::Hand(hand) if hand.is_empty())));
+
+        // Being knocked out lasts through a restart
+        let saved = serde_json::to_string(&state.save("mercy")).unwrap();
+        let restored = GameState::restore(serde_json::from_str(&saved).unwrap());
+        assert!(restored.eliminated.contains(&second));
+
+        // The last one standing wins
+        for _ in state.players[&third].hand_size()..MERCY_LIMIT {
+            hand_card(&mut state, &third, Card::Normal(Color::Blue, 1));
+        }
+        state.draw(&third).unwrap();
+        assert!(!state.in_game);
+        assert!(players[0].received().iter().any(|msg| matches!(msg.action, Action::Win)));
+        assert!(state.eliminated.is_empty());
+    }
+
+    #[test]
+    fn color_roulette_and_discard_all() {
+        let (mut state, mut players) = no_mercy_game();
+        let admin = state.turn;
+        let next = state.after(&admin).unwrap();
+
+        // The next player picks the color, then draws until they find it
+        let roulette = hand_card(&mut state, &admin, Card::WildColorRoulette(Color::None));
+        state.play(&admin, roulette, Some(Color::Red)).unwrap();
+        assert_eq!(Color::None, state.active_color);
+        assert_eq!(Some(next), state.awaiting_color);
+
+        state.choose_color(&next, Color::Yellow).unwrap();
+        // Read off what they were sent, since a long enough search can knock them out
+        let next_player = players.iter_mut().find(|player| player.id == next).unwrap();
+        let drawn: Vec<PhysicalCard> = next_player.received().into_iter()
+            .filter_map(|msg| match msg.action {
+                Action::DrawnCard(card) => Some(card),
+                _ => None
+            })
+            .collect();
+        assert!(!drawn.is_empty());
+        assert!(drawn[..drawn.len() - 1].iter().all(|card| card.card.color() != Color::Yellow));
+        assert_eq!(Color::Yellow, state.active_color);
+        assert_eq!(state.after(&next).unwrap(), state.turn);
+
+        // Every other card of the color goes down with a Discard All
+        let player = state.turn;
+        let yellows = state.players[&player].hand().iter().filter(|card| card.card.color() == Color::Yellow).count();
+        hand_card(&mut state, &player, Card::Normal(Color::Blue, 3));
+        let discard = hand_card(&mut state, &player, Card::DiscardAll(Color::Yellow));
+        let before = state.players[&player].hand_size();
+        state.play(&player, discard, None).unwrap();
+        assert_eq!(before - yellows - 1, state.players[&player].hand_size());
+        assert!(state.players[&player].hand().iter().all(|card| card.card.color() != Color::Yellow));
+        assert_eq!(Some(&Card::DiscardAll(Color::Yellow)), state.deck.get_facing());
+        state.deck.check_conservation();
+    }
+
+    #[test]
+    fn swap_hands_waits_for_a_target() {
+        let (mut state, mut players) = started_game();
+        let admin = players[0].id;
+        let next = state.after(&admin).unwrap();
+        set_top(&mut state, Card::Normal(Color::Red, 4));
+
+        let swap = hand_card(&mut state, &admin, Card::WildSwapHands(Color::None));
+        state.play(&admin, swap, Some(Color::Blue)).unwrap();
+        assert_eq!(Some(admin), state.awaiting_target);
+        assert!(players[0].received().iter().any(|msg| matches!(&msg.action, Action::PickTarget(names) if names.len() == 2)));
+
+        // Nobody moves until the swap is done
+        let before = state.players[&next].hand_size();
+        state.draw(&next).unwrap();
+        assert_eq!(before, state.players[&next].hand_size());
+
+        state.choose_target(&admin, "nobody").unwrap();
+        assert_eq!(Some(admin), state.awaiting_target);
+
+        let mine = state.players[&admin].hand().to_vec();
+        let theirs = state.players[&next].hand().to_vec();
+        let name = state.name_of(&next).unwrap();
+        state.choose_target(&admin, &name).unwrap();
+        assert_eq!(theirs, state.players[&admin].hand());
+        assert_eq!(mine, state.players[&next].hand());
+        assert_eq!(None, state.awaiting_target);
+        assert_eq!(next, state.turn);
+
+        let next_player = players.iter_mut().find(|player| player.id == next).unwrap();
+        let msgs = next_player.received();
+        assert!(msgs.iter().any(|msg| matches!(&msg.action, Action::Hand(hand) if *hand == mine)));
+        assert!(msgs.iter().any(|msg| matches!(msg.action, Action::YourTurn)));
+    }
+
+    #[test]
+    fn special_deck_deals_a_swap() {
+        let (mut state, mut players) = three_player_game();
+        let (admin, bob) = (players[0].id, players[1].id);
+
+        state.use_deck(&admin, SPECIAL).unwrap();
+        state.start(&admin).unwrap();
+        let held: usize = state.players.values().map(|player| player.hand_size()).sum();
+        assert_eq!(112 * state.rules.deck_copies(3), state.deck.deck_size() + state.deck.discard_size() + held);
+
+        state.turn = admin;
+        state.awaiting_color = None;
+        set_top(&mut state, Card::Normal(Color::Red, 4));
+
+        // Find the Wild Swap Hands wherever it ended up and hand it to the admin
+        let is_swap = |card: &PhysicalCard| matches!(card.card, Card::WildSwapHands(_));
+        let dealt = state.players.values_mut().find_map(|player| {
+            let card = player.hand().iter().copied().find(is_swap)?;
+            player.take(card.id)
+        });
+        let swap = match dealt {
+            Some(card) => card,
+            None => {
+                let mut passed = vec![];
+                let card = loop {
+                    let card = state.deck.draw().unwrap();
+                    if is_swap(&card) {
+                        break card
+                    }
+                    passed.push(card);
+                };
+                state.deck.put_back(&passed);
+                card
+            }
+        };
+        state.players.get_mut(&admin).unwrap().give_card(swap);
+
+        let mine = state.players[&admin].hand().iter().copied().filter(|card| card.id != swap.id).collect::<HashSet<_>>();
+        let theirs = state.players[&bob].hand().iter().copied().collect::<HashSet<_>>();
+        state.play(&admin, swap, Some(Color::Blue)).unwrap();
+        assert_eq!(Some(admin), state.awaiting_target);
+        assert!(players[0].received().iter().any(|msg| matches!(msg.action, Action::PickTarget(_))));
+
+        state.choose_target(&admin, "bob").unwrap();
+        assert_eq!(theirs, state.players[&admin].hand().iter().copied().collect());
+        assert_eq!(mine, state.players[&bob].hand().iter().copied().collect());
+        state.deck.check_conservation();
+    }
+
+    #[test]
+    fn shuffle_hands_deals_everything_back_out() {
+        let (mut state, mut players) = started_game();
+        let admin = players[0].id;
+        set_top(&mut state, Card::Normal(Color::Red, 4));
+
+        let shuffle = hand_card(&mut state, &admin, Card::WildShuffleHands(Color::None));
+        let mut before: HashSet<CardId> = state.players.values().flat_map(|player| player.hand().iter().map(|card| card.id)).collect();
+        before.remove(&shuffle.id);
+        state.play(&admin, shuffle, Some(Color::Green)).unwrap();
+
+        let after: HashSet<CardId> = state.players.values().flat_map(|player| player.hand().iter().map(|card| card.id)).collect();
+        let sizes: Vec<usize> = state.players.values().map(|player| player.hand_size()).collect();
+        assert!(sizes.iter().max().unwrap() - sizes.iter().min().unwrap() <= 1);
+        assert_eq!(before, after);
+
+        for player in players.iter_mut() {
+            let hand = state.players[&player.id].hand().to_vec();
+            assert!(player.received().iter().any(|msg| matches!(&msg.action, Action::Hand(dealt) if *dealt == hand)));
+        }
+        assert_eq!(Color::Green, state.active_color);
+        state.deck.check_conservation();
+    }
+
+    #[test]
+    fn blank_wilds_play_as_the_room_says() {
+        let (mut state, players) = three_player_game();
+        let admin = players[0].id;
+        state.use_blank_wild(&players[1].id, BlankWild::Skip).unwrap();
+        assert_eq!(BlankWild::Plain, state.rules.blank_wild);
+        state.use_blank_wild(&admin, BlankWild::Skip).unwrap();
+
+        state.start(&admin).unwrap();
+        state.turn = admin;
+        state.awaiting_color = None;
+        set_top(&mut state, Card::Normal(Color::Red, 4));
+
+        let blank = hand_card(&mut state, &admin, Card::WildCustomizable(Color::None));
+        state.play(&admin, blank, Some(Color::Yellow)).unwrap();
+        assert_eq!(Some(&Card::WildCustomizable(Color::None)), state.deck.get_facing());
+        assert_eq!(Color::Yellow, state.active_color);
+        assert_eq!(state.after(&state.after(&admin).unwrap()).unwrap(), state.turn);
+    }
+
+    /// Four players sat down for a team game, not started yet
+    fn team_game(teams: Teams) -> (GameState, Vec<TestPlayer>) {
+        let (mut state, mut players) = three_player_game();
+        players.push(TestPlayer::join(&mut state, "dave"));
+        state.use_teams(&players[0].id, teams).unwrap();
+
+        (state, players)
+    }
+
+    /// The most recent hand `player` was shown of their partner's
+    fn partner_hand(player: &mut TestPlayer) -> Option<Vec<PhysicalCard>> {
+        player.received().into_iter().rev().find_map(|msg| match msg.action {
+            Action::PartnerHand(hand) => Some(hand),
+            _ => None
+        })
+    }
+
+    #[test]
+    fn partners_sit_across_and_win_together() {
+        let (mut state, mut players) = three_player_game();
+        let admin = players[0].id;
+        state.use_teams(&players[1].id, Teams::Partners).unwrap();
+        assert_eq!(Teams::Solo, state.rules.teams);
+
+        // Three can't split into two teams of two
+        state.use_teams(&admin, Teams::Partners).unwrap();
+        state.start(&admin).unwrap();
+        assert!(!state.in_game);
+
+        players.push(TestPlayer::join(&mut state, "dave"));
+        let ids: Vec<Uuid> = players.iter().map(|player| player.id).collect();
+        state.start(&admin).unwrap();
+        assert_eq!(vec![[ids[0], ids[2]], [ids[1], ids[3]]], state.teams);
+        for id in ids.iter() {
+            assert_ne!(state.team_of(id), state.team_of(&state.after(id).unwrap()));
+        }
+        // Hands stay closed unless partners play open
+        assert!(partner_hand(&mut players[2]).is_none());
+
+        // Either partner going out wins it for both, scoring the other team's cards
+        let owed: u32 = [ids[1], ids[3]].iter()
+            .flat_map(|id| state.players[id].hand().iter().map(|held| held.card.points()))
+            .sum();
+        state.turn = ids[2];
+        state.awaiting_color = None;
+        state.players.get_mut(&ids[2]).unwrap().clear_hand();
+        set_top(&mut state, Card::Normal(Color::Red, 1));
+        let card = hand_card(&mut state, &ids[2], Card::Normal(Color::Red, 2));
+        state.play(&ids[2], card, None).unwrap();
+
+        assert!(!state.in_game);
+        assert_eq!([owed, 0], state.team_scores);
+        assert!(players[0].received().iter().any(|msg| matches!(&msg.action, Action::Message(text) if text.contains(&format!("score {} points", owed)))));
+
+        // Scores carry over to the next round, and survive a restart
+        state.start(&admin).unwrap();
+        let saved = serde_json::to_string(&state.save("teams")).unwrap();
+        let restored = GameState::restore(serde_json::from_str(&saved).unwrap());
+        assert_eq!([owed, 0], restored.team_scores);
+        assert_eq!(state.teams, restored.teams);
+
+        // Until the room stops playing in teams
+        state.end().unwrap();
+        state.use_teams(&admin, Teams::Solo).unwrap();
+        assert_eq!([0, 0], state.team_scores);
+    }
+
+    #[test]
+    fn open_partners_see_each_others_hands() {
+        let (mut state, mut players) = team_game(Teams::OpenPartners);
+        let (admin, partner) = (players[0].id, players[2].id);
+        state.start(&admin).unwrap();
+
+        assert_eq!(Some(state.players[&admin].hand().to_vec()), partner_hand(&mut players[2]));
+        assert_eq!(Some(state.players[&partner].hand().to_vec()), partner_hand(&mut players[0]));
+        // Opponents are only ever shown their own partner's hand
+        let msgs = players[1].received();
+        assert!(msgs.iter().any(|msg| matches!(msg.action, Action::PartnerHand(_))));
+        assert!(msgs.iter().filter(|msg| matches!(msg.action, Action::PartnerHand(_))).all(|msg| msg.sender.as_deref() == Some("dave")));
+        players[3].received();
+
+        state.turn = admin;
+        state.awaiting_color = None;
+        state.draw(&admin).unwrap();
+        assert_eq!(Some(state.players[&admin].hand().to_vec()), partner_hand(&mut players[2]));
+        assert!(!players[3].received().iter().any(|msg| reveals_hand(&msg.action)));
+        assert!(partner_hand(&mut players[1]).is_none());
+    }
+
+    #[tokio::test]
+    async fn lobby_enforces_limits() {
+        let config = Config { max_rooms: 1, max_players: 2, ..Config::default() };
+        let mut lobby = Lobby::new(&config, watch::channel(false).1, Arc::default(), Notifier::default(), None, Arc::default());
+
+        let room = lobby.room("first").unwrap();
+        assert!(matches!(lobby.room("second"), Err(ServerError::TooManyRooms)));
+
+        let (tx, mut rx) = outbox();
+        let ids = [Uuid::new_v4(), Uuid::new_v4()];
+        for id in ids {
+            room.join(id, Player::new(tx.clone())).await.unwrap();
+        }
+        assert!(matches!(room.join(Uuid::new_v4(), Player::new(tx.clone())).await, Err(ServerError::RoomFull)));
+        assert_eq!(2, room.summary().players);
+
+        // The room keeps going until everyone has left
+        room.leave(ids[0]).await.unwrap();
+        assert!(!room.is_closed());
+        assert_eq!(1, lobby.num_rooms());
+
+        room.leave(ids[1]).await.unwrap();
+        while !room.is_closed() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(0, lobby.num_rooms());
+        assert!(matches!(room.act(ids[0], Action::Start).await, Err(ServerError::RoomClosed)));
+        assert!(lobby.room("second").is_ok());
+
+        // Only the admin greeting was ever sent
+        drop(tx);
+        let mut sent = 0;
+        while rx.recv().await.is_some() {
+            sent += 1;
+        }
+        assert_eq!(1, sent);
+    }
+
+    #[tokio::test]
+    async fn rooms_apply_commands_in_order() {
+        let config = Config::default();
+        let mut lobby = Lobby::new(&config, watch::channel(false).1, Arc::default(), Notifier::default(), None, Arc::default());
+        let room = lobby.room("ordered").unwrap();
+
+        let (admin_tx, mut admin_rx) = outbox();
+        let (tx, _rx) = outbox();
+        let admin = Uuid::new_v4();
+        room.join(admin, Player::new(admin_tx)).await.unwrap();
+        room.join(Uuid::new_v4(), Player::new(tx.clone())).await.unwrap();
+
+        // Every start but the first finds the game already running and does nothing
+        for _ in 0..10 {
+            room.act(admin, Action::Start).await.unwrap();
+        }
+        // Joining only finishes once everything queued before it has been handled
+        room.join(Uuid::new_v4(), Player::new(tx)).await.unwrap();
+
+        let summary = room.summary();
+        assert!(summary.in_game);
+        assert_eq!(2, summary.players);
+        assert_eq!(1, summary.spectators);
+
+        let mut deals = 0;
+        while let Some(msg) = admin_rx.try_recv() {
+            let msg: DynMessage = serde_json::from_str(msg.to_str().unwrap()).unwrap();
+            if let Action::Started(_) = msg.action {
+                deals += 1;
+            }
+        }
+        assert_eq!(1, deals);
+    }
+}
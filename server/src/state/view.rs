@@ -0,0 +1,278 @@
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::game::card::{Card, Color, PhysicalCard, Side};
+
+use super::{msg::{Action, DynMessage}, state_man::GameState};
+
+/// Everything the game can tell its players about. Events are never sent as-is, each recipient
+/// gets their own projection of it through [`GameEvent::project`]
+#[derive(Debug, Clone)]
+pub enum GameEvent {
+    /// Text from the server meant for everyone
+    Announcement(String),
+    /// Text from the server meant for a single player
+    Notice { to: Uuid, text: String },
+    Chat { from: Uuid, name: String, text: String },
+    Dealt { player: Uuid, name: Option<String>, hand: [PhysicalCard; 7] },
+    Drew { player: Uuid, name: Option<String>, cards: Vec<PhysicalCard>, reason: DrawReason },
+    PlayAccepted { player: Uuid },
+    PlayDenied { player: Uuid },
+    TopCard { played_by: Option<String>, card: Card },
+    /// The color a wild was played as, which is what the next card has to match
+    ColorChosen { chosen_by: Option<String>, color: Color },
+    Skipped { player: Uuid },
+    TurnStarted { player: Uuid },
+    /// A wild was flipped to start the game and this player gets to pick its color
+    ColorRequested { player: Uuid },
+    /// This player played a Wild Swap Hands and gets to pick who to swap with, out of `targets`
+    TargetRequested { player: Uuid, targets: Vec<String> },
+    Won { player: Uuid, name: Option<String> },
+    /// The game was stopped without a winner, everyone's hand is gone
+    Ended,
+    Left { name: String },
+    /// Someone took back their seat after a restart, and needs to be told everything they missed
+    Rejoined { player: Uuid, name: Option<String>, hand: Vec<PhysicalCard>, top: Option<Card>, color: Color, side: Side },
+    /// Every card on the table was turned over by a Flip card, leaving `top` on the discard pile
+    Flipped { flipped_by: Option<String>, side: Side, top: Card },
+    /// A player's whole hand after it changed all at once, like when the table flipped or they
+    /// discarded a color
+    HandChanged { player: Uuid, hand: Vec<PhysicalCard> },
+    /// Knocked out of a No Mercy game for holding `cards` cards
+    Eliminated { player: Uuid, name: Option<String>, cards: usize },
+    /// What this player needs to take their seat back after a restart
+    SeatToken { player: Uuid, token: String }
+}
+
+/// What anyone outside the game can learn from a [`GameEvent`], as sent down a room's event feed.
+/// Players go by name, and cards only ever by how many
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum FeedEvent {
+    Announcement { text: String },
+    Chat { from: String, text: String },
+    Turn { player: Option<String> },
+    TopCard { played_by: Option<String>, card: Card },
+    Color { chosen_by: Option<String>, color: Color },
+    Hand { player: Option<String>, cards: usize },
+    Skipped { player: Option<String> },
+    Won { player: Option<String> },
+    Ended,
+    Left { player: String },
+    Back { player: Option<String> },
+    Flipped { flipped_by: Option<String>, side: Side, top: Card },
+    Eliminated { player: Option<String>, cards: usize }
+}
+
+impl FeedEvent {
+    /// Used as the SSE event name
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FeedEvent::Announcement { .. } => "announcement",
+            FeedEvent::Chat { .. } => "chat",
+            FeedEvent::Turn { .. } => "turn",
+            FeedEvent::TopCard { .. } => "top_card",
+            FeedEvent::Color { .. } => "color",
+            FeedEvent::Hand { .. } => "hand",
+            FeedEvent::Skipped { .. } => "skipped",
+            FeedEvent::Won { .. } => "won",
+            FeedEvent::Ended => "ended",
+            FeedEvent::Left { .. } => "left",
+            FeedEvent::Back { .. } => "back",
+            FeedEvent::Flipped { .. } => "flipped",
+            FeedEvent::Eliminated { .. } => "eliminated"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawReason {
+    Draw,
+    DrawTwo,
+    DrawFour,
+    /// Any other card that makes someone draw
+    Penalty
+}
+
+/// How a recipient relates to an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Viewer {
+    /// The player the event is about, allowed to see their own cards
+    Owner,
+    /// Another player seated in the game
+    Opponent,
+    /// Someone watching who isn't dealt in, only ever sees public information
+    Spectator
+}
+
+impl GameEvent {
+    /// The player this event is private to, if any
+    pub fn subject(&self) -> Option<Uuid> {
+        match self {
+            GameEvent::Notice { to, .. } => Some(*to),
+            GameEvent::Chat { from, .. } => Some(*from),
+            GameEvent::Dealt { player, .. }
+                | GameEvent::Drew { player, .. }
+                | GameEvent::PlayAccepted { player }
+                | GameEvent::PlayDenied { player }
+                | GameEvent::Skipped { player }
+                | GameEvent::TurnStarted { player }
+                | GameEvent::ColorRequested { player }
+                | GameEvent::TargetRequested { player, .. }
+                | GameEvent::Won { player, .. }
+                | GameEvent::Rejoined { player, .. }
+                | GameEvent::HandChanged { player, .. }
+                | GameEvent::Eliminated { player, .. }
+                | GameEvent::SeatToken { player, .. } => Some(*player),
+            GameEvent::Announcement(_)
+                | GameEvent::Ended
+                | GameEvent::TopCard { .. }
+                | GameEvent::ColorChosen { .. }
+                | GameEvent::Left { .. }
+                | GameEvent::Flipped { .. } => None
+        }
+    }
+
+    /// What the room's event feed shows of this, read off `state` once the event has happened.
+    /// Anything private to one player, and anything only the player themselves cares about, isn't
+    /// shown at all
+    pub fn public(&self, state: &GameState) -> Option<FeedEvent> {
+        let hand = |player: &Uuid| FeedEvent::Hand {
+            player: state.name_of(player),
+            cards: state.players.get(player).map_or(0, |player| player.hand_size())
+        };
+
+        match self {
+            GameEvent::Announcement(text) => Some(FeedEvent::Announcement { text: text.clone() }),
+            GameEvent::Chat { name, text, .. } => Some(FeedEvent::Chat { from: name.clone(), text: text.clone() }),
+            GameEvent::Dealt { player, .. }
+                | GameEvent::Drew { player, .. }
+                | GameEvent::PlayAccepted { player }
+                | GameEvent::HandChanged { player, .. } => Some(hand(player)),
+            GameEvent::TopCard { played_by, card } => Some(FeedEvent::TopCard { played_by: played_by.clone(), card: *card }),
+            GameEvent::ColorChosen { chosen_by, color } => Some(FeedEvent::Color { chosen_by: chosen_by.clone(), color: *color }),
+            GameEvent::Skipped { player } => Some(FeedEvent::Skipped { player: state.name_of(player) }),
+            GameEvent::TurnStarted { player } => Some(FeedEvent::Turn { player: state.name_of(player) }),
+            GameEvent::Won { name, .. } => Some(FeedEvent::Won { player: name.clone() }),
+            GameEvent::Ended => Some(FeedEvent::Ended),
+            GameEvent::Left { name } => Some(FeedEvent::Left { player: name.clone() }),
+            GameEvent::Rejoined { name, .. } => Some(FeedEvent::Back { player: name.clone() }),
+            GameEvent::Flipped { flipped_by, side, top } => Some(FeedEvent::Flipped { flipped_by: flipped_by.clone(), side: *side, top: *top }),
+            GameEvent::Eliminated { name, cards, .. } => Some(FeedEvent::Eliminated { player: name.clone(), cards: *cards }),
+            GameEvent::Notice { .. }
+                | GameEvent::PlayDenied { .. }
+                | GameEvent::ColorRequested { .. }
+                | GameEvent::TargetRequested { .. }
+                | GameEvent::SeatToken { .. } => None
+        }
+    }
+
+    /// What a player's partner sees of this on top of what any opponent does, when partners play
+    /// with their hands open. Anything that changes a hand shows them the whole of it, read off
+    /// `state` once the event has happened
+    pub fn to_partner(&self, state: &GameState) -> Option<DynMessage> {
+        match self {
+            GameEvent::Dealt { player, .. }
+                | GameEvent::Drew { player, .. }
+                | GameEvent::PlayAccepted { player }
+                | GameEvent::HandChanged { player, .. }
+                | GameEvent::Rejoined { player, .. }
+                | GameEvent::Eliminated { player, .. } => {
+                    let hand = state.players.get(player)?.hand().to_vec();
+                    Some(DynMessage::new_msg(state.name_of(player), Action::PartnerHand(hand)))
+                },
+            _ => None
+        }
+    }
+
+    /// What a viewer is allowed to learn from this event. Card identities only ever leave through
+    /// the `Owner` arm
+    pub fn project(&self, viewer: Viewer) -> Vec<DynMessage> {
+        match (self, viewer) {
+            (GameEvent::Announcement(text), _) => vec![DynMessage::broadcast(text)],
+
+            (GameEvent::Notice { text, .. }, Viewer::Owner) => vec![DynMessage::broadcast(text)],
+            (GameEvent::Notice { .. }, _) => vec![],
+
+            // The sender already echoes their own chat locally
+            (GameEvent::Chat { .. }, Viewer::Owner) => vec![],
+            (GameEvent::Chat { name, text, .. }, _) => vec![DynMessage::new_msg(Some(name.clone()), Action::Message(text.clone()))],
+
+            (GameEvent::Dealt { hand, .. }, Viewer::Owner) => vec![DynMessage::new_msg(None, Action::Started(*hand))],
+            (GameEvent::Dealt { name, hand, .. }, _) => vec![DynMessage::new_msg(name.clone(), Action::CardsDrawn(hand.len()))],
+
+            (GameEvent::Drew { cards, reason, .. }, Viewer::Owner) => match (reason, cards.as_slice()) {
+                (DrawReason::DrawTwo, [a, b]) => vec![DynMessage::new_msg(None, Action::DrawTwo([*a, *b]))],
+                (DrawReason::DrawFour, [a, b, c, d]) => vec![DynMessage::new_msg(None, Action::DrawFour([*a, *b, *c, *d]))],
+                // Plain draws, or a penalty cut short by running out of cards
+                _ => cards.iter().map(|card| DynMessage::draw(*card)).collect()
+            },
+            (GameEvent::Drew { name, cards, .. }, _) => vec![DynMessage::new_msg(name.clone(), Action::CardsDrawn(cards.len()))],
+
+            (GameEvent::PlayAccepted { .. }, Viewer::Owner) => vec![DynMessage::new_msg(None, Action::AcceptPlayCard)],
+            (GameEvent::PlayDenied { .. }, Viewer::Owner) => vec![DynMessage::new_msg(None, Action::DenyPlayCard)],
+            (GameEvent::Skipped { .. }, Viewer::Owner) => vec![DynMessage::new_msg(None, Action::Skipped)],
+            (GameEvent::TurnStarted { .. }, Viewer::Owner) => vec![DynMessage::new_msg(None, Action::YourTurn)],
+            (GameEvent::ColorRequested { .. }, Viewer::Owner) => vec![DynMessage::new_msg(None, Action::PickColor)],
+            (GameEvent::TargetRequested { targets, .. }, Viewer::Owner) => vec![DynMessage::new_msg(None, Action::PickTarget(targets.clone()))],
+            (GameEvent::PlayAccepted { .. }
+                | GameEvent::PlayDenied { .. }
+                | GameEvent::Skipped { .. }
+                | GameEvent::TurnStarted { .. }
+                | GameEvent::ColorRequested { .. }
+                | GameEvent::TargetRequested { .. }, _) => vec![],
+
+            (GameEvent::TopCard { played_by, card }, _) => vec![DynMessage::new_msg(played_by.clone(), Action::TopCard(*card))],
+            (GameEvent::ColorChosen { chosen_by, color }, _) => vec![DynMessage::new_msg(chosen_by.clone(), Action::ColorChosen(*color))],
+
+            (GameEvent::Won { name, .. }, _) => {
+                let name = name.as_deref().unwrap_or("Someone");
+                vec![DynMessage::broadcast(&format!("{} has won!!!", name)), DynMessage::new_msg(None, Action::Win)]
+            },
+
+            (GameEvent::Ended, _) => vec![DynMessage::new_msg(None, Action::GameOver)],
+
+            (GameEvent::Left { name }, _) => vec![DynMessage::broadcast(&format!("{} has left the game", name))],
+
+            (GameEvent::Rejoined { hand, top, color, side, .. }, Viewer::Owner) => {
+                let mut msgs = vec![DynMessage::broadcast("Welcome back, you're right where you left off")];
+                if *side == Side::Dark {
+                    msgs.push(DynMessage::new_msg(None, Action::Flipped(*side)));
+                }
+                msgs.push(DynMessage::new_msg(None, Action::Hand(hand.clone())));
+                if let Some(top) = top {
+                    msgs.push(DynMessage::top_card(*top));
+                    if top.color() == Color::None && *color != Color::None {
+                        msgs.push(DynMessage::new_msg(None, Action::ColorChosen(*color)));
+                    }
+                }
+                msgs
+            },
+            (GameEvent::Rejoined { name, .. }, _) => {
+                let name = name.as_deref().unwrap_or("Someone");
+                vec![DynMessage::broadcast(&format!("{} is back", name))]
+            },
+
+            (GameEvent::Flipped { flipped_by, side, top }, _) => vec![
+                DynMessage::broadcast(&format!("{} flipped everything over to the {} side", flipped_by.as_deref().unwrap_or("Someone"), side)),
+                DynMessage::new_msg(flipped_by.clone(), Action::Flipped(*side)),
+                DynMessage::top_card(*top)
+            ],
+
+            (GameEvent::HandChanged { hand, .. }, Viewer::Owner) => vec![DynMessage::new_msg(None, Action::Hand(hand.clone()))],
+            (GameEvent::HandChanged { .. }, _) => vec![],
+
+            (GameEvent::Eliminated { cards, .. }, Viewer::Owner) => vec![
+                DynMessage::broadcast(&format!("You're out with {} cards. No mercy", cards)),
+                DynMessage::new_msg(None, Action::Hand(vec![]))
+            ],
+            (GameEvent::Eliminated { name, cards, .. }, _) => {
+                let name = name.as_deref().unwrap_or("Someone");
+                vec![DynMessage::broadcast(&format!("{} is out with {} cards", name, cards))]
+            },
+
+            (GameEvent::SeatToken { token, .. }, Viewer::Owner) => vec![DynMessage::new_msg(None, Action::SeatToken(token.clone()))],
+            (GameEvent::SeatToken { .. }, _) => vec![]
+        }
+    }
+}
@@ -1,22 +1,26 @@
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 
-use server::{game::card::{Card, Color}, state::msg::{Action, DynMessage}};
+use client_common::{conn::Target, health::{Health, HealthMonitor, PING_EVERY}};
+use server::{game::card::Color, state::msg::{Action, DynMessage}};
 use test_client::hand::Hand;
 use tokio::io::{self, AsyncBufReadExt, BufReader};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use tokio_tungstenite::tungstenite::protocol::Message;
 use futures_util::{lock::Mutex, SinkExt, StreamExt};
 
 #[tokio::main]
 async fn main() {
-    let url = "ws://127.0.0.1:8080";
+    let target = Target::from_args().unwrap_or_else(|e| {
+        eprintln!("{}\nUsage: {} [URL] [--ca FILE] [--token TOKEN]", e, env!("CARGO_PKG_NAME"));
+        std::process::exit(2);
+    });
 
-    let (ws_stream, _) = connect_async(url).await.expect("Failed to connect");
+    let ws_stream = target.connect().await.expect("Failed to connect");
 
     println!("Connected to the server");
 
-    let (mut write, mut read) = ws_stream.split();
+    let (write, mut read) = ws_stream.split();
 
-    let mut write = Arc::new(Mutex::new(write));
+    let write = Arc::new(Mutex::new(write));
 
     let stdin = io::stdin();
     let mut reader = BufReader::new(stdin);
@@ -27,12 +31,41 @@ async fn main() {
     let mut username_sent = false;
 
     let hand: Arc<Mutex<Hand>> = Arc::new(Mutex::new(Hand::default()));
+    let health: Arc<Mutex<HealthMonitor>> = Arc::new(Mutex::new(HealthMonitor::default()));
+
+    // Pings the server, and speaks up whenever the connection goes bad or recovers
+    let watch_health = async {
+        let mut every = tokio::time::interval(PING_EVERY);
+        let mut was_healthy = true;
+
+        loop {
+            every.tick().await;
+
+            let (payload, status) = {
+                let mut health = health.lock().await;
+                (health.ping(), health.status())
+            };
+
+            let healthy = matches!(status, Health::Healthy(_) | Health::Connecting);
+            if healthy != was_healthy {
+                println!("Connection: {}", status);
+                was_healthy = healthy;
+            }
+
+            if write.lock().await.send(Message::Ping(payload)).await.is_err() {
+                break;
+            }
+        }
+    };
 
     let send_messages = async {
         loop {
 
             let action_msg = if !username_sent {
-                let sent_action = Action::SetName(username.clone());
+                let sent_action = match &target.token {
+                    Some(token) => Action::Rejoin { name: username.clone(), token: token.clone() },
+                    None => Action::SetName(username.clone())
+                };
                 username_sent = true;
 
                 serde_json::to_string(&sent_action)
@@ -47,38 +80,57 @@ async fn main() {
                     let card_choice: Result<usize, _> = choice.trim().parse();
 
                     if let Ok(i) = card_choice {
-                        let mut card = {
+                        let card = {
                             let mut hand = hand.lock().await;
                             hand.last_card_choice = Some(i);
-                            hand.cards[i].clone()
+                            hand.cards[i]
                         };
                         println!("Playing {}", card);
-                        while card.color() == Color::None {
+
+                        let mut chosen_color = None;
+                        while card.card.color() == Color::None && chosen_color.is_none() {
                             println!("What color would you like?");
                             let mut color_choice = String::new();
                             reader.read_line(&mut color_choice).await.expect("Read color choice");
 
-                            let color = match color_choice.trim() {
-                                "red" => Color::Red,
-                                "blue" => Color::Blue,
-                                "yellow" => Color::Yellow,
-                                "green" => Color::Green,
-                                _ => Color::None
-                            };
-                            card = match card {
-                                Card::DrawFour(_) => Card::DrawFour(color),
-                                Card::Wild(_) => Card::Wild(color),
-                                _ => unreachable!("Non wild or draw four card MUST have a color")
+                            chosen_color = match color_choice.trim() {
+                                "red" => Some(Color::Red),
+                                "blue" => Some(Color::Blue),
+                                "yellow" => Some(Color::Yellow),
+                                "green" => Some(Color::Green),
+                                "pink" => Some(Color::Pink),
+                                "teal" => Some(Color::Teal),
+                                "orange" => Some(Color::Orange),
+                                "purple" => Some(Color::Purple),
+                                _ => None
                             };
                         }
-                        Action::PlayCard(card)
+                        Action::PlayCard { card, chosen_color }
                     } else {
                         continue;
                     }
 
+                } else if input.trim() == "health" {
+                    println!("Connection: {}", health.lock().await.status());
+                    continue;
                 } else if input.trim().starts_with("draw") {
                     Action::DrawCard
-                }else {
+                } else if let Some(color) = input.trim().strip_prefix("color") {
+                    let color = match color.trim() {
+                        "red" => Color::Red,
+                        "blue" => Color::Blue,
+                        "yellow" => Color::Yellow,
+                        "green" => Color::Green,
+                        "pink" => Color::Pink,
+                        "teal" => Color::Teal,
+                        "orange" => Color::Orange,
+                        "purple" => Color::Purple,
+                        _ => continue
+                    };
+                    Action::ChooseColor(color)
+                } else if let Some(name) = input.trim().strip_prefix("target") {
+                    Action::ChooseTarget(name.trim().to_string())
+                } else {
                     Action::Message(input.trim().to_string())
                 };
 
@@ -95,6 +147,7 @@ async fn main() {
         while let Some(msg) = read.next().await {
             match msg {
                 Ok(Message::Text(text)) => {
+                    health.lock().await.heard();
                     let deserialized: Result<DynMessage, _> = serde_json::from_str(&text);
 
                     if let Ok(message) = deserialized {
@@ -106,22 +159,38 @@ async fn main() {
                             Action::Message(msg) => println!("{}{}", begin_msg, msg),
                             
                             Action::TopCard(card) => println!("Top Card is a {}", card),
-                            Action::PlayCard(card) => println!("{} played {}", begin_msg, card),
+                            Action::PlayCard { card, .. } => println!("{} played {}", begin_msg, card),
+                            Action::ColorChosen(color) => println!("{}The color is now {}", begin_msg, color),
+                            Action::CardsDrawn(count) => println!("{}drew {} card(s)", begin_msg, count),
                             Action::DrawnCard(card) => {
                                 println!("You draw a {}", card);
                                 hand.lock().await.cards.push(card);
                                 println!("Your hand: {:?}", hand.lock().await.cards);
                             },
                             Action::Started(starting_cards) => {
-                                hand.lock().await.cards.extend(starting_cards.iter());
-                                println!("Your hand: {:?}", hand.lock().await.cards);
+                                let mut hand = hand.lock().await;
+                                hand.cards = starting_cards.to_vec();
+                                hand.last_card_choice = None;
+                                println!("Your hand: {:?}", hand.cards);
+                            },
+                            Action::GameOver => {
+                                let mut hand = hand.lock().await;
+                                hand.cards.clear();
+                                hand.last_card_choice = None;
+                                println!("The game is over, start another with `START`");
+                            },
+                            Action::Hand(cards) => {
+                                let mut hand = hand.lock().await;
+                                hand.cards = cards;
+                                hand.last_card_choice = None;
+                                println!("Your hand: {:?}", hand.cards);
                             },
                             Action::AcceptPlayCard => {
                                 let mut hand = hand.lock().await;
                                 if let Some(choice) = hand.last_card_choice {
                                     hand.cards.remove(choice);
                                     hand.last_card_choice = None;
-                                    if hand.cards.len() == 0 {
+                                    if hand.cards.is_empty() {
                                         let win = serde_json::to_string(&Action::Win).unwrap();
                                         write.lock().await.send(Message::Text(win)).await.expect("Send win fail");
                                     }
@@ -137,9 +206,21 @@ async fn main() {
                                 println!("It's your turn!");
                                 println!("Your hand: {:?}", hand.lock().await.cards);
                             },
+                            Action::PickColor => {
+                                println!("The game opened on a wild, pick its color with `color <red|blue|yellow|green>`");
+                            },
                             Action::Skipped => {
                                 println!("You've been skipped buddy");
                             },
+                            Action::PartnerHand(cards) => {
+                                println!("{}Your partner's hand: {:?}", begin_msg, cards);
+                            },
+                            Action::SeatToken(token) => {
+                                println!("Your seat token is {}, start with --token {} to get your seat back if the server restarts", token, token);
+                            },
+                            Action::PickTarget(names) => {
+                                println!("Who do you want to swap hands with? Pick with `target <name>`: {}", names.join(", "));
+                            },
                             Action::DrawFour(cards) => {
                                 println!("You got hit with a draw 4 :(");
                                 for card in cards {
@@ -160,8 +241,13 @@ async fn main() {
                         };
                     } 
                 }
-                Ok(Message::Close(_)) => {
-                    println!("Connection closed");
+                Ok(Message::Ping(_)) => health.lock().await.heard(),
+                Ok(Message::Pong(payload)) => health.lock().await.pong(&payload),
+                Ok(Message::Close(frame)) => {
+                    match frame {
+                        Some(frame) if !frame.reason.is_empty() => println!("Connection closed: {}", frame.reason),
+                        _ => println!("Connection closed")
+                    }
                     break;
                 }
                 Err(e) => {
@@ -176,5 +262,6 @@ async fn main() {
     tokio::select! {
         _ = send_messages => {},
         _ = receive_messages => {},
+        _ = watch_health => {},
     }
 }